@@ -1,13 +1,47 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
 use bevy::{
+    asset::FileAssetIo,
     gltf::Gltf,
     prelude::{shape::Cube, *},
     utils::HashMap,
 };
+use serde::{Deserialize, Serialize};
 use strum::{Display, EnumIter, IntoEnumIterator};
 
-use crate::GemType;
+use crate::{
+    settings::{Palette, Settings},
+    GemType,
+};
+
+/// Letter drawn on each gem type when gem symbols are turned on
+pub fn gem_symbol(typ: GemType) -> &'static str {
+    match typ {
+        GemType::Ruby => "R",
+        GemType::Emerald => "E",
+        GemType::Sapphire => "S",
+        GemType::Topaz => "T",
+        GemType::Diamond => "D",
+        GemType::Amethyst => "A",
+        GemType::Skull => "X",
+        GemType::Equipment => "+",
+    }
+}
 
-#[derive(Display, EnumIter, Eq, Hash, PartialEq, Clone, Copy)]
+const GEMS_CONFIG: &str = "gems.ron";
+/// Folder the asset server loads from, under its root
+const ASSET_FOLDER: &str = "assets";
+
+/// Where `path` inside the assets folder is on disk, found the same way the asset server finds it
+/// so files read directly agree with the ones it loads, whatever the working directory
+pub fn asset_path(path: impl AsRef<Path>) -> PathBuf {
+    FileAssetIo::get_root_path().join(ASSET_FOLDER).join(path)
+}
+
+#[derive(Display, EnumIter, Eq, Hash, PartialEq, Clone, Copy, Deserialize)]
 pub enum GemShape {
     Asscher,
     Baguette,
@@ -19,14 +53,41 @@ pub enum GemShape {
     Equipment,
 }
 
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum MeshQuality {
+    Low,
+    #[default]
+    High,
+}
+
 impl GemShape {
-    pub fn mesh_path(self) -> String {
-        format!("{self}.glb")
+    pub fn mesh_path(self, quality: MeshQuality) -> String {
+        match quality {
+            MeshQuality::Low => format!("{self}_low.glb"),
+            MeshQuality::High => format!("{self}.glb"),
+        }
     }
 
     pub fn shattered_mesh_path(self) -> String {
         format!("{self}_shattered.glb")
     }
+
+    /// Rotation applied to the mesh so every shape presents its face to the camera
+    pub fn orientation(self) -> Quat {
+        // Every shape is exported from gems.blend already facing the camera, so none needs an
+        // offset yet. A mesh that's re-exported tilted gets its correction in its own arm
+        let (x, y, z) = match self {
+            GemShape::Asscher => (0.0, 0.0, 0.0),
+            GemShape::Baguette => (0.0, 0.0, 0.0),
+            GemShape::Marquise => (0.0, 0.0, 0.0),
+            GemShape::Pear => (0.0, 0.0, 0.0),
+            GemShape::Round => (0.0, 0.0, 0.0),
+            GemShape::Trillion => (0.0, 0.0, 0.0),
+            GemShape::Skull => (0.0, 0.0, 0.0),
+            GemShape::Equipment => (0.0, 0.0, 0.0),
+        };
+        Quat::from_euler(EulerRot::XYZ, x, y, z)
+    }
 }
 
 impl From<GemType> for GemShape {
@@ -44,40 +105,129 @@ impl From<GemType> for GemShape {
     }
 }
 
+/// How a gem type is presented, as described by `assets/gems.ron`
+#[derive(Clone, Deserialize)]
+pub struct GemLook {
+    pub typ: GemType,
+    pub name: String,
+    /// Color under the standard palette, the colorblind palettes keep their own
+    pub color: Color,
+    pub shape: GemShape,
+}
+
+impl From<GemType> for GemLook {
+    fn from(typ: GemType) -> Self {
+        Self {
+            typ,
+            name: typ.to_string(),
+            color: typ.into(),
+            shape: typ.into(),
+        }
+    }
+}
+
+/// Reads `assets/gems.ron`, falling back to the built-in look for every type it leaves out and for
+/// all of them if it can't be read
+fn load_gem_looks() -> HashMap<GemType, GemLook> {
+    let mut looks = GemType::iter()
+        .map(|typ| (typ, GemLook::from(typ)))
+        .collect::<HashMap<_, _>>();
+    let config = asset_path(GEMS_CONFIG);
+    if !config.exists() {
+        info!("No {GEMS_CONFIG} found, using the built-in gem looks");
+        return looks;
+    }
+    let configured = fs::read_to_string(config)
+        .map_err(|err| err.to_string())
+        .and_then(|contents| {
+            ron::from_str::<Vec<GemLook>>(&contents).map_err(|err| err.to_string())
+        });
+    match configured {
+        Ok(configured) => {
+            for look in configured {
+                looks.insert(look.typ, look);
+            }
+        }
+        Err(err) => warn!("Ignoring {GEMS_CONFIG}, using the built-in gem looks: {err}"),
+    }
+    looks
+}
+
 #[derive(Default)]
 pub struct GemAssets {
+    /// Name, color and shape of every gem type
+    pub looks: HashMap<GemType, GemLook>,
+    /// The meshes for the currently selected `MeshQuality`
     pub meshes: HashMap<GemShape, Handle<Gltf>>,
+    pub high_meshes: HashMap<GemShape, Handle<Gltf>>,
+    pub low_meshes: HashMap<GemShape, Handle<Gltf>>,
+    /// Whether every shape has a low quality mesh of its own, the mesh quality setting is only
+    /// offered once they do
+    pub low_meshes_shipped: bool,
     pub shatter_meshes: HashMap<GemShape, Handle<Gltf>>,
     pub materials: Vec<Handle<StandardMaterial>>,
     pub transparent: Handle<StandardMaterial>,
+    /// Tint for slots the selected gem can be swapped into
+    pub highlight: Handle<StandardMaterial>,
+    /// Outline for the slot under the keyboard cursor
+    pub focus: Handle<StandardMaterial>,
+    /// Overlay for slots whose gem is locked in place
+    pub locked: Handle<StandardMaterial>,
     pub cube: Handle<Mesh>,
 }
 
+impl GemAssets {
+    pub fn name(&self, typ: GemType) -> &str {
+        &self.looks[&typ].name
+    }
+
+    pub fn shape(&self, typ: GemType) -> GemShape {
+        self.looks[&typ].shape
+    }
+
+    /// The color of `typ` under `palette`, the standard palette is the one the gem looks configure
+    pub fn color(&self, typ: GemType, palette: Palette) -> Color {
+        match palette {
+            Palette::Standard => self.looks[&typ].color,
+            Palette::Deuteranopia | Palette::Protanopia | Palette::Tritanopia => palette.color(typ),
+        }
+    }
+}
+
 pub fn load_assets(
     mut commands: Commands,
+    settings: Res<Settings>,
     ass: Res<AssetServer>,
     mut mats: ResMut<Assets<StandardMaterial>>,
     mut meshes: ResMut<Assets<Mesh>>,
 ) {
-    let mut assets = GemAssets::default();
+    let mut assets = GemAssets {
+        looks: load_gem_looks(),
+        low_meshes_shipped: true,
+        ..default()
+    };
     for shape in GemShape::iter() {
-        assets.meshes.insert(shape, ass.load(&shape.mesh_path()));
+        let high = ass.load(&shape.mesh_path(MeshQuality::High));
+        let low_path = shape.mesh_path(MeshQuality::Low);
+        let low = if asset_path(&low_path).exists() {
+            ass.load(&low_path)
+        } else {
+            info!("No {low_path} found, using the high quality mesh instead");
+            assets.low_meshes_shipped = false;
+            high.clone()
+        };
+        assets.meshes.insert(shape, high.clone());
+        assets.high_meshes.insert(shape, high);
+        assets.low_meshes.insert(shape, low);
         assets
             .shatter_meshes
             .insert(shape, ass.load(&shape.shattered_mesh_path()));
     }
 
-    for color in [
-        Color::RED,
-        Color::GREEN,
-        Color::BLUE,
-        Color::YELLOW,
-        Color::WHITE,
-        Color::PURPLE,
-        Color::ANTIQUE_WHITE,
-        Color::GRAY,
-    ] {
-        assets.materials.push(mats.add(color.into()));
+    for typ in GemType::iter() {
+        assets
+            .materials
+            .push(mats.add(assets.color(typ, settings.palette).into()));
     }
 
     assets.transparent = mats.add(StandardMaterial {
@@ -86,7 +236,65 @@ pub fn load_assets(
         ..default()
     });
 
+    assets.highlight = mats.add(StandardMaterial {
+        base_color: Color::rgba(1.0, 1.0, 1.0, 0.15),
+        alpha_mode: AlphaMode::Blend,
+        unlit: true,
+        ..default()
+    });
+
+    assets.focus = mats.add(StandardMaterial {
+        base_color: Color::rgba(1.0, 0.85, 0.2, 0.35),
+        alpha_mode: AlphaMode::Blend,
+        unlit: true,
+        ..default()
+    });
+
+    assets.locked = mats.add(StandardMaterial {
+        base_color: Color::rgba(0.55, 0.6, 0.7, 0.5),
+        alpha_mode: AlphaMode::Blend,
+        unlit: true,
+        ..default()
+    });
+
     assets.cube = meshes.add(Cube { size: 0.19 }.into());
 
     commands.insert_resource(assets);
 }
+
+/// Recolors the shared per-type materials in place, gems with their own material pick the new
+/// colors up through `apply_material` once their type is flagged as changed
+pub fn apply_palette(
+    settings: Res<Settings>,
+    mut palette: Local<Option<Palette>>,
+    assets: Res<GemAssets>,
+    mut mats: ResMut<Assets<StandardMaterial>>,
+    mut gems: Query<&mut GemType>,
+) {
+    if *palette == Some(settings.palette) {
+        return;
+    }
+    let first = palette.is_none();
+    *palette = Some(settings.palette);
+    if first {
+        return;
+    }
+    for typ in GemType::iter() {
+        if let Some(mat) = mats.get_mut(&assets.materials[typ as usize]) {
+            mat.base_color = assets.color(typ, settings.palette);
+        }
+    }
+    for mut typ in gems.iter_mut() {
+        typ.set_changed();
+    }
+}
+
+pub fn apply_mesh_quality(settings: Res<Settings>, mut assets: ResMut<GemAssets>) {
+    if !settings.is_changed() {
+        return;
+    }
+    assets.meshes = match settings.mesh_quality {
+        MeshQuality::Low => assets.low_meshes.clone(),
+        MeshQuality::High => assets.high_meshes.clone(),
+    };
+}
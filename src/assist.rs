@@ -0,0 +1,52 @@
+use bevy::prelude::*;
+use bevy_match3::prelude::*;
+
+use crate::{
+    best_swap, moves::ValidMoves, settings::Settings, BoardTweens, GemSlot, InputBlockers, Player,
+    SelectedSlot, Swapper, Turn, TurnState,
+};
+
+// Event sent when the player asks the assist to make their move for them
+pub struct AutoMatch;
+
+// Makes the best swap the opponent AI would pick on the player's behalf, as long as the assist is
+// switched on. The swap goes through the board like any other, extra turns and all
+pub fn auto_match(
+    settings: Res<Settings>,
+    mut requests: EventReader<AutoMatch>,
+    mut selected: ResMut<SelectedSlot>,
+    mut board_commands: ResMut<BoardCommands>,
+    mut turn_state: ResMut<State<TurnState>>,
+    mut valid_moves: ResMut<ValidMoves>,
+    tweens: Res<BoardTweens>,
+    blockers: InputBlockers,
+    turn: Res<Turn>,
+    player: Query<(), With<Player>>,
+    slots: Query<&GemSlot>,
+    swapper: Swapper,
+) {
+    if requests.iter().count() == 0
+        || !settings.auto_match_assist
+        || turn_state.current() != &TurnState::AwaitingMove
+        || blockers.blocked()
+        || player.get(**turn).is_err()
+        || !tweens.settled()
+    {
+        return;
+    }
+    let ((from, to), _) = match best_swap(&swapper.board, &mut valid_moves) {
+        Some(best) => best,
+        None => return,
+    };
+    let (from, to) = match (
+        slots.get(swapper.index[&from]),
+        slots.get(swapper.index[&to]),
+    ) {
+        (Ok(from), Ok(to)) => (from, to),
+        _ => return,
+    };
+    if swapper.swap(from, to, &mut board_commands) {
+        turn_state.set(TurnState::Resolving).unwrap();
+        **selected = None;
+    }
+}
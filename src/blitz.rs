@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{self, Color32, FontId, RichText},
+    EguiContext,
+};
+
+use crate::{save::SavedGame, GameMode, GameState, Paused};
+
+const BLITZ_SECONDS: f32 = 90.0;
+/// The clock starts flashing once this little time is left
+const HURRY_SECONDS: f32 = 10.0;
+const FLASHES_PER_SECOND: f32 = 2.0;
+
+// Resource containing the time left in a blitz match
+#[derive(Deref, DerefMut)]
+pub struct BlitzClock(Timer);
+
+impl Default for BlitzClock {
+    fn default() -> Self {
+        Self::with_seconds_left(BLITZ_SECONDS)
+    }
+}
+
+impl BlitzClock {
+    pub fn with_seconds_left(seconds: f32) -> Self {
+        let mut timer = Timer::from_seconds(BLITZ_SECONDS, false);
+        timer.set_elapsed(Duration::from_secs_f32(
+            (BLITZ_SECONDS - seconds).clamp(0.0, BLITZ_SECONDS),
+        ));
+        Self(timer)
+    }
+
+    pub fn seconds_left(&self) -> f32 {
+        self.duration().as_secs_f32() - self.elapsed_secs()
+    }
+}
+
+// Continued matches pick up the time they had left from their save instead
+pub fn reset_blitz_clock(mut clock: ResMut<BlitzClock>, saved: Option<Res<SavedGame>>) {
+    *clock = saved
+        .and_then(|saved| saved.blitz_seconds_left())
+        .map_or_else(BlitzClock::default, BlitzClock::with_seconds_left);
+}
+
+pub fn tick_blitz_clock(
+    time: Res<Time>,
+    mode: Res<GameMode>,
+    paused: Res<Paused>,
+    mut clock: ResMut<BlitzClock>,
+    mut state: ResMut<State<GameState>>,
+) {
+    if *mode != GameMode::Blitz || **paused {
+        return;
+    }
+    if clock.tick(time.delta()).just_finished() {
+        state.set(GameState::GameOver).unwrap();
+    }
+}
+
+// Large countdown at the top of the screen, flashing red as time runs out
+pub fn blitz_clock_ui(
+    time: Res<Time>,
+    mode: Res<GameMode>,
+    clock: Res<BlitzClock>,
+    mut egui_ctx: ResMut<EguiContext>,
+) {
+    if *mode != GameMode::Blitz {
+        return;
+    }
+    let left = clock.seconds_left().max(0.0);
+    let shown = left.ceil() as u32;
+    let text =
+        RichText::new(format!("{}:{:02}", shown / 60, shown % 60)).font(FontId::monospace(60.0));
+    let flash = (time.seconds_since_startup() as f32 * FLASHES_PER_SECOND).fract() < 0.5;
+    let text = if left < HURRY_SECONDS && flash {
+        text.color(Color32::RED)
+    } else {
+        text.color(Color32::WHITE)
+    };
+    egui::Area::new("Blitz clock")
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 10.0))
+        .show(egui_ctx.ctx_mut(), |ui| {
+            ui.label(text);
+        });
+}
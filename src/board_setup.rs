@@ -0,0 +1,147 @@
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{self, FontId, RichText},
+    EguiContext,
+};
+use bevy_match3::Match3Config;
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+
+use crate::{
+    gravity::GravityDirection, seed::BoardSeed, settings::Settings, share::share_code_field,
+    GameState, GemType, MainCamera,
+};
+
+/// Fewest slots a side of the board can have, anything less can't fit a line of three
+const MIN_BOARD_SIDE: u32 = 3;
+/// Most slots a side of the board can have before gems get too small to pick out
+const MAX_BOARD_SIDE: u32 = 12;
+/// Fewest gem types a board can have and still not be one giant match
+const MIN_GEM_TYPES: u32 = 3;
+/// Board side the camera is framed for, bigger boards zoom it out
+const FRAMED_BOARD_SIDE: u32 = 8;
+
+/// The board the next match is played on
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct BoardSize {
+    pub width: u32,
+    pub height: u32,
+    /// How many of the gem types, in order, show up on the board
+    pub gem_types: u32,
+    /// The side of the screen gems fall towards
+    #[serde(default)]
+    pub gravity: GravityDirection,
+}
+
+impl Default for BoardSize {
+    fn default() -> Self {
+        Self {
+            width: 8,
+            height: 8,
+            gem_types: max_gem_types(),
+            gravity: GravityDirection::default(),
+        }
+    }
+}
+
+fn max_gem_types() -> u32 {
+    GemType::iter().count() as u32
+}
+
+impl BoardSize {
+    /// The same size pulled back within the limits, for sizes read from a hand edited file
+    pub fn validated(self) -> Self {
+        Self {
+            width: self.width.clamp(MIN_BOARD_SIDE, MAX_BOARD_SIDE),
+            height: self.height.clamp(MIN_BOARD_SIDE, MAX_BOARD_SIDE),
+            gem_types: self.gem_types.clamp(MIN_GEM_TYPES, max_gem_types()),
+            gravity: self.gravity,
+        }
+    }
+
+    pub fn config(self) -> Match3Config {
+        let size = self.validated();
+        Match3Config {
+            gem_types: size.gem_types,
+            board_dimensions: UVec2::new(size.width, size.height),
+        }
+    }
+}
+
+// Picked after the mode on the main menu, sets up the board the match is played on
+pub fn board_setup(
+    mut config: ResMut<Match3Config>,
+    mut gravity: ResMut<GravityDirection>,
+    mut egui_ctx: ResMut<EguiContext>,
+    mut settings: ResMut<Settings>,
+    mut state: ResMut<State<GameState>>,
+    mut seed: ResMut<BoardSeed>,
+    mut code_text: Local<String>,
+) {
+    egui::CentralPanel::default().show(egui_ctx.ctx_mut(), |ui| {
+        ui.with_layout(
+            egui::Layout::default().with_cross_align(egui::Align::Center),
+            |ui| {
+                ui.heading(RichText::new("Board").font(FontId::monospace(50.0)));
+                // Only written back when changed so the settings aren't saved every frame
+                let mut size = settings.board;
+                for (label, value, range) in [
+                    ("Width", &mut size.width, MIN_BOARD_SIDE..=MAX_BOARD_SIDE),
+                    ("Height", &mut size.height, MIN_BOARD_SIDE..=MAX_BOARD_SIDE),
+                    (
+                        "Gem types",
+                        &mut size.gem_types,
+                        MIN_GEM_TYPES..=max_gem_types(),
+                    ),
+                ] {
+                    ui.horizontal(|ui| {
+                        ui.label(label);
+                        ui.add(egui::DragValue::new(value).clamp_range(range));
+                    });
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Gravity");
+                    for direction in GravityDirection::iter() {
+                        ui.radio_value(&mut size.gravity, direction, direction.to_string());
+                    }
+                });
+                // A shared code brings the board it was played on along with its seed
+                if let Some(code) = share_code_field(ui, &mut code_text) {
+                    size = code.board;
+                    **seed = Some(code.seed);
+                }
+                if size != settings.board {
+                    settings.board = size;
+                }
+                if ui
+                    .button(RichText::new("Play").font(FontId::monospace(50.0)))
+                    .clicked()
+                {
+                    *config = settings.board.config();
+                    *gravity = settings.board.gravity;
+                    state.set(GameState::Game).unwrap();
+                }
+                if ui
+                    .button(RichText::new("Back").font(FontId::monospace(30.0)))
+                    .clicked()
+                {
+                    state.set(GameState::MainMenu).unwrap();
+                }
+            },
+        );
+    });
+}
+
+// Zooms the camera out for boards bigger than it's framed for, so every slot stays in view
+pub fn fit_camera_to_board(
+    config: Res<Match3Config>,
+    mut camera: Query<&mut OrthographicProjection, With<MainCamera>>,
+) {
+    if !config.is_changed() {
+        return;
+    }
+    let side = config.board_dimensions.max_element();
+    for mut projection in camera.iter_mut() {
+        projection.scale = (side as f32 / FRAMED_BOARD_SIDE as f32).max(1.0);
+    }
+}
@@ -0,0 +1,124 @@
+use bevy::{
+    diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin},
+    prelude::*,
+};
+use bevy_egui::{
+    egui::{self, Color32, FontId, Stroke},
+    EguiContext,
+};
+
+use crate::{BoardTweens, GameState, GemSlot, GemsPopped, MainCamera, Player, Turn, TurnState};
+
+const TOGGLE_KEY: KeyCode = KeyCode::F3;
+const COORDINATE_SIZE: f32 = 12.0;
+const MATCH_MARKER_RADIUS: f32 = 14.0;
+
+/// Development overlay toggled with F3, drawing every slot's board position over it, marking the
+/// gems popped last and showing FPS alongside the game, turn and animation state.
+/// Only built with `--features debug_overlay`
+pub struct DebugOverlayPlugin;
+
+impl Plugin for DebugOverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(FrameTimeDiagnosticsPlugin)
+            .init_resource::<DebugOverlay>()
+            .add_system(toggle_debug_overlay)
+            .add_system(remember_popped)
+            .add_system(debug_overlay.after(toggle_debug_overlay));
+    }
+}
+
+#[derive(Default)]
+struct DebugOverlay {
+    shown: bool,
+    /// Where the gems of the last popped batch were
+    last_popped: Vec<Vec3>,
+}
+
+fn toggle_debug_overlay(keys: Res<Input<KeyCode>>, mut overlay: ResMut<DebugOverlay>) {
+    if keys.just_pressed(TOGGLE_KEY) {
+        overlay.shown = !overlay.shown;
+    }
+}
+
+fn remember_popped(mut popped: EventReader<GemsPopped>, mut overlay: ResMut<DebugOverlay>) {
+    for popped in popped.iter() {
+        debug!(
+            "Popped {} gems at cascade depth {}",
+            popped.gems.len(),
+            popped.cascade_depth
+        );
+        overlay.last_popped = popped.gems.iter().map(|(pos, _)| *pos).collect();
+    }
+}
+
+fn debug_overlay(
+    overlay: Res<DebugOverlay>,
+    mut egui_ctx: ResMut<EguiContext>,
+    diagnostics: Res<Diagnostics>,
+    windows: Res<Windows>,
+    images: Res<Assets<Image>>,
+    camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    slots: Query<(&GlobalTransform, &GemSlot)>,
+    game_state: Res<State<GameState>>,
+    turn_state: Res<State<TurnState>>,
+    turn: Option<Res<Turn>>,
+    player: Query<(), With<Player>>,
+    tweens: Res<BoardTweens>,
+) {
+    if !overlay.shown {
+        return;
+    }
+    let fps = diagnostics
+        .get(FrameTimeDiagnosticsPlugin::FPS)
+        .and_then(|fps| fps.average())
+        .unwrap_or_default();
+    let turn = turn.map_or("none", |turn| {
+        if player.get(**turn).is_ok() {
+            "player"
+        } else {
+            "opponent"
+        }
+    });
+    egui::Area::new("Debug overlay")
+        .anchor(egui::Align2::LEFT_BOTTOM, egui::vec2(10.0, -10.0))
+        .show(egui_ctx.ctx_mut(), |ui| {
+            for line in [
+                format!("FPS: {fps:.0}"),
+                format!("Game state: {:?}", game_state.current()),
+                format!("Turn: {turn} ({:?})", turn_state.current()),
+                format!("Tweens in flight: {}", tweens.in_flight()),
+            ] {
+                ui.colored_label(Color32::LIGHT_GREEN, line);
+            }
+        });
+
+    let (camera, camera_transform) = match camera.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let window_height = windows.primary().height();
+    let painter = egui_ctx.ctx_mut().layer_painter(egui::LayerId::new(
+        egui::Order::Foreground,
+        egui::Id::new("debug overlay"),
+    ));
+    let to_screen = |translation| {
+        camera
+            .world_to_screen(&windows, &images, camera_transform, translation)
+            .map(|screen| egui::pos2(screen.x, window_height - screen.y))
+    };
+    for (transform, slot) in slots.iter() {
+        if let Some(pos) = to_screen(transform.translation) {
+            painter.text(
+                pos,
+                egui::Align2::CENTER_CENTER,
+                format!("{},{}", slot.pos.x, slot.pos.y),
+                FontId::monospace(COORDINATE_SIZE),
+                Color32::LIGHT_GREEN,
+            );
+        }
+    }
+    for pos in overlay.last_popped.iter().filter_map(|pos| to_screen(*pos)) {
+        painter.circle_stroke(pos, MATCH_MARKER_RADIUS, Stroke::new(2.0, Color32::YELLOW));
+    }
+}
@@ -0,0 +1,132 @@
+use bevy::{prelude::*, utils::HashMap};
+use strum::IntoEnumIterator;
+
+use crate::{GemType, Rarity, Resources};
+
+/// Damage each popped skull deals to the side whose turn it isn't
+pub const SKULL_DAMAGE: u32 = 5;
+
+/// Everything a gem effect can touch when a gem pops
+pub struct PopContext<'a> {
+    pub typ: GemType,
+    pub rarity: Rarity,
+    /// Resources of the side whose turn it is
+    pub resources: &'a mut Resources,
+    /// Damage to deal to the side whose turn it isn't
+    pub damage: u32,
+}
+
+pub type GemEffect = Box<dyn Fn(&mut PopContext) + Send + Sync>;
+
+/// What happens when a gem of each type pops.
+///
+/// By default every gem grants mana to the side that popped it and skulls deal damage, use
+/// [`GemEffectsExt::register_gem_effect`] to replace that for a gem type.
+pub struct GemEffects(HashMap<GemType, GemEffect>);
+
+impl GemEffects {
+    /// Replaces the effect of `typ`
+    pub fn register(
+        &mut self,
+        typ: GemType,
+        effect: impl Fn(&mut PopContext) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.0.insert(typ, Box::new(effect));
+        self
+    }
+
+    pub fn apply(&self, ctx: &mut PopContext) {
+        if let Some(effect) = self.0.get(&ctx.typ) {
+            effect(ctx);
+        }
+    }
+}
+
+impl Default for GemEffects {
+    fn default() -> Self {
+        let mut effects = Self(HashMap::default());
+        for typ in GemType::iter() {
+            effects.register(typ, |ctx| {
+                ctx.resources.add(ctx.typ, ctx.rarity.value());
+            });
+        }
+        effects.register(GemType::Skull, |ctx| {
+            ctx.damage += SKULL_DAMAGE * ctx.rarity.value();
+        });
+        effects
+    }
+}
+
+/// Registers gem effects before the app runs:
+///
+/// ```ignore
+/// App::new()
+///     .register_gem_effect(GemType::Ruby, |ctx| {
+///         ctx.resources.add(GemType::Ruby, 5);
+///     })
+/// ```
+pub trait GemEffectsExt {
+    fn register_gem_effect(
+        &mut self,
+        typ: GemType,
+        effect: impl Fn(&mut PopContext) + Send + Sync + 'static,
+    ) -> &mut Self;
+}
+
+impl GemEffectsExt for App {
+    fn register_gem_effect(
+        &mut self,
+        typ: GemType,
+        effect: impl Fn(&mut PopContext) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.init_resource::<GemEffects>();
+        self.world
+            .get_resource_mut::<GemEffects>()
+            .unwrap()
+            .register(typ, effect);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pop(effects: &GemEffects, resources: &mut Resources, typ: GemType, rarity: Rarity) -> u32 {
+        let mut ctx = PopContext {
+            typ,
+            rarity,
+            resources,
+            damage: 0,
+        };
+        effects.apply(&mut ctx);
+        ctx.damage
+    }
+
+    #[test]
+    fn equipment_pays_out_gold_instead_of_mana() {
+        let effects = GemEffects::default();
+        let mut resources = Resources::default();
+        pop(&effects, &mut resources, GemType::Equipment, Rarity::Common);
+        pop(&effects, &mut resources, GemType::Equipment, Rarity::Rare);
+        assert_eq!(resources.gold, 3);
+        assert!(resources.mana.is_empty());
+    }
+
+    #[test]
+    fn mana_gems_pay_out_mana_and_skulls_damage() {
+        let effects = GemEffects::default();
+        let mut resources = Resources::default();
+        assert_eq!(
+            pop(&effects, &mut resources, GemType::Ruby, Rarity::Rare),
+            0
+        );
+        assert_eq!(
+            pop(&effects, &mut resources, GemType::Skull, Rarity::Common),
+            SKULL_DAMAGE
+        );
+        assert_eq!(resources.amount(GemType::Ruby), 2);
+        assert_eq!(resources.amount(GemType::Skull), 0);
+        assert_eq!(resources.gold, 0);
+    }
+}
@@ -0,0 +1,71 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+
+use crate::{Health, Player};
+
+/// Most opponents a classic or blitz battle puts up at once
+pub const MAX_LINEUP: usize = 3;
+
+// Resource containing the opponents of the battle in the order they take their turns
+#[derive(Default, Deref, DerefMut)]
+pub struct Lineup(pub Vec<Entity>);
+
+// Resource containing the opponent the player's skulls and attacks hit, kept on one still standing
+// by `retarget_enemy`
+#[derive(Default, Deref, DerefMut)]
+pub struct TargetedEnemy(pub Option<Entity>);
+
+// Who hurts whom, the player hits the opponent they're targeting and every opponent hits the player
+#[derive(SystemParam)]
+pub struct Foes<'w, 's> {
+    lineup: Res<'w, Lineup>,
+    targeted: Res<'w, TargetedEnemy>,
+    player: Query<'w, 's, Entity, With<Player>>,
+}
+
+impl Foes<'_, '_> {
+    /// The side `attacker` deals its damage to
+    pub fn of(&self, attacker: Entity) -> Option<Entity> {
+        let player = self.player.get_single().ok()?;
+        if attacker == player {
+            **self.targeted
+        } else {
+            Some(player)
+        }
+    }
+
+    /// Whose turn follows `current`'s, going from the player through every opponent in lineup
+    /// order and skipping whoever isn't `standing`
+    pub fn next_turn(&self, current: Entity, standing: impl Fn(Entity) -> bool) -> Option<Entity> {
+        let player = self.player.get_single().ok()?;
+        let order = std::iter::once(player)
+            .chain(self.lineup.iter().copied())
+            .collect::<Vec<_>>();
+        let start = order
+            .iter()
+            .position(|side| *side == current)
+            .unwrap_or_default();
+        order
+            .iter()
+            .cycle()
+            .skip(start + 1)
+            .take(order.len())
+            .copied()
+            .find(|side| standing(*side))
+    }
+}
+
+// Moves the target on to the next opponent in the lineup once the targeted one falls
+pub fn retarget_enemy(
+    lineup: Res<Lineup>,
+    mut targeted: ResMut<TargetedEnemy>,
+    health: Query<&Health, Without<Player>>,
+) {
+    let standing = |enemy: &Entity| health.get(*enemy).is_ok_and(|health| health.current > 0);
+    if targeted.filter(standing).is_some() {
+        return;
+    }
+    let next = lineup.iter().copied().find(standing);
+    if **targeted != next {
+        **targeted = next;
+    }
+}
@@ -0,0 +1,26 @@
+use bevy::{app::AppExit, prelude::*, window::WindowCloseRequested};
+
+use crate::{save::MatchSnapshot, settings::Settings, stats::Stats, stress::StressTest, GameState};
+
+// Writes everything out on the way out, whether the game is quit or its window closed. A match in
+// progress is saved to be continued, the menus have nothing of their own to save
+pub fn save_on_exit(
+    mut exits: EventReader<AppExit>,
+    mut closes: EventReader<WindowCloseRequested>,
+    state: Res<State<GameState>>,
+    settings: Res<Settings>,
+    stats: Res<Stats>,
+    snapshot: MatchSnapshot,
+    stress_test: Option<Res<StressTest>>,
+) {
+    // Closing the window sends both, only save once
+    if exits.iter().count() + closes.iter().count() == 0 {
+        return;
+    }
+    settings.save();
+    stats.save();
+    // A stress test's match isn't the player's, it mustn't replace their save
+    if *state.current() == GameState::Game && stress_test.is_none() {
+        snapshot.save();
+    }
+}
@@ -0,0 +1,127 @@
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{self, Color32, FontId, ProgressBar, RichText},
+    EguiContext,
+};
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+
+use crate::{mana_cap, GemType, GemsPopped, Health, Player, Resources};
+
+/// Experience for every gem the player pops
+const XP_PER_GEM: u32 = 1;
+/// Extra experience for every match the player makes, cascades included
+const XP_PER_MATCH: u32 = 2;
+const HEALTH_PER_LEVEL: u32 = 5;
+/// Mana of every type granted on leveling up
+const LEVEL_UP_MANA: u32 = 3;
+const LEVEL_UP_BANNER_SECONDS: f32 = 2.0;
+
+/// The player's experience and the level it has earned them
+#[derive(Component, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Experience {
+    /// Experience gathered towards the next level
+    pub xp: u32,
+    pub level: u32,
+}
+
+impl Default for Experience {
+    fn default() -> Self {
+        Self { xp: 0, level: 1 }
+    }
+}
+
+/// Experience needed to go from `level` to the next one, the one place to tune the curve
+fn xp_to_next(level: u32) -> u32 {
+    20 + 10 * level * level
+}
+
+impl Experience {
+    /// Max health the levels gained so far add, so it carries over along with the level
+    pub fn bonus_health(&self) -> u32 {
+        HEALTH_PER_LEVEL * self.level.saturating_sub(1)
+    }
+
+    /// Adds experience, returning how many levels it gained
+    fn gain(&mut self, xp: u32) -> u32 {
+        self.xp += xp;
+        let mut levels = 0;
+        while self.xp >= xp_to_next(self.level) {
+            self.xp -= xp_to_next(self.level);
+            self.level += 1;
+            levels += 1;
+        }
+        levels
+    }
+}
+
+impl egui::Widget for &Experience {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        ui.horizontal(|ui| {
+            let needed = xp_to_next(self.level);
+            ui.label(format!("Level {}", self.level));
+            ui.add(
+                ProgressBar::new(self.xp as f32 / needed as f32)
+                    .text(format!("{} xp to next", needed - self.xp)),
+            );
+        })
+        .response
+    }
+}
+
+// Resource containing the level just reached, shown as a banner until its timer runs out
+#[derive(Default)]
+pub struct LevelUpBanner(Option<(u32, Timer)>);
+
+pub fn gain_experience(
+    mut popped: EventReader<GemsPopped>,
+    mut banner: ResMut<LevelUpBanner>,
+    mut player: Query<(Entity, &mut Experience, &mut Health, &mut Resources), With<Player>>,
+) {
+    let (entity, mut experience, mut health, mut resources) = match player.get_single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    for popped in popped.iter().filter(|popped| popped.side == entity) {
+        let levels = experience.gain(XP_PER_MATCH + XP_PER_GEM * popped.gems.len() as u32);
+        if levels == 0 {
+            continue;
+        }
+        info!("Reached level {}", experience.level);
+        health.max += HEALTH_PER_LEVEL * levels;
+        health.heal(HEALTH_PER_LEVEL * levels);
+        for typ in GemType::iter().filter(|typ| mana_cap(*typ) > 0) {
+            resources.add(typ, LEVEL_UP_MANA * levels);
+        }
+        banner.0 = Some((
+            experience.level,
+            Timer::from_seconds(LEVEL_UP_BANNER_SECONDS, false),
+        ));
+    }
+}
+
+pub fn level_up_banner(
+    time: Res<Time>,
+    mut banner: ResMut<LevelUpBanner>,
+    mut egui_ctx: ResMut<EguiContext>,
+) {
+    let (level, timer) = match &mut banner.0 {
+        Some(banner) => banner,
+        None => return,
+    };
+    if timer.tick(time.delta()).finished() {
+        banner.0 = None;
+        return;
+    }
+    let fade = 1.0 - timer.percent();
+    egui::Area::new("Level up")
+        .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, -120.0))
+        .show(egui_ctx.ctx_mut(), |ui| {
+            ui.label(
+                RichText::new(format!("Level up! Level {level}"))
+                    .font(FontId::monospace(50.0))
+                    .color(Color32::GOLD.linear_multiply(fade)),
+            );
+        });
+}
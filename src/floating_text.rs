@@ -0,0 +1,196 @@
+use std::time::Duration;
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_egui::{
+    egui::{self, Color32, FontId},
+    EguiContext,
+};
+use bevy_tweening::{lens::TransformPositionLens, Animator, EaseFunction, Tween, TweeningType};
+
+use crate::{
+    assets::{gem_symbol, GemAssets},
+    settings::Settings,
+    CriticalHit, GemType, GemsPopped, MainCamera,
+};
+
+const FLOATING_TEXT_SECONDS: f32 = 1.0;
+/// Reduced motion keeps the text still, so it doesn't need to stay up as long to be read
+const STILL_TEXT_SECONDS: f32 = 0.6;
+const FLOATING_TEXT_RISE: f32 = 0.4;
+const FLOATING_TEXT_SIZE: f32 = 24.0;
+const CRIT_TEXT_SIZE: f32 = 36.0;
+const GEM_SYMBOL_SIZE: f32 = 16.0;
+
+// Text shown where gems popped, rising and fading out until its timer runs out
+#[derive(Component)]
+pub struct FloatingText {
+    text: String,
+    color: Color32,
+    size: f32,
+    timer: Timer,
+}
+
+pub fn spawn_floating_text(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    assets: Res<GemAssets>,
+    mut popped: EventReader<GemsPopped>,
+    mut crits: EventReader<CriticalHit>,
+) {
+    for popped in popped.iter() {
+        let mut by_type: HashMap<GemType, Vec<Vec3>> = HashMap::default();
+        for (pos, typ) in &popped.gems {
+            by_type.entry(*typ).or_default().push(*pos);
+        }
+        for (typ, positions) in by_type {
+            let (text, color) = match typ {
+                // Critical hits get their own text
+                GemType::Skull if popped.critical => continue,
+                GemType::Skull if popped.damage > 0 => {
+                    (format!("-{} health", popped.damage), Color32::RED)
+                }
+                GemType::Skull => continue,
+                typ => (
+                    format!("+{} {}", positions.len(), assets.name(typ).to_lowercase()),
+                    typ.into(),
+                ),
+            };
+            let start = positions.iter().sum::<Vec3>() / positions.len() as f32;
+            spawn(
+                &mut commands,
+                &settings,
+                start,
+                text,
+                color,
+                FLOATING_TEXT_SIZE,
+            );
+        }
+    }
+    for crit in crits.iter() {
+        spawn(
+            &mut commands,
+            &settings,
+            crit.at,
+            format!("CRIT! -{} health", crit.damage),
+            Color32::GOLD,
+            CRIT_TEXT_SIZE,
+        );
+    }
+}
+
+fn spawn(
+    commands: &mut Commands,
+    settings: &Settings,
+    start: Vec3,
+    text: String,
+    color: Color32,
+    size: f32,
+) {
+    let seconds = if settings.reduced_motion {
+        STILL_TEXT_SECONDS
+    } else {
+        FLOATING_TEXT_SECONDS
+    };
+    let mut entity = commands.spawn_bundle((
+        Transform::from_translation(start),
+        GlobalTransform::default(),
+        FloatingText {
+            text,
+            color,
+            size,
+            timer: Timer::from_seconds(seconds, false),
+        },
+    ));
+    if !settings.reduced_motion {
+        entity.insert(Animator::new(Tween::new(
+            EaseFunction::QuadraticOut,
+            TweeningType::Once,
+            Duration::from_secs_f32(seconds),
+            TransformPositionLens {
+                start,
+                end: start + Vec3::Y * FLOATING_TEXT_RISE,
+            },
+        )));
+    }
+}
+
+// Draws the text over the board facing the screen, fading it out over its lifetime
+pub fn floating_text(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut egui_ctx: ResMut<EguiContext>,
+    windows: Res<Windows>,
+    images: Res<Assets<Image>>,
+    camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    mut texts: Query<(Entity, &Transform, &mut FloatingText)>,
+) {
+    let (camera, camera_transform) = match camera.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let window_height = windows.primary().height();
+    let painter = egui_ctx.ctx_mut().layer_painter(egui::LayerId::new(
+        egui::Order::Foreground,
+        egui::Id::new("floating text"),
+    ));
+    for (entity, transform, mut text) in texts.iter_mut() {
+        if text.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        let screen = match camera.world_to_screen(
+            &windows,
+            &images,
+            camera_transform,
+            transform.translation,
+        ) {
+            Some(screen) => screen,
+            None => continue,
+        };
+        let fade = 1.0 - text.timer.percent();
+        painter.text(
+            // Bevy's screen space starts at the bottom of the window, egui's at the top
+            egui::pos2(screen.x, window_height - screen.y),
+            egui::Align2::CENTER_CENTER,
+            &text.text,
+            FontId::proportional(text.size),
+            text.color.linear_multiply(fade),
+        );
+    }
+}
+
+// Letters over every gem for telling types apart without relying on color
+pub fn gem_symbols(
+    settings: Res<Settings>,
+    mut egui_ctx: ResMut<EguiContext>,
+    windows: Res<Windows>,
+    images: Res<Assets<Image>>,
+    camera: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    gems: Query<(&GlobalTransform, &GemType)>,
+) {
+    if !settings.gem_symbols {
+        return;
+    }
+    let (camera, camera_transform) = match camera.get_single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let window_height = windows.primary().height();
+    let painter = egui_ctx.ctx_mut().layer_painter(egui::LayerId::new(
+        egui::Order::Background,
+        egui::Id::new("gem symbols"),
+    ));
+    for (transform, typ) in gems.iter() {
+        if let Some(screen) =
+            camera.world_to_screen(&windows, &images, camera_transform, transform.translation)
+        {
+            painter.text(
+                egui::pos2(screen.x, window_height - screen.y),
+                egui::Align2::CENTER_CENTER,
+                gem_symbol(*typ),
+                FontId::monospace(GEM_SYMBOL_SIZE),
+                Color32::BLACK,
+            );
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_match3::prelude::*;
+
+use crate::{
+    gravity::GravityDirection, BoardPosition, GemSlot, InputBlockers, Player, SelectedSlot,
+    SlotIndex, Swapper, Turn, TurnState,
+};
+
+// Resource containing the slot the keyboard cursor is on, hidden while the mouse is in use
+#[derive(Default, Deref, DerefMut)]
+pub struct FocusedSlot(Option<UVec2>);
+
+/// A move of the cursor, or of the selected gem when one is selected
+#[derive(Clone, Copy)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl Direction {
+    /// The position next to `pos` in this direction, `None` past the edge of the board
+    fn step(self, pos: UVec2, board: &Board) -> Option<UVec2> {
+        let next = match self {
+            Direction::Left => pos.left(),
+            Direction::Right => pos.right(),
+            Direction::Up => pos.up(),
+            Direction::Down => pos.down(),
+        };
+        let max = board
+            .iter()
+            .fold(UVec2::ZERO, |max, (pos, _)| max.max(*pos));
+        (next != pos && next.cmple(max).all()).then_some(next)
+    }
+}
+
+// Everything needed to move the cursor around and swap gems from it, shared by every input device
+#[derive(SystemParam)]
+pub struct BoardCursor<'w, 's> {
+    focused: ResMut<'w, FocusedSlot>,
+    selected: ResMut<'w, SelectedSlot>,
+    board_commands: ResMut<'w, BoardCommands>,
+    turn_state: ResMut<'w, State<TurnState>>,
+    board: Res<'w, Board>,
+    gravity: Res<'w, GravityDirection>,
+    index: Res<'w, SlotIndex>,
+    slots: Query<'w, 's, &'static GemSlot>,
+    swapper: Swapper<'w, 's>,
+    blockers: InputBlockers<'w, 's>,
+    turn: Res<'w, Turn>,
+    player: Query<'w, 's, (), With<Player>>,
+}
+
+impl BoardCursor<'_, '_> {
+    /// Whether it's the player's move and nothing else has hold of the board
+    pub fn usable(&self) -> bool {
+        self.turn_state.current() == &TurnState::AwaitingMove
+            && !self.blockers.blocked()
+            && self.player.get(**self.turn).is_ok()
+    }
+
+    /// Shows the cursor on the selected slot or the top left corner, if it isn't showing already
+    fn show(&mut self) -> Option<UVec2> {
+        if self.focused.is_none() {
+            let selected = self
+                .selected
+                .and_then(|selected| self.slots.get(selected).ok())
+                .map(|slot| slot.pos);
+            **self.focused = Some(selected.unwrap_or(UVec2::ZERO));
+            return None;
+        }
+        **self.focused
+    }
+
+    /// Moves the cursor, or swaps the selected gem with its neighbour if one is selected, going
+    /// `direction` on screen whichever way the board is turned
+    pub fn step(&mut self, direction: Direction) {
+        if !self.usable() {
+            return;
+        }
+        let focused = match self.show() {
+            Some(focused) => focused,
+            None => return,
+        };
+        let next = match self.gravity.on_board(direction).step(focused, &self.board) {
+            Some(next) => next,
+            None => return,
+        };
+        let selected = self
+            .selected
+            .and_then(|selected| self.slots.get(selected).ok());
+        if let Some(selected) = selected.filter(|selected| selected.pos == focused) {
+            let other = match self.slots.get(self.index[&next]) {
+                Ok(other) => other,
+                Err(_) => return,
+            };
+            if self.swapper.swap(selected, other, &mut self.board_commands) {
+                self.turn_state.set(TurnState::Resolving).unwrap();
+            }
+            **self.selected = None;
+        }
+        **self.focused = Some(next);
+    }
+
+    /// Selects the slot under the cursor, or deselects it if it already is
+    pub fn confirm(&mut self) {
+        if !self.usable() {
+            return;
+        }
+        let focused = match self.show() {
+            Some(focused) => focused,
+            None => return,
+        };
+        let slot = self.index[&focused];
+        **self.selected = if **self.selected == Some(slot) {
+            None
+        } else {
+            self.slots
+                .get(slot)
+                .ok()
+                .filter(|focused| self.swapper.movable(focused))
+                .map(|_| slot)
+        };
+    }
+}
+
+pub fn keyboard_cursor(keys: Res<Input<KeyCode>>, mut cursor: BoardCursor) {
+    for (key, direction) in [
+        (KeyCode::Left, Direction::Left),
+        (KeyCode::Right, Direction::Right),
+        (KeyCode::Up, Direction::Up),
+        (KeyCode::Down, Direction::Down),
+    ] {
+        if keys.just_pressed(key) {
+            cursor.step(direction);
+        }
+    }
+    if keys.just_pressed(KeyCode::Space) || keys.just_pressed(KeyCode::Return) {
+        cursor.confirm();
+    }
+}
+
+// Hides the cursor as soon as the mouse is used, so the two never show different slots
+pub fn hide_cursor_on_mouse(mut moved: EventReader<CursorMoved>, mut focused: ResMut<FocusedSlot>) {
+    if moved.iter().count() > 0 && focused.is_some() {
+        **focused = None;
+    }
+}
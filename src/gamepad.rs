@@ -0,0 +1,137 @@
+use bevy::prelude::*;
+
+use crate::{
+    focus::{BoardCursor, Direction},
+    shop::Progress,
+    ManaWarning, Player, Resources, Skill, SkillBook, SkillType,
+};
+
+/// How far the stick has to be pushed to move the cursor, it has to come back inside before it
+/// moves again
+const STICK_THRESHOLD: f32 = 0.5;
+
+// Resource containing the gamepad the game listens to, the first one connected
+#[derive(Default, Deref, DerefMut)]
+pub struct ActiveGamepad(Option<Gamepad>);
+
+pub fn track_gamepads(
+    mut events: EventReader<GamepadEvent>,
+    gamepads: Res<Gamepads>,
+    mut active: ResMut<ActiveGamepad>,
+) {
+    for GamepadEvent(gamepad, event) in events.iter() {
+        match event {
+            GamepadEventType::Connected if active.is_none() => {
+                info!("Using gamepad {}", gamepad.0);
+                **active = Some(*gamepad);
+            }
+            GamepadEventType::Disconnected if **active == Some(*gamepad) => {
+                **active = gamepads.iter().copied().find(|other| other != gamepad);
+                match **active {
+                    Some(other) => info!("Gamepad {} disconnected, using {}", gamepad.0, other.0),
+                    None => info!("Gamepad {} disconnected", gamepad.0),
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+pub fn gamepad_cursor(
+    active: Res<ActiveGamepad>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    mut stick_held: Local<bool>,
+    mut cursor: BoardCursor,
+) {
+    let gamepad = match **active {
+        Some(gamepad) => gamepad,
+        None => return,
+    };
+    let pressed = |button| buttons.just_pressed(GamepadButton(gamepad, button));
+    for (button, direction) in [
+        (GamepadButtonType::DPadLeft, Direction::Left),
+        (GamepadButtonType::DPadRight, Direction::Right),
+        (GamepadButtonType::DPadUp, Direction::Up),
+        (GamepadButtonType::DPadDown, Direction::Down),
+    ] {
+        if pressed(button) {
+            cursor.step(direction);
+        }
+    }
+
+    let stick = Vec2::new(
+        axes.get(GamepadAxis(gamepad, GamepadAxisType::LeftStickX))
+            .unwrap_or_default(),
+        axes.get(GamepadAxis(gamepad, GamepadAxisType::LeftStickY))
+            .unwrap_or_default(),
+    );
+    if stick.length() < STICK_THRESHOLD {
+        *stick_held = false;
+    } else if !*stick_held {
+        *stick_held = true;
+        // The stick points up when y is positive, the board counts rows downward
+        let direction = if stick.x.abs() > stick.y.abs() {
+            if stick.x < 0.0 {
+                Direction::Left
+            } else {
+                Direction::Right
+            }
+        } else if stick.y > 0.0 {
+            Direction::Up
+        } else {
+            Direction::Down
+        };
+        cursor.step(direction);
+    }
+
+    if pressed(GamepadButtonType::South) {
+        cursor.confirm();
+    }
+}
+
+// Casts the skills that don't need a target from the face buttons, like clicking them in the
+// sidebar does
+pub fn gamepad_skills(
+    active: Res<ActiveGamepad>,
+    buttons: Res<Input<GamepadButton>>,
+    cursor: BoardCursor,
+    book: Res<SkillBook>,
+    mut warning: ResMut<ManaWarning>,
+    mut skills: EventWriter<Skill>,
+    player: Query<(Entity, &Resources), With<Player>>,
+    progress: Res<Progress>,
+) {
+    let gamepad = match **active {
+        Some(gamepad) => gamepad,
+        None => return,
+    };
+    if !cursor.usable() {
+        return;
+    }
+    let (player, resources) = match player.get_single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    for (button, skill) in [
+        (GamepadButtonType::West, SkillType::Bonk),
+        (GamepadButtonType::North, SkillType::Heal),
+    ] {
+        if !buttons.just_pressed(GamepadButton(gamepad, button)) || !progress.knows(skill) {
+            continue;
+        }
+        let cost = book.cost(skill);
+        if resources.can_afford(cost) {
+            skills.send(Skill {
+                typ: skill,
+                source: player,
+                target: None,
+            });
+        } else {
+            *warning = ManaWarning {
+                missing: resources.missing(cost),
+                timer: Timer::from_seconds(0.6, false),
+            };
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumIter};
+
+use crate::{focus::Direction, GEM_SIZE};
+
+// Resource containing the side of the screen gems fall towards. The board itself only ever drops
+// gems down its columns, so any other direction turns the whole board on screen to match
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Display, EnumIter, Serialize, Deserialize)]
+pub enum GravityDirection {
+    #[default]
+    Down,
+    Up,
+    Left,
+    Right,
+}
+
+impl GravityDirection {
+    /// Where on screen a gem goes, given where it would be on a board falling down
+    pub fn orient(self, pos: Vec3) -> Vec3 {
+        match self {
+            GravityDirection::Down => pos,
+            GravityDirection::Up => Vec3::new(-pos.x, -pos.y, pos.z),
+            GravityDirection::Left => Vec3::new(pos.y, -pos.x, pos.z),
+            GravityDirection::Right => Vec3::new(-pos.y, pos.x, pos.z),
+        }
+    }
+
+    /// How far from its slot a new gem starts, a whole board back against the fall so it drops in
+    /// from off screen
+    pub fn spawn_offset(self, dimensions: UVec2) -> Vec3 {
+        self.orient(Vec3::Y * GEM_SIZE * dimensions.y as f32)
+    }
+
+    /// The step on the board that moves `direction` on screen
+    pub fn on_board(self, direction: Direction) -> Direction {
+        match (self, direction) {
+            (GravityDirection::Down, direction) => direction,
+            (GravityDirection::Up, Direction::Left) => Direction::Right,
+            (GravityDirection::Up, Direction::Right) => Direction::Left,
+            (GravityDirection::Up, Direction::Up) => Direction::Down,
+            (GravityDirection::Up, Direction::Down) => Direction::Up,
+            (GravityDirection::Left, Direction::Left) => Direction::Down,
+            (GravityDirection::Left, Direction::Right) => Direction::Up,
+            (GravityDirection::Left, Direction::Up) => Direction::Left,
+            (GravityDirection::Left, Direction::Down) => Direction::Right,
+            (GravityDirection::Right, Direction::Left) => Direction::Up,
+            (GravityDirection::Right, Direction::Right) => Direction::Down,
+            (GravityDirection::Right, Direction::Up) => Direction::Right,
+            (GravityDirection::Right, Direction::Down) => Direction::Left,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_gems_start_a_board_back_against_the_fall() {
+        let dimensions = UVec2::new(6, 10);
+        let height = GEM_SIZE * 10.0;
+        for (gravity, expected) in [
+            (GravityDirection::Down, Vec3::Y * height),
+            (GravityDirection::Up, -Vec3::Y * height),
+            (GravityDirection::Left, Vec3::X * height),
+            (GravityDirection::Right, -Vec3::X * height),
+        ] {
+            let offset = gravity.spawn_offset(dimensions);
+            assert!(offset.abs_diff_eq(expected, 1e-5), "{gravity}: {offset}");
+        }
+    }
+}
@@ -14,187 +14,1112 @@
 )]
 #![feature(is_some_with)]
 
-use std::time::Duration;
+use std::{cmp::Ordering, collections::VecDeque, fs, marker::PhantomData, time::Duration};
 
-use assets::{load_assets, GemAssets};
-use bevy::{app::AppExit, gltf::Gltf, prelude::*, utils::HashMap};
+use assets::{apply_mesh_quality, apply_palette, asset_path, load_assets, GemAssets, MeshQuality};
+use assist::{auto_match, AutoMatch};
+use bevy::{
+    app::AppExit,
+    ecs::system::SystemParam,
+    gltf::Gltf,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
 use bevy_egui::{
     egui::{self, FontId, RichText},
     EguiContext, EguiPlugin,
 };
 use bevy_inspector_egui::egui::{Color32, ProgressBar};
+#[cfg(feature = "inspector")]
+use bevy_inspector_egui::WorldInspectorPlugin;
 use bevy_match3::{prelude::*, Match3Config};
 use bevy_mod_raycast::{DefaultRaycastingPlugin, RayCastMesh, RayCastMethod, RayCastSource};
 use bevy_tweening::{
-    lens::{TransformPositionLens, TransformRotateZLens},
-    Animator, EaseFunction, EaseMethod, Tween, TweeningPlugin, TweeningType,
+    lens::{TransformPositionLens, TransformScaleLens},
+    Animator, AnimatorState, EaseFunction, EaseMethod, Lens, Tween, Tweenable, TweeningPlugin,
+    TweeningType,
 };
+use blitz::{blitz_clock_ui, reset_blitz_clock, tick_blitz_clock, BlitzClock};
+use board_setup::{board_setup, fit_camera_to_board};
+use effects::{GemEffects, PopContext};
+use encounter::{retarget_enemy, Foes, Lineup, TargetedEnemy, MAX_LINEUP};
+use exit::save_on_exit;
+use experience::{gain_experience, level_up_banner, Experience, LevelUpBanner};
+use floating_text::{floating_text, gem_symbols, spawn_floating_text};
+use focus::{hide_cursor_on_mouse, keyboard_cursor, FocusedSlot};
+use gamepad::{gamepad_cursor, gamepad_skills, track_gamepads, ActiveGamepad};
+use gravity::GravityDirection;
 use heron::PhysicsPlugin;
+use moves::{cache_valid_moves, invalidate_valid_moves, track_locked_slots, ValidMoves};
+use music::{load_music, play_music, Music};
+use opponents::{load_opponents, AiDifficulty, OpponentDefinition, OpponentRoster};
+use particles::{load_particle_mesh, move_particles, spawn_particles};
+use portrait::{
+    detect_hurt, portrait_frame, react_to_hurt, thinking_dots, thinking_pulse, PortraitReactions,
+    SideHurt,
+};
+use preview::swap_preview;
+use replay::{chain_replay, CascadeStep, ChainReplay};
+use save::{delete_save, forget_abandoned, Abandoned, MatchSnapshot, SavedGame};
+use score::{track_combo, ActiveScoreRule, Combo, Score};
+use seed::{
+    generate_board, seed_field, seeded_locks, BoardSeed, CombatRng, EncounterRng, MatchSeed,
+    SpawnRng,
+};
+use serde::{Deserialize, Serialize};
+use settings::{
+    apply_msaa, apply_shadows, apply_vsync, limit_frame_rate, save_settings, settings_menu,
+    settings_ui, CameraView, SelectionStyle, Settings, SwapInput,
+};
+use share::{share_code_label, ShareCode};
+use shop::{bank_progress, shop, Progress};
+use sounds::{duck_for_big_moments, load_sounds, play_hurt_sound, play_pop_sound, Ducking};
+use stats::{
+    chain_ui, record_result, reset_match_cascade, track_cascades, Chain, MatchCascade, Stats,
+};
+use status::{
+    burn, skip_stunned_turns, tick_status_effects, StatusEffect, StatusEffects, StatusKind,
+};
+use stress::StressTestPlugin;
 use strum::{Display, EnumIter, IntoEnumIterator};
+use undo::{record_undo, reset_undo, undo_move, Undo};
 
 mod assets;
+mod assist;
+mod blitz;
+mod board_setup;
+#[cfg(feature = "debug_overlay")]
+mod debug_overlay;
+mod effects;
+mod encounter;
+mod exit;
+mod experience;
+mod floating_text;
+mod focus;
+mod gamepad;
+mod gravity;
+mod moves;
+mod music;
+mod opponents;
+mod particles;
+mod persistence;
+mod portrait;
+mod preview;
+mod replay;
+mod save;
+mod score;
+mod seed;
+mod settings;
+mod share;
+mod shop;
+mod sounds;
+mod stats;
+mod status;
+mod stress;
+mod undo;
 
+// Development builds can opt into extra tooling with cargo features:
+// - `inspector` adds the egui world inspector, in its own collapsible "World" window floating over
+//   the board so the side panels keep their place
+// - `debug_overlay` adds the F3 overlay with board coordinates and match info
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins)
-        .insert_resource(Msaa { samples: 4 })
+    let settings = Settings::load();
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins)
+        .insert_resource(Msaa {
+            samples: settings.supported_msaa(),
+        })
         .insert_resource(AmbientLight {
             color: Color::WHITE,
             brightness: 3.0,
         })
         .insert_resource(ClearColor(Color::BLACK))
         .add_plugin(EguiPlugin)
-        // .add_plugin(WorldInspectorPlugin::default())
         .add_plugin(PhysicsPlugin::default())
         .add_plugin(DefaultRaycastingPlugin::<RaycastSet>::default())
         .add_plugin(TweeningPlugin)
-        .insert_resource(Match3Config {
-            gem_types: 8,
-            board_dimensions: UVec2::splat(8),
-        })
+        .insert_resource(settings.board.config())
         .add_plugin(Match3Plugin)
+        .init_resource::<GameMode>()
+        .init_resource::<GravityDirection>()
+        .init_resource::<BoardSeed>()
+        .init_resource::<MatchSeed>()
+        .init_resource::<CombatRng>()
+        .init_resource::<SpawnRng>()
+        .init_resource::<EncounterRng>()
         .add_state(GameState::MainMenu)
         .add_state(TurnState::AwaitingMove)
         .add_startup_system(setup)
         .add_startup_system(load_assets)
+        .add_startup_system(load_music)
+        .add_startup_system(load_sounds)
+        .add_startup_system(load_opponents)
+        .add_startup_system(load_particle_mesh)
+        .init_resource::<Music>()
+        .add_system(play_music.after(duck_for_big_moments))
+        .insert_resource(settings)
+        .add_system(apply_msaa)
+        .add_system(apply_shadows)
+        .add_system(apply_vsync)
+        .add_system_to_stage(CoreStage::Last, limit_frame_rate)
+        .add_system_to_stage(CoreStage::Last, save_on_exit)
+        .add_system(save_settings)
+        .add_system(apply_mesh_quality)
+        .add_system(apply_camera_view)
+        .add_system(fit_camera_to_board)
+        .init_resource::<ScreenShake>()
+        .add_system(add_trauma)
+        .add_system(shake_camera.after(add_trauma).after(apply_camera_view))
+        .init_resource::<RarityTable>()
+        .init_resource::<GemEffects>()
+        .init_resource::<Obstacles>()
+        .add_system(apply_palette.before(apply_material))
         .add_system(apply_material)
+        .add_system(shimmer)
+        .add_system(rainbow)
         .add_event::<Skill>()
+        .add_event::<AutoMatch>()
+        .add_event::<GemsPopped>()
+        .add_event::<CriticalHit>()
+        .add_event::<SideHurt>()
+        .add_event::<BoardSettled>()
+        .add_event::<MoveResolved>()
+        .init_resource::<MoveLimit>()
+        .init_resource::<MovesLeft>()
+        .init_resource::<BlitzClock>()
+        .init_resource::<Undo>()
+        .insert_resource(Stats::load())
+        .insert_resource(Progress::load())
+        .init_resource::<MatchCascade>()
+        .init_resource::<Chain>()
+        .init_resource::<LevelUpBanner>()
+        .add_system(track_cascades)
+        .init_resource::<Ducking>()
+        .add_system(duck_for_big_moments)
+        .add_system(play_pop_sound.after(duck_for_big_moments))
+        .add_system(play_hurt_sound.after(duck_for_big_moments))
+        .add_system(spawn_floating_text)
+        .add_system(floating_text.after(spawn_floating_text))
+        .add_system(spawn_particles)
+        .add_system(move_particles.after(spawn_particles))
+        .init_resource::<AutoCast>()
+        .init_resource::<Targeting>()
+        .init_resource::<Paused>()
+        .init_resource::<ConfirmingQuit>()
+        .init_resource::<Abandoned>()
+        .init_resource::<FocusedSlot>()
+        .init_resource::<ActiveGamepad>()
+        .add_system(track_gamepads)
+        .init_resource::<ManaWarning>()
+        .init_resource::<ChainReplay>()
+        .insert_resource(SkillBook::load())
+        .init_resource::<ActiveScoreRule>()
+        .init_resource::<Combo>()
+        .add_system(track_combo)
+        .init_resource::<ValidMoves>()
+        .init_resource::<BoardTweens>()
+        .init_resource::<OpponentIntent>()
+        .init_resource::<Lineup>()
+        .init_resource::<TargetedEnemy>()
+        .init_resource::<PortraitReactions>()
+        .add_system_to_stage(CoreStage::PostUpdate, detect_hurt)
+        .add_system_to_stage(CoreStage::PostUpdate, invalidate_valid_moves)
+        .add_system_to_stage(CoreStage::PostUpdate, track_locked_slots)
         .add_system_set(SystemSet::on_enter(GameState::MainMenu))
         .add_system_set(SystemSet::on_update(GameState::MainMenu).with_system(main_menu))
         .add_system_set(SystemSet::on_exit(GameState::MainMenu))
+        .add_system_set(SystemSet::on_update(GameState::Settings).with_system(settings_menu))
+        .add_system_set(SystemSet::on_update(GameState::BoardSetup).with_system(board_setup))
         .add_system_set(
             SystemSet::on_enter(GameState::Game)
+                .with_system(generate_board.before(spawn_board).before(setup_resources))
                 .with_system(spawn_board)
-                .with_system(setup_resources),
+                .with_system(setup_resources)
+                .with_system(reset_moves_left.before(setup_resources))
+                .with_system(reset_blitz_clock.before(setup_resources))
+                .with_system(start_camera_intro)
+                .with_system(reset_match_cascade)
+                .with_system(reset_undo)
+                .with_system(forget_abandoned),
         )
         .add_system_set(
             SystemSet::on_update(GameState::Game)
+                .with_system(
+                    settle_board_tweens
+                        .after(freeze_animations)
+                        .before(gem_events)
+                        .before(select),
+                )
                 .with_system(gem_events)
                 .with_system(update_raycast_with_cursor)
+                .with_system(hover_highlight.after(update_raycast_with_cursor))
+                .with_system(swap_preview.after(update_raycast_with_cursor).after(select))
                 .with_system(select)
+                .with_system(drag_swap.after(select).before(animate_selected))
+                .with_system(keyboard_cursor.after(drag_swap).before(animate_selected))
+                .with_system(hide_cursor_on_mouse.before(keyboard_cursor))
+                .with_system(
+                    gamepad_cursor
+                        .after(keyboard_cursor)
+                        .before(animate_selected),
+                )
+                .with_system(gamepad_skills.before(skills))
+                .with_system(toggle_pause.before(pick_target))
+                .with_system(freeze_animations.after(toggle_pause))
+                .with_system(pause_menu.after(toggle_pause))
+                .with_system(
+                    hint.before(select)
+                        .before(animate_selected)
+                        .before(hover_highlight),
+                )
+                .with_system(pick_target.after(select))
+                .with_system(highlight_swap_targets.after(select).after(keyboard_cursor))
+                .with_system(highlight_targets.after(apply_material))
                 .with_system(animate_selected.before(gem_events))
+                .with_system(respawn_gem_meshes.after(apply_mesh_quality))
+                .with_system(crack_gems)
+                .with_system(shatter)
+                .with_system(finish_camera_intro)
+                .with_system(tick_mana_warning)
+                .with_system(chain_replay)
+                .with_system(gem_symbols)
                 .with_system(left_sidebar)
-                .with_system(right_sidebar)
+                .with_system(auto_match.after(left_sidebar).before(gem_events))
+                .with_system(right_sidebar.after(react_to_hurt))
+                .with_system(react_to_hurt)
                 .with_system(skills)
+                .with_system(auto_cast.before(skills))
                 .with_system(turn_switched)
-                .with_system(opponent_ai),
+                .with_system(opponent_ai.before(skills).after(skip_stunned_turns))
+                .with_system(count_moves.before(check_defeat))
+                .with_system(tick_blitz_clock)
+                .with_system(record_undo.after(settle_board_tweens))
+                .with_system(undo_move.after(record_undo).after(pause_menu))
+                .with_system(blitz_clock_ui)
+                .with_system(chain_ui)
+                .with_system(gain_experience)
+                .with_system(level_up_banner)
+                .with_system(retarget_enemy)
+                .with_system(tick_status_effects)
+                .with_system(burn)
+                .with_system(skip_stunned_turns.after(gem_events))
+                .with_system(check_defeat),
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::Game)
+                .after(settle_board_tweens)
+                .with_system(check_board_mirror)
+                .with_system(cache_valid_moves)
+                .with_system(reshuffle_when_stuck.after(cache_valid_moves)),
         )
         .add_system_set(SystemSet::on_exit(GameState::Game))
-        .run();
+        .add_system_set(SystemSet::on_update(GameState::GameOver).with_system(game_over))
+        .add_system_set(
+            SystemSet::on_enter(GameState::GameOver)
+                .with_system(delete_save)
+                .with_system(record_result)
+                .with_system(bank_progress),
+        )
+        .add_system_set(SystemSet::on_exit(GameState::GameOver).with_system(despawn_game))
+        .add_system_set(SystemSet::on_update(GameState::Shop).with_system(shop));
+    if let Some(stress_test) = StressTestPlugin::from_args() {
+        app.add_plugin(stress_test);
+    }
+    #[cfg(feature = "inspector")]
+    app.add_plugin(WorldInspectorPlugin::default());
+    #[cfg(feature = "debug_overlay")]
+    app.add_plugin(debug_overlay::DebugOverlayPlugin);
+    app.run();
 }
 
-fn setup(mut commands: Commands) {
+fn setup(mut commands: Commands, settings: Res<Settings>) {
     let mut camera = OrthographicCameraBundle::new_3d();
-    camera.transform = Transform::from_xyz(0.0, 0.0, 10.0).looking_at(Vec3::ZERO, Vec3::Y);
+    camera.transform = camera_transform(settings.camera_view);
     commands
         .spawn_bundle(camera)
-        .insert(RayCastSource::<RaycastSet>::new());
+        .insert_bundle((RayCastSource::<RaycastSet>::new(), MainCamera));
+    // A key light from the top left, for the gems to cast their shadows onto the slots behind them
+    commands.spawn_bundle(DirectionalLightBundle {
+        directional_light: DirectionalLight {
+            illuminance: KEY_LIGHT_LUX,
+            shadows_enabled: settings.shadows,
+            ..default()
+        },
+        transform: Transform::from_xyz(-4.0, 4.0, 10.0).looking_at(Vec3::ZERO, Vec3::Y),
+        ..default()
+    });
+}
+
+/// Brightness of the key light, kept low next to the ambient light that does most of the lighting
+const KEY_LIGHT_LUX: f32 = 10_000.0;
+
+#[derive(Component)]
+struct MainCamera;
+
+fn camera_transform(view: CameraView) -> Transform {
+    match view {
+        CameraView::Flat => Transform::from_xyz(0.0, 0.0, 10.0),
+        // Look up at the board from slightly below to show off the gem meshes
+        CameraView::Tilted => Transform::from_xyz(0.0, -3.0, 9.5),
+    }
+    .looking_at(Vec3::ZERO, Vec3::Y)
+}
+
+// Raycasting goes through the camera's transform, so selection stays accurate at any angle
+fn apply_camera_view(settings: Res<Settings>, mut camera: Query<&mut Transform, With<MainCamera>>) {
+    if !settings.is_changed() {
+        return;
+    }
+    for mut transform in camera.iter_mut() {
+        *transform = camera_transform(settings.camera_view);
+    }
+}
+
+// Trauma added for every gem popped in one batch, so bigger matches shake harder
+const TRAUMA_PER_GEM: f32 = 0.05;
+const TRAUMA_PER_SKILL: f32 = 0.4;
+const TRAUMA_PER_CRIT: f32 = 0.6;
+// Trauma lost per second
+const TRAUMA_DECAY: f32 = 1.5;
+const MAX_SHAKE_OFFSET: f32 = 0.15;
+const MAX_SHAKE_ROLL: f32 = 0.05;
+
+// Resource containing how hard the camera is shaking, from 0 at rest to 1
+#[derive(Default)]
+struct ScreenShake {
+    trauma: f32,
+}
+
+impl ScreenShake {
+    fn add(&mut self, trauma: f32) {
+        self.trauma = (self.trauma + trauma).min(1.0);
+    }
+}
+
+fn add_trauma(
+    mut shake: ResMut<ScreenShake>,
+    mut popped: EventReader<GemsPopped>,
+    mut skills: EventReader<Skill>,
+    mut crits: EventReader<CriticalHit>,
+) {
+    for popped in popped.iter() {
+        shake.add(TRAUMA_PER_GEM * popped.gems.len() as f32);
+    }
+    for _ in skills.iter() {
+        shake.add(TRAUMA_PER_SKILL);
+    }
+    for _ in crits.iter() {
+        shake.add(TRAUMA_PER_CRIT);
+    }
+}
+
+// Offsets the camera from its resting pose by noise scaled with the square of the trauma, putting it
+// back exactly at rest once the trauma has worn off
+fn shake_camera(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    mut shake: ResMut<ScreenShake>,
+    mut shaking: Local<bool>,
+    mut camera: Query<&mut Transform, (With<MainCamera>, Without<CameraIntro>)>,
+) {
+    if settings.reduced_motion {
+        shake.trauma = 0.0;
+    }
+    if shake.trauma <= 0.0 && !*shaking {
+        return;
+    }
+    let rest = camera_transform(settings.camera_view);
+    let strength = shake.trauma * shake.trauma;
+    let noise = || fastrand::f32().mul_add(2.0, -1.0) * strength;
+    for mut transform in camera.iter_mut() {
+        *transform = rest;
+        if strength > 0.0 {
+            transform.translation += rest.rotation
+                * Vec3::new(noise() * MAX_SHAKE_OFFSET, noise() * MAX_SHAKE_OFFSET, 0.0);
+            transform.rotation *= Quat::from_rotation_z(noise() * MAX_SHAKE_ROLL);
+        }
+    }
+    *shaking = shake.trauma > 0.0;
+    shake.trauma = (shake.trauma - TRAUMA_DECAY * time.delta_seconds()).max(0.0);
+}
+
+const CAMERA_INTRO_SECONDS: f32 = 0.5;
+
+// Present while the camera sweeps in at the start of a game, the board ignores clicks until it's gone
+#[derive(Component)]
+struct CameraIntro;
+
+// Moves the camera between two poses, keeping it on the shortest arc between their rotations
+struct CameraIntroLens {
+    start: Transform,
+    end: Transform,
+}
+
+impl Lens<Transform> for CameraIntroLens {
+    fn lerp(&mut self, target: &mut Transform, ratio: f32) {
+        target.translation = self.start.translation.lerp(self.end.translation, ratio);
+        target.rotation = self.start.rotation.slerp(self.end.rotation, ratio);
+    }
+}
+
+fn start_camera_intro(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    mut camera: Query<(Entity, &mut Transform), With<MainCamera>>,
+) {
+    let end = camera_transform(settings.camera_view);
+    for (entity, mut transform) in camera.iter_mut() {
+        if !settings.camera_intro || settings.reduced_motion {
+            *transform = end;
+            continue;
+        }
+        let start = Transform::from_xyz(0.0, -8.0, 6.0).looking_at(Vec3::ZERO, Vec3::Y);
+        *transform = start;
+        commands.entity(entity).insert_bundle((
+            CameraIntro,
+            Animator::new(Tween::new(
+                EaseFunction::QuadraticOut,
+                TweeningType::Once,
+                Duration::from_secs_f32(CAMERA_INTRO_SECONDS),
+                CameraIntroLens { start, end },
+            )),
+        ));
+    }
+}
+
+// Ends the intro once the sweep is done or the player skips it, snapping to the exact resting pose
+// so raycasting matches the board
+fn finish_camera_intro(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    mut camera: Query<(Entity, &mut Transform, &Animator<Transform>), With<CameraIntro>>,
+) {
+    let skipped = [KeyCode::Escape, KeyCode::Space, KeyCode::Return]
+        .iter()
+        .any(|key| keys.just_pressed(*key))
+        || mouse_buttons.just_pressed(MouseButton::Left);
+    for (entity, mut transform, animator) in camera.iter_mut() {
+        if skipped || approx_equal(animator.progress(), 1.0) {
+            *transform = camera_transform(settings.camera_view);
+            commands
+                .entity(entity)
+                .remove::<CameraIntro>()
+                .remove::<Animator<Transform>>();
+        }
+    }
+}
+
+// Resource set while a quit confirmation is up, so Escape closes it rather than toggling the pause
+#[derive(Default, Deref, DerefMut)]
+struct ConfirmingQuit(bool);
+
+// Asks `question` in a small window over everything else, giving the answer once the player picks
+// one. Escape answers no
+fn confirm_dialog(ctx: &egui::Context, keys: &Input<KeyCode>, question: &str) -> Option<bool> {
+    if keys.just_pressed(KeyCode::Escape) {
+        return Some(false);
+    }
+    let mut answer = None;
+    egui::Window::new("Confirm")
+        .title_bar(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.label(question);
+                ui.horizontal(|ui| {
+                    if ui.button("Yes").clicked() {
+                        answer = Some(true);
+                    }
+                    if ui.button("No").clicked() {
+                        answer = Some(false);
+                    }
+                });
+            });
+        });
+    answer
+}
+
+// What to do with an abandoned match on the way out
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum QuitAnswer {
+    Save,
+    Discard,
+    Cancel,
+}
+
+// Asks whether to save the match abandoned to the menu before quitting. Escape cancels
+fn quit_dialog(ctx: &egui::Context, keys: &Input<KeyCode>) -> Option<QuitAnswer> {
+    if keys.just_pressed(KeyCode::Escape) {
+        return Some(QuitAnswer::Cancel);
+    }
+    let mut answer = None;
+    egui::Window::new("Confirm")
+        .title_bar(false)
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .collapsible(false)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.vertical_centered(|ui| {
+                ui.label("The match you left hasn't been saved. Save it before quitting?");
+                ui.horizontal(|ui| {
+                    if ui.button("Save").clicked() {
+                        answer = Some(QuitAnswer::Save);
+                    }
+                    if ui.button("Discard").clicked() {
+                        answer = Some(QuitAnswer::Discard);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        answer = Some(QuitAnswer::Cancel);
+                    }
+                });
+            });
+        });
+    answer
 }
 
 fn main_menu(
+    mut commands: Commands,
     mut egui_ctx: ResMut<EguiContext>,
+    mut mode: ResMut<GameMode>,
+    mut board: ResMut<Board>,
+    stats: Res<Stats>,
     mut state: ResMut<State<GameState>>,
     mut events: EventWriter<AppExit>,
+    mut seed: ResMut<BoardSeed>,
+    mut seed_text: Local<String>,
+    mut limit: ResMut<MoveLimit>,
+    mut obstacles: ResMut<Obstacles>,
+    keys: Res<Input<KeyCode>>,
+    mut confirming: ResMut<ConfirmingQuit>,
+    mut abandoned: ResMut<Abandoned>,
+    mut config: ResMut<Match3Config>,
+    mut gravity: ResMut<GravityDirection>,
 ) {
     egui::CentralPanel::default().show(egui_ctx.ctx_mut(), |ui| {
+        ui.set_enabled(!**confirming);
         ui.set_min_width(200.0);
         ui.with_layout(
             egui::Layout::default().with_cross_align(egui::Align::Center),
             |ui| {
                 ui.heading(RichText::new("UNTITLED MATCH 3 RPG").font(FontId::monospace(100.0)));
+                ui.label(format!(
+                    "Wins: {}   Losses: {}   Best cascade: {}",
+                    stats.wins, stats.losses, stats.best_cascade
+                ));
                 if ui
-                    .button(RichText::new("Start").font(FontId::monospace(50.0)))
+                    .add_enabled(
+                        SavedGame::exists(),
+                        egui::Button::new(RichText::new("Continue").font(FontId::monospace(50.0))),
+                    )
                     .clicked()
                 {
+                    if let Some(saved) = SavedGame::load() {
+                        *mode = saved.mode();
+                        *board = saved.board();
+                        config.board_dimensions = saved.dimensions();
+                        *gravity = saved.gravity();
+                        commands.insert_resource(saved);
+                    } else {
+                        warn!("Couldn't continue the saved match, starting a fresh one");
+                        *mode = GameMode::Classic;
+                    }
                     state.set(GameState::Game).unwrap();
                 }
+                if ui
+                    .button(RichText::new("Start").font(FontId::monospace(50.0)))
+                    .clicked()
+                {
+                    *mode = GameMode::Classic;
+                    limit.enabled = false;
+                    state.set(GameState::BoardSetup).unwrap();
+                }
+                if ui
+                    .button(RichText::new("Limited").font(FontId::monospace(50.0)))
+                    .on_hover_text("A duel that ends after your last move, most health left wins")
+                    .clicked()
+                {
+                    *mode = GameMode::Classic;
+                    limit.enabled = true;
+                    state.set(GameState::BoardSetup).unwrap();
+                }
+                ui.add(
+                    egui::DragValue::new(&mut limit.moves)
+                        .clamp_range(1..=99)
+                        .suffix(" moves"),
+                );
+                if ui
+                    .button(RichText::new("Practice").font(FontId::monospace(50.0)))
+                    .clicked()
+                {
+                    *mode = GameMode::Practice;
+                    state.set(GameState::BoardSetup).unwrap();
+                }
+                if ui
+                    .button(RichText::new("Zen").font(FontId::monospace(50.0)))
+                    .clicked()
+                {
+                    *mode = GameMode::Zen;
+                    state.set(GameState::BoardSetup).unwrap();
+                }
+                if ui
+                    .button(RichText::new("Blitz").font(FontId::monospace(50.0)))
+                    .on_hover_text("Defeat the opponent before the clock runs out")
+                    .clicked()
+                {
+                    *mode = GameMode::Blitz;
+                    state.set(GameState::BoardSetup).unwrap();
+                }
+                if ui
+                    .button(RichText::new("Settings").font(FontId::monospace(50.0)))
+                    .clicked()
+                {
+                    state.set(GameState::Settings).unwrap();
+                }
                 if ui
                     .button(RichText::new("Exit").font(FontId::monospace(50.0)))
                     .clicked()
                 {
-                    events.send(AppExit);
+                    // Only a match left without saving is worth stopping for
+                    if abandoned.is_some() {
+                        **confirming = true;
+                    } else {
+                        events.send(AppExit);
+                    }
                 }
+                ui.collapsing("Debug", |ui| {
+                    seed_field(ui, &mut seed, &mut seed_text);
+                    ui.horizontal(|ui| {
+                        ui.label("Locked slots");
+                        ui.add(
+                            egui::DragValue::new(&mut obstacles.locked_slots)
+                                .clamp_range(0..=MAX_LOCKED_SLOTS),
+                        );
+                    });
+                    ui.add(
+                        egui::Slider::new(&mut obstacles.locked_chance, 0.0..=MAX_LOCKED_CHANCE)
+                            .text("Locked gem chance"),
+                    );
+                });
             },
         );
     });
+    if **confirming {
+        match quit_dialog(egui_ctx.ctx_mut(), &keys) {
+            Some(QuitAnswer::Save) => {
+                abandoned.save();
+                events.send(AppExit);
+            }
+            Some(QuitAnswer::Discard) => events.send(AppExit),
+            Some(QuitAnswer::Cancel) => **confirming = false,
+            None => {}
+        }
+    }
 }
 
+// Continued matches get their locked slots and gems back, new ones have some picked at random
 fn spawn_board(
     mut commands: Commands,
-    assets: Res<GemAssets>,
-    gltf_assets: Res<Assets<Gltf>>,
+    mut spawner: GemSpawner,
     board: Res<Board>,
+    seed: Res<MatchSeed>,
+    saved: Option<Res<SavedGame>>,
+) {
+    match saved {
+        Some(saved) => spawn_slots(
+            &mut commands,
+            &mut spawner,
+            &board,
+            &saved.locked_slots(),
+            Some(&saved.locked_gems()),
+        ),
+        None => {
+            let locked = seeded_locks(**seed, &board, spawner.obstacles.locked_slots);
+            spawn_slots(&mut commands, &mut spawner, &board, &locked, None);
+        }
+    }
+}
+
+// Spawns a slot holding a gem for every position on the board, starting from a clean selection.
+// `locked_gems` are the locked gems of a board being put back, a new board rolls for its own
+fn spawn_slots(
+    commands: &mut Commands,
+    spawner: &mut GemSpawner,
+    board: &Board,
+    locked: &[UVec2],
+    locked_gems: Option<&[(UVec2, CrackedState)]>,
 ) {
+    let mut index = SlotIndex::default();
     board.iter().for_each(|(pos, typ)| {
-        let translation = gem_pos_from(*pos);
+        let translation = gem_pos_from(*pos, spawner.config.board_dimensions, *spawner.gravity);
 
-        let gem = spawn_gem(
-            &mut commands,
-            translation,
-            (*typ as u8).into(),
-            &gltf_assets,
-            &assets,
-        );
+        let state = match locked_gems {
+            Some(locked_gems) => locked_gems
+                .iter()
+                .find(|(at, _)| at == pos)
+                .map(|(_, state)| *state),
+            None => spawner.roll_lock(),
+        };
+        let gem = spawn_gem(commands, translation, (*typ as u8).into(), state, spawner);
 
-        commands
-            .spawn_bundle(PbrBundle {
-                transform: Transform::from_translation(translation),
-                mesh: assets.cube.clone_weak(),
-                material: assets.transparent.clone_weak(),
-                ..default()
-            })
-            .insert_bundle((
-                GemSlot {
-                    pos: *pos,
-                    gem: Some(gem),
-                },
-                RayCastMesh::<RaycastSet>::default(),
-            ));
+        let mut slot = commands.spawn_bundle(PbrBundle {
+            transform: Transform::from_translation(translation),
+            mesh: spawner.assets.cube.clone_weak(),
+            material: spawner.assets.transparent.clone_weak(),
+            ..default()
+        });
+        slot.insert_bundle((
+            GemSlot {
+                pos: *pos,
+                gem: Some(gem),
+            },
+            RayCastMesh::<RaycastSet>::default(),
+        ));
+        if locked.contains(pos) {
+            slot.insert(Locked);
+        }
+        index.insert(*pos, slot.id());
     });
+    commands.insert_resource(index);
     commands.insert_resource(SelectedSlot(None));
+    commands.insert_resource(BoardTweens::default());
+}
+
+// Distance between the centres of neighbouring slots
+const GEM_SIZE: f32 = 0.2;
+
+// Places a board of any dimensions centred on the origin, turned so gems fall towards `gravity`
+fn gem_pos_from(pos: UVec2, dimensions: UVec2, gravity: GravityDirection) -> Vec3 {
+    let top = (GEM_SIZE * dimensions.y as f32 / 2.0) - (GEM_SIZE / 2.0);
+    let left = -(GEM_SIZE * dimensions.x as f32 / 2.0) + (GEM_SIZE / 2.0);
+    gravity.orient(Vec3::new(
+        left + pos.x as f32 * GEM_SIZE,
+        top - pos.y as f32 * GEM_SIZE,
+        0.0,
+    ))
+}
+
+// Resource containing the gems tweening as part of resolving the board, `gem_events` and `select`
+// wait for it to empty. Decorative animations like the selection wobble aren't tracked, so they
+// can never hold the board up
+#[derive(Default)]
+struct BoardTweens {
+    gems: HashSet<Entity>,
+    /// Whether `BoardSettled` has gone out since the board last moved
+    announced: bool,
+}
+
+impl BoardTweens {
+    fn start(
+        &mut self,
+        commands: &mut Commands,
+        gem: Entity,
+        tweenable: impl Tweenable<Transform> + Send + Sync + 'static,
+    ) {
+        commands.entity(gem).insert(Animator::new(tweenable));
+        self.gems.insert(gem);
+    }
+
+    fn settled(&self) -> bool {
+        self.gems.is_empty()
+    }
+
+    #[cfg(feature = "debug_overlay")]
+    fn in_flight(&self) -> usize {
+        self.gems.len()
+    }
 }
 
-fn gem_pos_from(pos: UVec2) -> Vec3 {
-    let size = 0.2;
-    let top = (size * 4.0) - (size / 2.0);
-    let left = -(size * 4.0) + (size / 2.0);
-    Vec3::new(left + pos.x as f32 * size, top - pos.y as f32 * size, 0.0)
+// Event sent once each time the board comes to rest with nothing left to resolve
+struct BoardSettled;
+
+// Lets go of gems whose tween has finished, and of gems that were despawned or lost their animator
+// mid-tween, so the board always settles eventually. A paused tween is held on to whatever the
+// pause menu says, as unpausing can reach here before `freeze_animations` starts it up again
+fn settle_board_tweens(
+    mut commands: Commands,
+    paused: Res<Paused>,
+    turn_state: Res<State<TurnState>>,
+    mut tweens: ResMut<BoardTweens>,
+    animators: Query<&Animator<Transform>>,
+    mut settled: EventWriter<BoardSettled>,
+) {
+    if **paused {
+        return;
+    }
+    tweens.gems.retain(|gem| match animators.get(*gem) {
+        Ok(animator) if !approx_equal(animator.progress(), 1.0) => true,
+        Ok(_) => {
+            commands.entity(*gem).remove::<Animator<Transform>>();
+            false
+        }
+        Err(_) => false,
+    });
+    if !tweens.settled() || turn_state.current() == &TurnState::Resolving {
+        tweens.announced = false;
+    } else if !tweens.announced {
+        tweens.announced = true;
+        settled.send(BoardSettled);
+    }
 }
 
 fn gem_events(
     mut commands: Commands,
-    mut events: ResMut<BoardEvents>,
-    mut board_commands: ResMut<BoardCommands>,
-    gltf_assets: Res<Assets<Gltf>>,
-    assets: Res<GemAssets>,
+    mut board: BoardIo,
+    mut spawner: GemSpawner,
+    mut rules: Rules,
     mut turn_state: ResMut<State<TurnState>>,
     mut turn: ResMut<Turn>,
-    mut end_of_sequence: Local<bool>,
-    mut change_turns_at_end_of_sequence: Local<bool>,
-    gems: Query<(&Transform, Option<&Animator<Transform>>, Entity, &GemType)>,
+    mut sequence: Local<Sequence>,
+    gems: Query<(
+        &Transform,
+        Entity,
+        &GemType,
+        Option<&CrackedState>,
+        Option<&PowerGem>,
+        Option<&BombGem>,
+        Option<&Wildcard>,
+    )>,
+    index: Res<SlotIndex>,
     mut slots: Query<(&Transform, &mut GemSlot)>,
-    mut player: Query<(Entity, &mut Resources), With<Player>>,
-    mut opponent: Query<(Entity, &mut Resources), Without<Player>>,
+    mut player: Query<(Entity, &mut Resources, &mut Health), With<Player>>,
+    mut opponent: Query<(Entity, &mut Resources, &mut Health), Without<Player>>,
+    mut tallies: Query<(&mut Score, &mut Collected)>,
+    mut feedback: BoardFeedback,
+    mut tweens: ResMut<BoardTweens>,
 ) {
+    if **rules.paused {
+        return;
+    }
     // Only read new events if we're done moving gems around
-    for (animator, entity) in gems
-        .iter()
-        .filter_map(|(_, animator, entity, _)| animator.map(|animator| (animator, entity)))
-    {
-        if approx_equal(animator.progress(), 1.0) {
-            commands.entity(entity).remove::<Animator<Transform>>();
+    if !tweens.settled() {
+        return;
+    }
+
+    // The last match has settled into place, draw each group of matched gems together and pop
+    // them once they arrive
+    if let Some(matched) = sequence.matched.take() {
+        let matched = matched
+            .into_iter()
+            .map(|pos| {
+                let (transform, slot) = get_slot_from_pos(pos, &index, &slots);
+                let gem = slot.gem.unwrap();
+                let typ = *gems.get_component::<GemType>(gem).unwrap();
+                (pos, typ, gem, transform.translation)
+            })
+            .collect::<Vec<_>>();
+        // Wildcards keep a type on the board for lack of a spare one, but only ever pop by being
+        // swapped, so lines are counted without them
+        let lines = lined_up(
+            &matched
+                .iter()
+                .filter(|(_, _, gem, _)| gems.get_component::<Wildcard>(*gem).is_err())
+                .map(|(pos, typ, ..)| (*pos, *typ))
+                .collect::<Vec<_>>(),
+        );
+        let matched = matched
+            .into_iter()
+            .filter(|(pos, ..)| lines.contains(pos))
+            .collect::<Vec<_>>();
+        let locked = matched
+            .iter()
+            .filter(|(_, _, gem, _)| {
+                gems.get_component::<CrackedState>(*gem)
+                    .is_ok_and(|state| *state == CrackedState::Intact)
+            })
+            .map(|(pos, ..)| *pos)
+            .collect::<Vec<_>>();
+        // Where the player swapped to, only the first match of a cascade has one
+        let swapped = sequence.swapped.take();
+        let mut powered = Vec::new();
+        let mut wildcards = Vec::new();
+        let mut bombs = Vec::new();
+
+        for group in match_groups(&matched.iter().map(|m| (m.0, m.1)).collect::<Vec<_>>()) {
+            let line = longest_line(&group);
+            if line >= EXTRA_TURN_LINE {
+                sequence.extra_turn = true;
+            }
+            // Five in a line leaves a wildcard behind, an L, T or cross a bomb and exactly four in
+            // a line a power gem, in place of one of the matched gems
+            let mut sorted = group.clone();
+            sorted.sort_by_key(|pos| (pos.y, pos.x));
+            let kept = sorted
+                .iter()
+                .copied()
+                .find(|pos| swapped.is_some_and(|(from, to)| pos == from || pos == to))
+                .unwrap_or(sorted[1]);
+            if line >= WILDCARD_LINE {
+                wildcards.push(kept);
+            } else if crosses(&group) {
+                bombs.push(kept);
+            } else if group.len() == 4 && line == 4 {
+                let orientation = if sorted[0].y == sorted[3].y {
+                    Orientation::Row
+                } else {
+                    Orientation::Column
+                };
+                powered.push((kept, orientation));
+            }
+            let members = matched
+                .iter()
+                .filter(|(pos, ..)| group.contains(pos))
+                .collect::<Vec<_>>();
+            if let Ok((mut score, _)) = tallies.get_mut(turn.0) {
+                **score +=
+                    rules
+                        .score
+                        .score(members.len() as u32, members[0].1, sequence.cascade_depth);
+            }
+            let centroid = members
+                .iter()
+                .fold(Vec3::ZERO, |sum, (.., translation)| sum + *translation)
+                / members.len() as f32;
+            for (pos, _, gem, translation) in members {
+                if locked.contains(pos)
+                    || wildcards.contains(pos)
+                    || bombs.contains(pos)
+                    || powered.iter().any(|(powered, _)| powered == pos)
+                {
+                    continue;
+                }
+                tweens.start(
+                    &mut commands,
+                    *gem,
+                    Tween::new(
+                        EaseFunction::QuadraticIn,
+                        TweeningType::Once,
+                        rules.settings.motion_duration(0.15),
+                        TransformPositionLens {
+                            start: *translation,
+                            end: translation.lerp(centroid, 0.3),
+                        },
+                    ),
+                );
+            }
+        }
+        sequence.cascade_depth += 1;
+
+        feedback.replay.record(CascadeStep {
+            gems: slots
+                .iter()
+                .filter_map(|(_, slot)| {
+                    let typ = gems.get_component::<GemType>(slot.gem?).ok()?;
+                    Some((slot.pos, *typ))
+                })
+                .collect(),
+            matched: matched.iter().map(|(pos, ..)| *pos).collect(),
+        });
+
+        // Locked gems only crack on their first match and stay on the board, so they don't give
+        // any mana until the match that actually pops them
+        for (pos, typ, gem, _) in &matched {
+            if locked.contains(pos) {
+                commands.entity(*gem).insert(CrackedState::Cracked);
+            }
+            if let Some((_, orientation)) = powered.iter().find(|(powered, _)| powered == pos) {
+                // Reinserting the type has `apply_material` light the gem up
+                commands.entity(*gem).insert_bundle((
+                    PowerGem {
+                        orientation: *orientation,
+                    },
+                    *typ,
+                ));
+            }
+            if wildcards.contains(pos) {
+                commands.entity(*gem).insert_bundle((Wildcard, *typ));
+            }
+            if bombs.contains(pos) {
+                commands.entity(*gem).insert_bundle((BombGem, *typ));
+            }
+        }
+        let kept = |pos: &UVec2| {
+            wildcards.contains(pos)
+                || bombs.contains(pos)
+                || powered.iter().any(|(powered, _)| powered == pos)
+        };
+        let mut to_pop = matched
+            .iter()
+            .map(|(pos, ..)| *pos)
+            .filter(|pos| !locked.contains(pos) && !kept(pos))
+            .collect::<Vec<_>>();
+        if to_pop.is_empty() {
+            // A match made only of locked gems would be left standing once they cracked, with
+            // nothing moving to match it again, so it counts as their second match straight away
+            to_pop = locked.iter().copied().filter(|pos| !kept(pos)).collect();
+        }
+        // Popping power gems clears their whole line and bombs the gems around them, which can
+        // set off more power gems and bombs
+        let mut i = 0;
+        while i < to_pop.len() {
+            let pos = to_pop[i];
+            i += 1;
+            let gem = index
+                .get(&pos)
+                .and_then(|slot| slots.get(*slot).ok())
+                .and_then(|(_, slot)| slot.gem);
+            if gem.is_some_and(|gem| gems.get_component::<BombGem>(gem).is_ok()) {
+                let mut area = index
+                    .keys()
+                    .copied()
+                    .filter(|other| {
+                        other.x.abs_diff(pos.x) <= BOMB_RADIUS
+                            && other.y.abs_diff(pos.y) <= BOMB_RADIUS
+                    })
+                    .filter(|other| !to_pop.contains(other))
+                    .collect::<Vec<_>>();
+                to_pop.append(&mut area);
+            }
+            let power_gem = gem.and_then(|gem| gems.get_component::<PowerGem>(gem).ok());
+            if let Some(power_gem) = power_gem {
+                let mut line = index
+                    .keys()
+                    .copied()
+                    .filter(|other| match power_gem.orientation {
+                        Orientation::Row => other.y == pos.y,
+                        Orientation::Column => other.x == pos.x,
+                    })
+                    .filter(|other| !to_pop.contains(other))
+                    .collect::<Vec<_>>();
+                to_pop.append(&mut line);
+            }
+        }
+        // Popping a gem frees the slots around it, and its own, from their locks
+        for slot in rules.locked.iter() {
+            if let Ok((_, locked)) = slots.get(slot) {
+                if to_pop
+                    .iter()
+                    .any(|pos| *pos == locked.pos || pos.cardinally_adjacent(&locked.pos))
+                {
+                    commands.entity(slot).remove::<Locked>();
+                }
+            }
+        }
+        if to_pop.is_empty() {
+            // Nothing pops so nothing spawns to end the sequence either
+            sequence.end_of_sequence = true;
         } else {
+            board.commands.push(BoardCommand::Pop(to_pop)).unwrap();
             return;
         }
     }
 
-    while let Ok(event) = events.pop() {
-        *end_of_sequence = false;
+    while let Ok(event) = board.events.pop() {
+        // Every board event means gems moved, so whatever moves were cached are stale now
+        feedback.valid_moves.invalidate();
+        sequence.queue.push_back(event);
+    }
+
+    while let Some(event) = sequence.queue.pop_front() {
+        sequence.end_of_sequence = false;
         match event {
             BoardEvent::Swapped(from, to) => {
                 info!("Swapped from {from} to {to}");
-                let from_gem = get_gem_from_pos(from, &slots);
-                let to_gem = get_gem_from_pos(to, &slots);
+                let from_gem = get_gem_from_pos(from, &index, &slots);
+                let to_gem = get_gem_from_pos(to, &index, &slots);
 
                 swap_gems_in_slots(
                     &GemSlot {
@@ -205,45 +1130,60 @@ fn gem_events(
                         pos: to,
                         gem: Some(to_gem),
                     },
+                    &index,
                     &mut slots,
                 );
 
                 let from_transform = gems.get_component::<Transform>(from_gem).unwrap();
                 let to_transform = gems.get_component::<Transform>(to_gem).unwrap();
-                commands.entity(from_gem).insert(Animator::new(Tween::new(
-                    EaseFunction::QuadraticInOut,
-                    TweeningType::Once,
-                    Duration::from_secs_f32(0.5),
-                    TransformPositionLens {
-                        start: from_transform.translation,
-                        end: to_transform.translation,
-                    },
-                )));
-                commands.entity(to_gem).insert(Animator::new(Tween::new(
-                    EaseFunction::QuadraticInOut,
-                    TweeningType::Once,
-                    Duration::from_secs_f32(0.5),
-                    TransformPositionLens {
-                        start: to_transform.translation,
-                        end: from_transform.translation,
-                    },
-                )));
-                *change_turns_at_end_of_sequence = true;
+                tweens.start(
+                    &mut commands,
+                    from_gem,
+                    Tween::new(
+                        EaseFunction::QuadraticInOut,
+                        TweeningType::Once,
+                        rules.settings.motion_duration(0.5),
+                        TransformPositionLens {
+                            start: from_transform.translation,
+                            end: to_transform.translation,
+                        },
+                    ),
+                );
+                tweens.start(
+                    &mut commands,
+                    to_gem,
+                    Tween::new(
+                        EaseFunction::QuadraticInOut,
+                        TweeningType::Once,
+                        rules.settings.motion_duration(0.5),
+                        TransformPositionLens {
+                            start: to_transform.translation,
+                            end: from_transform.translation,
+                        },
+                    ),
+                );
+                sequence.change_turns_at_end_of_sequence = true;
+                sequence.cascade_depth = 0;
+                sequence.extra_turn = false;
+                sequence.swapped = Some((from, to));
+                feedback.replay.start();
             }
             BoardEvent::FailedSwap(from, to) => {
                 info!("Failed to swap from {from} to {to}");
 
-                let from_gem = get_gem_from_pos(from, &slots);
-                let to_gem = get_gem_from_pos(to, &slots);
+                let from_gem = get_gem_from_pos(from, &index, &slots);
+                let to_gem = get_gem_from_pos(to, &index, &slots);
 
                 let from_transform = gems.get_component::<Transform>(from_gem).unwrap();
                 let to_transform = gems.get_component::<Transform>(to_gem).unwrap();
 
-                commands.entity(from_gem).insert(Animator::new(
+                tweens.start(
+                    &mut commands,
+                    from_gem,
                     Tween::new(
                         EaseFunction::QuadraticInOut,
                         TweeningType::Once,
-                        Duration::from_secs_f32(0.25),
+                        rules.settings.motion_duration(0.25),
                         TransformPositionLens {
                             start: from_transform.translation,
                             end: to_transform.translation,
@@ -252,18 +1192,20 @@ fn gem_events(
                     .then(Tween::new(
                         EaseFunction::QuadraticInOut,
                         TweeningType::Once,
-                        Duration::from_secs_f32(0.25),
+                        rules.settings.motion_duration(0.25),
                         TransformPositionLens {
                             start: to_transform.translation,
                             end: from_transform.translation,
                         },
                     )),
-                ));
-                commands.entity(to_gem).insert(Animator::new(
+                );
+                tweens.start(
+                    &mut commands,
+                    to_gem,
                     Tween::new(
                         EaseFunction::QuadraticInOut,
                         TweeningType::Once,
-                        Duration::from_secs_f32(0.25),
+                        rules.settings.motion_duration(0.25),
                         TransformPositionLens {
                             start: to_transform.translation,
                             end: from_transform.translation,
@@ -272,87 +1214,166 @@ fn gem_events(
                     .then(Tween::new(
                         EaseFunction::QuadraticInOut,
                         TweeningType::Once,
-                        Duration::from_secs_f32(0.25),
+                        rules.settings.motion_duration(0.25),
                         TransformPositionLens {
                             start: from_transform.translation,
                             end: to_transform.translation,
                         },
                     )),
-                ));
+                );
                 turn_state.set(TurnState::AwaitingMove).unwrap();
             }
             BoardEvent::Dropped(drops) => {
                 info!("Dropped {drops:?}");
-                for Drop { from, to } in drops.iter().copied() {
-                    let from_gem = get_gem_from_pos(from, &slots);
-                    let (to_transform, to_slot) = get_slot_from_pos(to, &slots);
-
-                    swap_gems_in_slots(
-                        &GemSlot {
-                            pos: from,
-                            gem: Some(from_gem),
-                        },
-                        &to_slot,
-                        &mut slots,
-                    );
+                // Drops come in no particular order and a gem can land where another one is about
+                // to leave, so every falling gem is picked up before any of them is put down
+                let falling = drops
+                    .iter()
+                    .map(|Drop { from, to }| (get_gem_from_pos(*from, &index, &slots), *from, *to))
+                    .collect::<Vec<_>>();
+                for (_, from, _) in &falling {
+                    let (_, mut slot) = slots.get_mut(index[from]).unwrap();
+                    slot.gem = None;
+                }
+                for (gem, _, to) in falling {
+                    let (to_transform, mut slot) = slots.get_mut(index[&to]).unwrap();
+                    slot.gem = Some(gem);
+                    let end = to_transform.translation;
 
-                    let from_transform = gems.get_component::<Transform>(from_gem).unwrap();
-                    commands.entity(from_gem).insert(Animator::new(Tween::new(
-                        EaseFunction::CubicIn,
-                        TweeningType::Once,
-                        Duration::from_secs_f32(0.25),
-                        TransformPositionLens {
-                            start: from_transform.translation,
-                            end: to_transform.translation,
-                        },
-                    )));
+                    let from_transform = gems.get_component::<Transform>(gem).unwrap();
+                    tweens.start(
+                        &mut commands,
+                        gem,
+                        Tween::new(
+                            EaseFunction::CubicIn,
+                            TweeningType::Once,
+                            rules.settings.motion_duration(0.25),
+                            TransformPositionLens {
+                                start: from_transform.translation,
+                                end,
+                            },
+                        ),
+                    );
                 }
             }
             BoardEvent::Popped(pop) => {
-                info!("Popped {pop}");
-                let mut current_resource = player
+                // Pops from one match arrive back to back, handle them all in a single pass
+                let mut pops = vec![pop];
+                while matches!(sequence.queue.front(), Some(BoardEvent::Popped(_))) {
+                    if let Some(BoardEvent::Popped(pop)) = sequence.queue.pop_front() {
+                        pops.push(pop);
+                    }
+                }
+                info!("Popped {pops:?}");
+                let mut current_resource = player
                     .get_mut(turn.0)
-                    .map(|(_, resources)| resources)
-                    .or_else(|_| opponent.get_mut(turn.0).map(|(_, resources)| resources))
+                    .map(|(_, resources, _)| resources)
+                    .or_else(|_| opponent.get_mut(turn.0).map(|(_, resources, _)| resources))
                     .unwrap();
-                let mut slot = slots.iter_mut().find(|slot| slot.1.pos == pop).unwrap().1;
-                let gem = slot.gem.unwrap();
-                let typ = gems.get_component::<GemType>(gem).unwrap();
-                current_resource.add(*typ);
-                commands.entity(gem).despawn_recursive();
-                slot.gem = None;
+                let (_, mut collected) = tallies.get_mut(turn.0).unwrap();
+                let mut damage = 0;
+                let mut popped = Vec::with_capacity(pops.len());
+                for pos in pops {
+                    let (_, mut slot) = slots.get_mut(index[&pos]).unwrap();
+                    let gem = slot.gem.unwrap();
+                    let typ = gems.get_component::<GemType>(gem).unwrap();
+                    let transform = gems.get_component::<Transform>(gem).unwrap();
+                    spawn_shatter(&mut commands, *transform, *typ, &spawner);
+                    popped.push((transform.translation, *typ));
+                    let mut ctx = PopContext {
+                        typ: *typ,
+                        rarity: rules.rarities.rarity(*typ),
+                        resources: &mut *current_resource,
+                        damage: 0,
+                    };
+                    rules.effects.apply(&mut ctx);
+                    damage += ctx.damage;
+                    *collected.entry(*typ).or_default() += 1;
+                    commands.entity(gem).despawn_recursive();
+                    slot.gem = None;
+                }
+                let crit_chance = rules
+                    .defenses
+                    .get(turn.0)
+                    .map_or(0.0, |(stats, _)| stats.crit_chance);
+                let (mut damage, critical) = rules.crits.roll(damage, crit_chance);
+                if let Some(target) = rules.foes.of(turn.0) {
+                    if let Ok((stats, status)) = rules.defenses.get(target) {
+                        damage = damage_after_defense(damage, stats.defense(status));
+                    }
+                    if let Ok((_, _, mut health)) =
+                        player.get_mut(target).or_else(|_| opponent.get_mut(target))
+                    {
+                        health.damage(damage);
+                    }
+                }
+                if critical {
+                    let skulls = popped
+                        .iter()
+                        .filter(|(_, typ)| *typ == GemType::Skull)
+                        .map(|(pos, _)| *pos)
+                        .collect::<Vec<_>>();
+                    let at = skulls.iter().sum::<Vec3>() / skulls.len().max(1) as f32;
+                    rules.crits.announce(at, damage);
+                }
+                feedback.popped.send(GemsPopped {
+                    side: turn.0,
+                    gems: popped,
+                    cascade_depth: sequence.cascade_depth,
+                    damage,
+                    critical,
+                });
             }
             BoardEvent::Spawned(spawns) => {
+                // bevy_match3 picks the refills without a seed, so they're picked again from the
+                // match's and written back over its own
+                let spawns = board.refill(spawns.iter().copied(), &mut spawner);
                 info!("Spawned {spawns:?}");
                 for (pos, typ) in spawns.iter().copied() {
                     let typ = GemType::from(typ as u8);
-                    let (transform, mut slot) =
-                        slots.iter_mut().find(|(_, slot)| slot.pos == pos).unwrap();
-                    let mut start_pos = transform.translation;
-                    // offset starting position by about a board length so they drop in from off screen
-                    start_pos.y += 0.2 * 8.0;
-                    let gem = spawn_gem(&mut commands, start_pos, typ, &gltf_assets, &assets);
-                    commands.entity(gem).insert(Animator::new(Tween::new(
-                        EaseMethod::Linear,
-                        TweeningType::Once,
-                        Duration::from_secs_f32(0.25),
-                        TransformPositionLens {
-                            start: start_pos,
-                            end: transform.translation,
-                        },
-                    )));
+                    let (transform, mut slot) = slots.get_mut(index[&pos]).unwrap();
+                    let start_pos = transform.translation
+                        + spawner
+                            .gravity
+                            .spawn_offset(spawner.config.board_dimensions);
+                    let state = spawner.roll_lock();
+                    let gem = spawn_gem(&mut commands, start_pos, typ, state, &spawner);
+                    tweens.start(
+                        &mut commands,
+                        gem,
+                        Tween::new(
+                            EaseMethod::Linear,
+                            TweeningType::Once,
+                            rules.settings.motion_duration(0.25),
+                            TransformPositionLens {
+                                start: start_pos,
+                                end: transform.translation,
+                            },
+                        ),
+                    );
 
                     slot.gem = Some(gem);
                 }
-                *end_of_sequence = true;
+                // Any match bevy_match3 found among its own refills is gone, the lines the board
+                // actually holds now are matched instead
+                sequence
+                    .queue
+                    .retain(|event| !matches!(event, BoardEvent::Matched(_)));
+                let lined = lines_of_three(&board.board);
+                if lined.is_empty() {
+                    sequence.end_of_sequence = true;
+                } else {
+                    info!("Matched {lined:?}");
+                    sequence.matched = Some(lined);
+                    break;
+                }
             }
             BoardEvent::Matched(matches) => {
                 info!("Matched {:?}", matches.without_duplicates());
-                board_commands
-                    .push(BoardCommand::Pop(
-                        matches.without_duplicates().iter().copied().collect(),
-                    ))
-                    .unwrap();
+                // Let any drops and spawns finish before the matched gems converge and pop,
+                // the rest of the queue is handled after that
+                sequence.matched = Some(matches.without_duplicates().iter().copied().collect());
+                break;
             }
             BoardEvent::Shuffled(moves) => {
                 let mut old_slots = HashMap::new();
@@ -369,127 +1390,788 @@ fn gem_events(
                         let from_transform = gems.get_component::<Transform>(from_gem).unwrap();
                         let to_transform = gems.get_component::<Transform>(to_gem).unwrap();
 
-                        commands.entity(from_gem).insert(Animator::new(Tween::new(
-                            EaseFunction::QuadraticInOut,
-                            TweeningType::Once,
-                            Duration::from_secs_f32(0.5),
-                            TransformPositionLens {
-                                start: from_transform.translation,
-                                end: to_transform.translation,
-                            },
-                        )));
+                        tweens.start(
+                            &mut commands,
+                            from_gem,
+                            Tween::new(
+                                EaseFunction::QuadraticInOut,
+                                TweeningType::Once,
+                                rules.settings.motion_duration(0.5),
+                                TransformPositionLens {
+                                    start: from_transform.translation,
+                                    end: to_transform.translation,
+                                },
+                            ),
+                        );
                     }
                 }
                 for (_, mut slot) in slots.iter_mut() {
                     let new_gem = new_slots.get(&slot.pos).copied().flatten();
                     slot.gem = new_gem;
                 }
-                *end_of_sequence = true;
+                sequence.end_of_sequence = true;
             }
         }
     }
 
-    if *end_of_sequence {
+    if sequence.end_of_sequence {
         turn_state.set(TurnState::AwaitingMove).unwrap();
-        *end_of_sequence = false;
-        if *change_turns_at_end_of_sequence {
-            *change_turns_at_end_of_sequence = false;
-
-            let (player, _) = player.single();
-            let (opponent, _) = opponent.single();
-            if **turn == player {
-                **turn = opponent;
-            } else {
-                **turn = player;
+        sequence.end_of_sequence = false;
+        if sequence.change_turns_at_end_of_sequence {
+            sequence.change_turns_at_end_of_sequence = false;
+            feedback.replay.finish(&rules.settings);
+            feedback.resolved.send(MoveResolved(**turn));
+
+            // Only a real opponent ever gets a turn
+            let extra_turn = std::mem::take(&mut sequence.extra_turn);
+            if extra_turn {
+                info!("Extra turn");
+            }
+            if *rules.mode == GameMode::Classic && !extra_turn {
+                // Fallen opponents are skipped over
+                let next = rules.foes.next_turn(**turn, |side| {
+                    player
+                        .get(side)
+                        .or_else(|_| opponent.get(side))
+                        .is_ok_and(|(_, _, health)| health.current > 0)
+                });
+                if let Some(next) = next {
+                    **turn = next;
+                }
+            }
+        }
+    }
+}
+
+// Logs any slot whose gem disagrees with the logical board, run whenever the board has settled
+fn check_board_mirror(
+    mut settled: EventReader<BoardSettled>,
+    board: Res<Board>,
+    index: Option<Res<SlotIndex>>,
+    slots: Query<&GemSlot>,
+    gems: Query<&GemType>,
+) {
+    if settled.iter().count() == 0 {
+        return;
+    }
+    let index = match index {
+        Some(index) => index,
+        None => return,
+    };
+    for (pos, typ) in board.iter() {
+        let gem = index
+            .get(pos)
+            .and_then(|slot| slots.get(*slot).ok())
+            .and_then(|slot| slot.gem)
+            .and_then(|gem| gems.get(gem).ok());
+        match gem {
+            Some(gem) if *gem as u8 == *typ as u8 => {}
+            Some(gem) => error!("Slot {pos} shows a {gem} but the board holds type {typ}"),
+            None => error!("Slot {pos} is empty but the board holds type {typ}"),
+        }
+    }
+}
+
+// The rules deciding what matching and popping gems does, and whether it happens at all right now
+#[derive(SystemParam)]
+struct Rules<'w, 's> {
+    mode: Res<'w, GameMode>,
+    score: Res<'w, ActiveScoreRule>,
+    rarities: Res<'w, RarityTable>,
+    effects: Res<'w, GemEffects>,
+    settings: Res<'w, Settings>,
+    paused: Res<'w, Paused>,
+    locked: Query<'w, 's, Entity, With<Locked>>,
+    foes: Foes<'w, 's>,
+    defenses: Query<'w, 's, (&'static CombatStats, &'static StatusEffects)>,
+    crits: Crits<'w, 's>,
+}
+
+// The board `gem_events` plays out, with what just happened on it and what it's told to do next
+#[derive(SystemParam)]
+struct BoardIo<'w, 's> {
+    board: ResMut<'w, Board>,
+    events: ResMut<'w, BoardEvents>,
+    commands: ResMut<'w, BoardCommands>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+impl BoardIo<'_, '_> {
+    /// Picks the gems bevy_match3 just spawned again from the spawner's seeded numbers, writing
+    /// them over its own picks on the board
+    fn refill(
+        &mut self,
+        spawns: impl IntoIterator<Item = (UVec2, u32)>,
+        spawner: &mut GemSpawner,
+    ) -> Vec<(UVec2, u32)> {
+        let mut positions = spawns.into_iter().map(|(pos, _)| pos).collect::<Vec<_>>();
+        // The spawns don't come in any order, so they're put in one before drawing
+        positions.sort_by_key(|pos| (pos.y, pos.x));
+        let mut rows = board_rows(&self.board);
+        let spawns = positions
+            .into_iter()
+            .map(|pos| {
+                let typ = spawner.rng.gem_type(spawner.config.gem_types);
+                rows[pos.y as usize][pos.x as usize] = typ;
+                (pos, typ)
+            })
+            .collect();
+        *self.board = Board::from(rows);
+        spawns
+    }
+}
+
+// Where `gem_events` tells everything outside of the board what just happened on it
+#[derive(SystemParam)]
+struct BoardFeedback<'w, 's> {
+    replay: ResMut<'w, ChainReplay>,
+    valid_moves: ResMut<'w, ValidMoves>,
+    resolved: EventWriter<'w, 's, MoveResolved>,
+    popped: EventWriter<'w, 's, GemsPopped>,
+}
+
+// Event sent for every batch of gems popped together
+struct GemsPopped {
+    /// The side whose turn it is, and who gets the mana
+    side: Entity,
+    /// Where each popped gem was and what it was
+    gems: Vec<(Vec3, GemType)>,
+    cascade_depth: u32,
+    /// Damage dealt to the side whose turn it isn't
+    damage: u32,
+    critical: bool,
+}
+
+// Event sent for every hit that lands as a critical hit
+struct CriticalHit {
+    /// Where on the board to show it
+    at: Vec3,
+    damage: u32,
+}
+
+/// How much a critical hit multiplies damage by, before defense
+const CRIT_MULTIPLIER: u32 = 2;
+
+// Rolls for critical hits and announces the ones that land
+#[derive(SystemParam)]
+struct Crits<'w, 's> {
+    rng: ResMut<'w, CombatRng>,
+    hits: EventWriter<'w, 's, CriticalHit>,
+}
+
+impl Crits<'_, '_> {
+    /// `damage` from an attacker with `chance` to crit, multiplied if the roll lands, and whether
+    /// it did. Nothing to hit with means nothing to roll
+    fn roll(&mut self, damage: u32, chance: f32) -> (u32, bool) {
+        if damage > 0 && self.rng.roll(chance) {
+            (damage * CRIT_MULTIPLIER, true)
+        } else {
+            (damage, false)
+        }
+    }
+
+    fn announce(&mut self, at: Vec3, damage: u32) {
+        self.hits.send(CriticalHit { at, damage });
+    }
+}
+
+// State carried between runs of `gem_events` while a sequence of board events resolves
+#[derive(Default)]
+struct Sequence {
+    end_of_sequence: bool,
+    change_turns_at_end_of_sequence: bool,
+    // How many matches deep the current cascade is, used for scoring
+    cascade_depth: u32,
+    // Events read from the board that haven't been handled yet
+    queue: VecDeque<BoardEvent>,
+    // Positions of the last match, popped once its gems have finished moving
+    matched: Option<Vec<UVec2>>,
+    // Whether a line of four or more was matched since the last swap, keeping the turn
+    extra_turn: bool,
+    // The positions of the swap that started the sequence, until its first match is handled
+    swapped: Option<(UVec2, UVec2)>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Orientation {
+    Row,
+    Column,
+}
+
+const WILDCARD_LINE: u32 = 5;
+
+/// Left behind by matching five in a line, swapping it with a neighbour pops every gem of the
+/// neighbour's type instead
+#[derive(Component)]
+struct Wildcard;
+
+// Owned material of a wildcard, cycled through the colours by `rainbow`
+#[derive(Component)]
+struct Rainbow(Handle<StandardMaterial>);
+
+const RAINBOW_SPEED: f32 = 120.0;
+
+fn rainbow(
+    time: Res<Time>,
+    book: Res<SkillBook>,
+    targeting: Res<Targeting>,
+    mut mats: ResMut<Assets<StandardMaterial>>,
+    gems: Query<(&GemType, &Rainbow)>,
+) {
+    let hue = (time.seconds_since_startup() as f32 * RAINBOW_SPEED) % 360.0;
+    let candidates = targeting.map_or(&[][..], |skill| book.target_types(skill));
+    for (typ, rainbow) in gems.iter() {
+        if let Some(mat) = mats.get_mut(&rainbow.0) {
+            mat.base_color = Color::hsl(hue, 1.0, 0.6);
+            // Held at the target glow by `highlight_targets` while its type can be picked
+            if !candidates.contains(typ) {
+                mat.emissive = Color::hsl(hue, 1.0, 0.3);
+            }
+        }
+    }
+}
+
+/// Left behind by matching exactly four in a line, clears its whole row or column when popped
+#[derive(Component, Clone, Copy)]
+struct PowerGem {
+    orientation: Orientation,
+}
+
+/// Left behind by an L, T or cross shaped match, pops every gem around it when popped
+#[derive(Component)]
+struct BombGem;
+
+/// How many slots out from a bomb it pops, one for a 3x3 area
+const BOMB_RADIUS: u32 = 1;
+const BOMB_BRIGHTNESS: f32 = 0.4;
+
+const EXTRA_TURN_LINE: u32 = 4;
+
+// Length of the longest straight row or column within a group of positions
+fn longest_line(group: &[UVec2]) -> u32 {
+    longest_run(group, UVec2::X).max(longest_run(group, UVec2::Y))
+}
+
+// Length of the longest straight run going `step` at a time within a group of positions
+fn longest_run(group: &[UVec2], step: UVec2) -> u32 {
+    group
+        .iter()
+        .map(|start| {
+            let mut length = 1;
+            while group.contains(&(*start + step * length)) {
+                length += 1;
+            }
+            length
+        })
+        .max()
+        .unwrap_or_default()
+}
+
+// Whether a group holds both a row and a column of three, making an L, T or cross
+fn crosses(group: &[UVec2]) -> bool {
+    longest_run(group, UVec2::X) >= 3 && longest_run(group, UVec2::Y) >= 3
+}
+
+// Matched gems still in a row or column of three or more of their type among the others
+fn lined_up(matched: &[(UVec2, GemType)]) -> Vec<UVec2> {
+    let run = |pos: UVec2, typ: GemType, step: UVec2| {
+        let mut start = pos;
+        while start.x >= step.x && start.y >= step.y && matched.contains(&(start - step, typ)) {
+            start -= step;
+        }
+        let mut length = 0;
+        while matched.contains(&(start + step * length, typ)) {
+            length += 1;
+        }
+        length
+    };
+    matched
+        .iter()
+        .filter(|(pos, typ)| run(*pos, *typ, UVec2::X) >= 3 || run(*pos, *typ, UVec2::Y) >= 3)
+        .map(|(pos, _)| *pos)
+        .collect()
+}
+
+// Splits matched gems into groups of touching gems of the same type
+fn match_groups(matched: &[(UVec2, GemType)]) -> Vec<Vec<UVec2>> {
+    let mut groups: Vec<Vec<UVec2>> = Vec::new();
+    let mut remaining = matched.to_vec();
+    while let Some(first) = remaining.pop() {
+        let mut group = vec![first];
+        let mut i = 0;
+        while i < group.len() {
+            let (pos, typ) = group[i];
+            let (neighbours, rest): (Vec<_>, Vec<_>) =
+                remaining.into_iter().partition(|(other, other_typ)| {
+                    *other_typ == typ && pos.cardinally_adjacent(other)
+                });
+            group.extend(neighbours);
+            remaining = rest;
+            i += 1;
+        }
+        groups.push(group.into_iter().map(|(pos, _)| pos).collect());
+    }
+    groups
+}
+
+// Gem types of the board by row, top to bottom
+fn board_rows(board: &Board) -> Vec<Vec<u32>> {
+    let height = board.iter().map(|(pos, _)| pos.y + 1).max().unwrap_or(0);
+    let width = board.iter().map(|(pos, _)| pos.x + 1).max().unwrap_or(0);
+    let mut rows = vec![vec![0; width as usize]; height as usize];
+    for (pos, typ) in board.iter() {
+        rows[pos.y as usize][pos.x as usize] = *typ;
+    }
+    rows
+}
+
+// Every position in a straight line of three or more gems of the same type, for when the board is
+// changed behind the back of its own match detection
+fn lines_of_three(board: &Board) -> Vec<UVec2> {
+    lines_in(&board_types(board))
+}
+
+fn board_types(board: &Board) -> HashMap<UVec2, u32> {
+    board.iter().map(|(pos, typ)| (*pos, *typ)).collect()
+}
+
+/// The gems swapping `from` and `to` would line up, empty if the swap makes no match. Only looks
+/// at the board, so the gems spawned afterwards and any cascade they start aren't included
+fn swap_matches(board: &Board, from: UVec2, to: UVec2) -> Vec<UVec2> {
+    let mut types = board_types(board);
+    match (types.get(&from).copied(), types.get(&to).copied()) {
+        (Some(from_type), Some(to_type)) => {
+            types.insert(from, to_type);
+            types.insert(to, from_type);
+        }
+        _ => return Vec::new(),
+    }
+    lines_in(&types)
+}
+
+fn lines_in(types: &HashMap<UVec2, u32>) -> Vec<UVec2> {
+    let mut lined = HashSet::default();
+    for (start, typ) in types {
+        for step in [UVec2::X, UVec2::Y] {
+            let line = (0..)
+                .map(|i| *start + step * i)
+                .take_while(|pos| types.get(pos) == Some(typ))
+                .collect::<Vec<_>>();
+            if line.len() >= 3 {
+                lined.extend(line);
             }
         }
     }
+    lined.into_iter().collect()
 }
 
 fn swap_gems_in_slots(
     slot1: &GemSlot,
     slot2: &GemSlot,
+    index: &SlotIndex,
     slots: &mut Query<(&Transform, &mut GemSlot)>,
 ) {
-    slots.for_each_mut(|(_, mut slot)| {
-        if slot.pos == slot1.pos {
-            slot.gem = slot2.gem;
-        } else if slot.pos == slot2.pos {
-            slot.gem = slot1.gem;
-        }
-    });
+    for (target, gem) in [(slot1.pos, slot2.gem), (slot2.pos, slot1.gem)] {
+        let (_, mut slot) = slots.get_mut(index[&target]).unwrap();
+        slot.gem = gem;
+    }
 }
 
-fn get_gem_from_pos(pos: UVec2, slots: &Query<(&Transform, &mut GemSlot)>) -> Entity {
-    get_slot_from_pos(pos, slots).1.gem.unwrap()
+fn get_gem_from_pos(
+    pos: UVec2,
+    index: &SlotIndex,
+    slots: &Query<(&Transform, &mut GemSlot)>,
+) -> Entity {
+    get_slot_from_pos(pos, index, slots).1.gem.unwrap()
 }
 
 fn get_slot_from_pos(
     pos: UVec2,
+    index: &SlotIndex,
     slots: &Query<(&Transform, &mut GemSlot)>,
 ) -> (Transform, GemSlot) {
-    slots
-        .iter()
-        .find(|(_, slot)| slot.pos == pos)
-        .map(|(t, s)| (*t, *s))
-        .unwrap()
+    slots.get(index[&pos]).map(|(t, s)| (*t, *s)).unwrap()
+}
+
+// Everything needed to spawn the mesh of a gem
+#[derive(SystemParam)]
+struct GemSpawner<'w, 's> {
+    gltf_assets: Res<'w, Assets<Gltf>>,
+    assets: Res<'w, GemAssets>,
+    obstacles: Res<'w, Obstacles>,
+    config: Res<'w, Match3Config>,
+    gravity: Res<'w, GravityDirection>,
+    rng: ResMut<'w, SpawnRng>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
 }
 
+impl GemSpawner<'_, '_> {
+    /// The state of a gem about to be spawned, locked at the obstacles' chance
+    fn roll_lock(&mut self) -> Option<CrackedState> {
+        self.rng
+            .roll(self.obstacles.locked_chance)
+            .then_some(CrackedState::Intact)
+    }
+}
+
+// Spawns a gem, locked or cracked as `state` says
 fn spawn_gem(
     commands: &mut Commands,
     pos: Vec3,
     typ: GemType,
-    gltf_assets: &Res<Assets<Gltf>>,
-    assets: &Res<GemAssets>,
+    state: Option<CrackedState>,
+    spawner: &GemSpawner,
 ) -> Entity {
-    commands
+    let mut gem = commands.spawn_bundle((
+        Transform::from_translation(pos),
+        GlobalTransform::default(),
+        typ,
+    ));
+    let cracked = state == Some(CrackedState::Cracked);
+    gem.with_children(|parent| spawn_gem_mesh(parent, typ, cracked, spawner));
+    if let Some(state) = state {
+        gem.insert(state);
+    }
+    gem.id()
+}
+
+fn spawn_gem_mesh(parent: &mut ChildBuilder, typ: GemType, cracked: bool, spawner: &GemSpawner) {
+    // The orientation offset lives on its own child so animations on the gem itself
+    // (swaps, selection wobble) never overwrite it
+    let shape = spawner.assets.shape(typ);
+    parent
         .spawn_bundle((
-            Transform::from_translation(pos),
+            Transform::from_rotation(shape.orientation()),
             GlobalTransform::default(),
-            typ,
         ))
         .with_children(|parent| {
-            parent.spawn_scene(
-                gltf_assets
-                    .get(assets.meshes.get(&typ.into()).unwrap())
-                    .unwrap()
-                    .scenes[0]
-                    .clone(),
-            );
-        })
-        .id()
+            let meshes = if cracked {
+                &spawner.assets.shatter_meshes
+            } else {
+                &spawner.assets.meshes
+            };
+            // A mesh that hasn't loaded leaves the gem invisible rather than taking the game down,
+            // which also lets the board run without any meshes at all
+            match meshes
+                .get(&shape)
+                .and_then(|mesh| spawner.gltf_assets.get(mesh))
+            {
+                Some(gltf) => {
+                    parent.spawn_scene(gltf.scenes[0].clone());
+                }
+                None => warn!("The {shape} mesh isn't loaded, spawning the gem without it"),
+            }
+        });
+}
+
+// Swaps the meshes of gems already on the board when the mesh quality changes
+fn respawn_gem_meshes(
+    mut commands: Commands,
+    mut current_quality: Local<Option<MeshQuality>>,
+    settings: Res<Settings>,
+    spawner: GemSpawner,
+    gems: Query<(Entity, &GemType, &Children, Option<&CrackedState>)>,
+) {
+    let previous_quality = current_quality.replace(settings.mesh_quality);
+    if previous_quality.map_or(true, |quality| quality == settings.mesh_quality) {
+        return;
+    }
+    for (gem, typ, children, state) in gems.iter() {
+        for child in children.iter() {
+            commands.entity(*child).despawn_recursive();
+        }
+        let cracked = state == Some(&CrackedState::Cracked);
+        commands
+            .entity(gem)
+            .with_children(|parent| spawn_gem_mesh(parent, *typ, cracked, &spawner))
+            // Reinserting the type has `apply_material` color the new mesh
+            .insert(*typ);
+    }
 }
 
 fn apply_material(
+    mut commands: Commands,
+    settings: Res<Settings>,
     assets: Res<GemAssets>,
-    gems: Query<(&GemType, &Children), Added<GemType>>,
-    mut children_query: Query<
-        (Option<&mut Handle<StandardMaterial>>, Option<&Children>),
-        With<Parent>,
+    rarities: Res<RarityTable>,
+    mut mats: ResMut<Assets<StandardMaterial>>,
+    gems: Query<
+        (
+            Entity,
+            &GemType,
+            &Children,
+            Option<&CrackedState>,
+            Option<&PowerGem>,
+            Option<&Wildcard>,
+            Option<&BombGem>,
+        ),
+        Changed<GemType>,
     >,
-    mut to_check: Local<Vec<Entity>>,
+    mut children_query: MeshChildren,
 ) {
-    for (typ, children) in gems.iter() {
-        to_check.extend(children.iter().copied());
-        while let Some(child) = to_check.pop() {
-            if let Ok((material, children)) = children_query.get_mut(child) {
-                if let Some(mut mat) = material {
-                    *mat = assets.materials[*typ as usize].clone_weak();
-                }
-                to_check.extend(children.iter().flat_map(|children| children.iter()));
+    for (entity, typ, children, state, power_gem, wildcard, bomb) in gems.iter() {
+        let rare = rarities.rarity(*typ) == Rarity::Rare;
+        let locked = state == Some(&CrackedState::Intact);
+        let special = power_gem.is_some() || wildcard.is_some() || bomb.is_some();
+        let material = if rare || locked || special {
+            // Special gems get their own material so their shimmer, tint or glow doesn't reach every
+            // gem of the type
+            let color = if locked {
+                assets.color(*typ, settings.palette) * LOCKED_BRIGHTNESS
+            } else if bomb.is_some() {
+                assets.color(*typ, settings.palette) * BOMB_BRIGHTNESS
+            } else {
+                assets.color(*typ, settings.palette)
+            };
+            let emissive = if power_gem.is_some() {
+                assets.color(*typ, settings.palette) * POWER_GEM_GLOW
+            } else {
+                Color::BLACK
+            };
+            let mut material = StandardMaterial {
+                base_color: color,
+                emissive,
+                ..default()
+            };
+            if bomb.is_some() {
+                // Dark polished metal, set apart from the dull tint of locked gems
+                material.metallic = 1.0;
+                material.perceptual_roughness = 0.2;
+            }
+            let material = mats.add(material);
+            commands.entity(entity).insert(GemMaterial {
+                handle: material.clone(),
+                emissive,
+            });
+            if wildcard.is_some() {
+                commands.entity(entity).insert(Rainbow(material.clone()));
+            } else if rare {
+                commands.entity(entity).insert(Shimmer(material.clone()));
+            }
+            material
+        } else {
+            let material = assets.materials[*typ as usize].clone_weak();
+            commands.entity(entity).insert(GemMaterial {
+                handle: material.clone_weak(),
+                emissive: Color::BLACK,
+            });
+            material
+        };
+        paint(children, &material, &mut children_query);
+    }
+}
+
+// The material a gem is painted with, shared with its whole type unless the gem is special, and
+// the glow it has when nothing is lighting it up
+#[derive(Component)]
+struct GemMaterial {
+    handle: Handle<StandardMaterial>,
+    /// Emissive to go back to once the gem stops being a skill target
+    emissive: Color,
+}
+
+type MeshChildren<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Option<&'static mut Handle<StandardMaterial>>,
+        Option<&'static Children>,
+    ),
+    With<Parent>,
+>;
+
+// Sets the material of every mesh below an entity, returning whether there were any. glTF scenes
+// nest their meshes a few levels down and only show up a frame after being spawned
+fn paint(
+    children: &Children,
+    material: &Handle<StandardMaterial>,
+    children_query: &mut MeshChildren,
+) -> bool {
+    let mut painted = false;
+    let mut to_check = children.to_vec();
+    while let Some(child) = to_check.pop() {
+        if let Ok((material_handle, children)) = children_query.get_mut(child) {
+            if let Some(mut mat) = material_handle {
+                *mat = material.clone();
+                painted = true;
+            }
+            to_check.extend(children.iter().flat_map(|children| children.iter()));
+        }
+    }
+    painted
+}
+
+const SHATTER_SECONDS: f32 = 0.4;
+
+// The shattered remains of a popped gem, despawned once its timer runs out
+#[derive(Component)]
+struct Shatter {
+    typ: GemType,
+    timer: Timer,
+    painted: bool,
+}
+
+fn spawn_shatter(
+    commands: &mut Commands,
+    transform: Transform,
+    typ: GemType,
+    spawner: &GemSpawner,
+) {
+    commands
+        .spawn_bundle((
+            transform,
+            GlobalTransform::default(),
+            Shatter {
+                typ,
+                timer: Timer::from_seconds(SHATTER_SECONDS, false),
+                painted: false,
+            },
+            Animator::new(Tween::new(
+                EaseFunction::QuadraticIn,
+                TweeningType::Once,
+                Duration::from_secs_f32(SHATTER_SECONDS),
+                TransformScaleLens {
+                    start: transform.scale,
+                    end: Vec3::ZERO,
+                },
+            )),
+        ))
+        .with_children(|parent| spawn_gem_mesh(parent, typ, true, spawner));
+}
+
+fn shatter(
+    mut commands: Commands,
+    time: Res<Time>,
+    assets: Res<GemAssets>,
+    mut shatters: Query<(Entity, &mut Shatter, Option<&Children>)>,
+    mut children_query: MeshChildren,
+) {
+    for (entity, mut shatter, children) in shatters.iter_mut() {
+        if shatter.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        if !shatter.painted {
+            if let Some(children) = children {
+                shatter.painted = paint(
+                    children,
+                    &assets.materials[shatter.typ as usize],
+                    &mut children_query,
+                );
             }
         }
     }
 }
 
+const LOCKED_BRIGHTNESS: f32 = 0.35;
+const POWER_GEM_GLOW: f32 = 0.5;
+
+/// Obstacles put on the board, none outside objective modes unless set up from the main menu
+#[derive(Default)]
+struct Obstacles {
+    /// Chance of a newly spawned gem being locked
+    locked_chance: f32,
+    /// How many slots a new board starts with locked
+    locked_slots: u32,
+}
+
+/// Most slots the main menu lets a new board start with locked
+const MAX_LOCKED_SLOTS: u32 = 16;
+
+/// Highest chance of a spawned gem being locked the main menu offers
+const MAX_LOCKED_CHANCE: f32 = 0.5;
+
+/// A slot whose gem can't be swapped until a gem next to it pops
+#[derive(Component)]
+struct Locked;
+
+/// A locked gem that takes two matches to clear, the first one only cracks it
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+enum CrackedState {
+    Intact,
+    Cracked,
+}
+
+// Swaps a freshly cracked gem over to its shattered mesh
+fn crack_gems(
+    mut commands: Commands,
+    spawner: GemSpawner,
+    gems: Query<(Entity, &GemType, &Children, &CrackedState), Changed<CrackedState>>,
+) {
+    for (gem, typ, children, state) in gems.iter() {
+        if *state != CrackedState::Cracked {
+            continue;
+        }
+        for child in children.iter() {
+            commands.entity(*child).despawn_recursive();
+        }
+        commands
+            .entity(gem)
+            .with_children(|parent| spawn_gem_mesh(parent, *typ, true, &spawner))
+            // Reinserting the type has `apply_material` drop the locked tint
+            .insert(*typ);
+    }
+}
+
+const SHIMMER_SPEED: f32 = 3.0;
+const SHIMMER_STRENGTH: f32 = 0.6;
+
+// Owned material of a rare gem, pulsed by `shimmer`
+#[derive(Component)]
+struct Shimmer(Handle<StandardMaterial>);
+
+fn shimmer(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    book: Res<SkillBook>,
+    targeting: Res<Targeting>,
+    assets: Res<GemAssets>,
+    mut mats: ResMut<Assets<StandardMaterial>>,
+    gems: Query<(&GemType, &Shimmer)>,
+) {
+    let intensity = (time.seconds_since_startup() as f32 * SHIMMER_SPEED).sin() * 0.5 + 0.5;
+    let candidates = targeting.map_or(&[][..], |skill| book.target_types(skill));
+    for (typ, shimmer) in gems.iter() {
+        // Held at the target glow by `highlight_targets` while its type can be picked
+        if candidates.contains(typ) {
+            continue;
+        }
+        if let Some(mat) = mats.get_mut(&shimmer.0) {
+            mat.emissive = assets.color(*typ, settings.palette) * intensity * SHIMMER_STRENGTH;
+        }
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
 enum GameState {
     MainMenu,
+    Settings,
     Game,
+    GameOver,
+    /// Between a won battle and the next
+    Shop,
+    /// Picking the board between picking a mode on the main menu and playing
+    BoardSetup,
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+enum GameMode {
+    #[default]
+    Classic,
+    // The opponent never takes a turn so the player can freely try things out
+    Practice,
+    // Endless matching without an opponent
+    Zen,
+    // Racing the clock to defeat the opponent, who never takes a turn
+    Blitz,
 }
 
 #[repr(u8)]
-#[derive(Component, Clone, Copy, EnumIter, Display, Eq, Hash, PartialEq)]
+#[derive(
+    Component, Clone, Copy, EnumIter, Display, Eq, Hash, PartialEq, Serialize, Deserialize,
+)]
 enum GemType {
     Ruby,
     Emerald,
@@ -530,13 +2212,68 @@ impl From<GemType> for Color32 {
     }
 }
 
+impl TryFrom<u8> for GemType {
+    /// The value that doesn't name a gem type
+    type Error = u8;
+
+    // Follows the order of the `repr(u8)` discriminants
+    fn try_from(val: u8) -> Result<Self, Self::Error> {
+        match val {
+            0 => Ok(GemType::Ruby),
+            1 => Ok(GemType::Emerald),
+            2 => Ok(GemType::Sapphire),
+            3 => Ok(GemType::Topaz),
+            4 => Ok(GemType::Diamond),
+            5 => Ok(GemType::Amethyst),
+            6 => Ok(GemType::Skull),
+            7 => Ok(GemType::Equipment),
+            _ => Err(val),
+        }
+    }
+}
+
+/// For gem types coming from the board, which only holds valid ones.
+///
+/// # Panics
+///
+/// If `val` isn't a gem type, use `GemType::try_from` where that can happen
 impl From<u8> for GemType {
     fn from(val: u8) -> Self {
-        GemType::iter()
-            .enumerate()
-            .find(|(i, _)| *i == val as usize)
-            .unwrap()
-            .1
+        GemType::try_from(val).unwrap_or_else(|val| panic!("{val} isn't a gem type"))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Rarity {
+    Common,
+    Rare,
+}
+
+impl Rarity {
+    // Mana granted per popped gem
+    fn value(self) -> u32 {
+        match self {
+            Rarity::Common => 1,
+            Rarity::Rare => 2,
+        }
+    }
+}
+
+// Resource assigning a rarity to each gem type, anything missing is common
+#[derive(Deref, DerefMut)]
+struct RarityTable(HashMap<GemType, Rarity>);
+
+impl RarityTable {
+    fn rarity(&self, typ: GemType) -> Rarity {
+        self.get(&typ).copied().unwrap_or(Rarity::Common)
+    }
+}
+
+impl Default for RarityTable {
+    fn default() -> Self {
+        let mut table = HashMap::default();
+        table.insert(GemType::Diamond, Rarity::Rare);
+        Self(table)
     }
 }
 
@@ -548,6 +2285,10 @@ struct GemSlot {
     gem: Option<Entity>,
 }
 
+// Resource mapping board positions to their slot entity
+#[derive(Default, Deref, DerefMut)]
+struct SlotIndex(HashMap<UVec2, Entity>);
+
 fn update_raycast_with_cursor(
     mut cursor: EventReader<CursorMoved>,
     mut query: Query<&mut RayCastSource<RaycastSet>>,
@@ -559,38 +2300,57 @@ fn update_raycast_with_cursor(
     }
 }
 
+// Everything that takes clicks on the board away from selecting gems
+#[derive(SystemParam)]
+struct InputBlockers<'w, 's> {
+    intro: Query<'w, 's, (), With<CameraIntro>>,
+    targeting: Res<'w, Targeting>,
+    paused: Res<'w, Paused>,
+}
+
+impl InputBlockers<'_, '_> {
+    fn blocked(&self) -> bool {
+        !self.intro.is_empty() || self.targeting.is_some() || **self.paused
+    }
+}
+
 fn select(
-    mut commands: Commands,
     mouse_buttons: Res<Input<MouseButton>>,
     mut selected: ResMut<SelectedSlot>,
     mut board_commands: ResMut<BoardCommands>,
     mut turn_state: ResMut<State<TurnState>>,
     from: Query<&RayCastSource<RaycastSet>>,
     to: Query<&GemSlot>,
-    gems: Query<(&Animator<Transform>, Entity), With<GemType>>,
+    tweens: Res<BoardTweens>,
+    settings: Res<Settings>,
+    mut kept: Local<Option<Entity>>,
+    blockers: InputBlockers,
+    turn: Res<Turn>,
+    player: Query<(), With<Player>>,
+    swapper: Swapper,
 ) {
+    // Only hand the selection to the swapped-to slot once the swap and everything it set off has
+    // resolved, the slot's gem may have been popped and replaced in the meantime
+    if turn_state.current() == &TurnState::AwaitingMove {
+        if let Some(slot) = kept.take() {
+            let holds_gem = to.get(slot).is_ok_and(|slot| slot.gem.is_some());
+            if let Some(slot) = settings.post_swap_selection.reselect(slot, holds_gem) {
+                **selected = Some(slot);
+            }
+        }
+    }
+
     if !mouse_buttons.just_pressed(MouseButton::Left)
+        || settings.swap_input == SwapInput::Drag
         || matches!(turn_state.current(), TurnState::Resolving)
+        || blockers.blocked()
+        || player.get(**turn).is_err()
     {
         return;
     }
-    // Only allow selection if no gems (other than currently selected gem) are moving
-    for (animator, entity) in gems.iter() {
-        // if a gem is selected and is moving, check next gem
-        if selected.is_some_and(|selected| {
-            to.get(*selected).is_ok_and(|selected_slot| {
-                selected_slot
-                    .gem
-                    .is_some_and(|selected_gem| *selected_gem == entity)
-            })
-        }) {
-            continue;
-        }
-        // if gem is moving, return
-        if !approx_equal(animator.progress(), 1.0) {
-            return;
-        }
-        commands.entity(entity).remove::<Animator<Transform>>();
+    // Only allow selection once the board has stopped moving, the selected gem's wobble aside
+    if !tweens.settled() {
+        return;
     }
     for raycast_source in from.iter() {
         let (hit_entity, hit_slot) = match raycast_source
@@ -619,23 +2379,182 @@ fn select(
             if previously_selected_slot
                 .pos
                 .cardinally_adjacent(&hit_slot.pos)
+                && swapper.swap(previously_selected_slot, hit_slot, &mut board_commands)
             {
-                board_commands
-                    .push(BoardCommand::Swap(
-                        previously_selected_slot.pos,
-                        hit_slot.pos,
-                    ))
-                    .unwrap();
-
                 turn_state.set(TurnState::Resolving).unwrap();
+                *kept = Some(hit_entity);
             }
             **selected = None;
-        } else {
+        } else if swapper.movable(hit_slot) {
             **selected = Some(hit_entity);
         }
     }
 }
 
+// Everything deciding what swapping two gems does, shared by every way of swapping
+#[derive(SystemParam)]
+struct Swapper<'w, 's> {
+    board: Res<'w, Board>,
+    index: Res<'w, SlotIndex>,
+    wildcards: Query<'w, 's, (), With<Wildcard>>,
+    locked: Query<'w, 's, (), With<Locked>>,
+}
+
+impl Swapper<'_, '_> {
+    /// Whether the gem in `slot` can be swapped at all, a locked slot holds its gem in place
+    fn movable(&self, slot: &GemSlot) -> bool {
+        self.index
+            .get(&slot.pos)
+            .is_some_and(|slot| self.locked.get(*slot).is_err())
+    }
+
+    /// Swaps the gems in two adjacent slots, a wildcard isn't swapped though, it pops itself and
+    /// every gem of the other gem's type. Returns whether anything happened, nothing does if
+    /// either slot is locked
+    fn swap(&self, from: &GemSlot, to: &GemSlot, board_commands: &mut BoardCommands) -> bool {
+        if !self.movable(from) || !self.movable(to) {
+            return false;
+        }
+        let wildcard = [(from, to), (to, from)]
+            .into_iter()
+            .find(|(slot, _)| slot.gem.is_some_and(|gem| self.wildcards.get(gem).is_ok()));
+        if let Some((wildcard, other)) = wildcard {
+            let target = self
+                .board
+                .iter()
+                .find(|(pos, _)| **pos == other.pos)
+                .map(|(_, typ)| *typ);
+            let mut positions = self
+                .board
+                .iter()
+                .filter(|(_, typ)| Some(**typ) == target)
+                .map(|(pos, _)| *pos)
+                .collect::<Vec<_>>();
+            positions.push(wildcard.pos);
+            board_commands.push(BoardCommand::Pop(positions)).unwrap();
+        } else {
+            board_commands
+                .push(BoardCommand::Swap(from.pos, to.pos))
+                .unwrap();
+        }
+        true
+    }
+
+    /// The positions swapping two adjacent slots would pop straight away, matching `swap`
+    fn preview(&self, from: &GemSlot, to: &GemSlot) -> Vec<UVec2> {
+        if !from.pos.cardinally_adjacent(&to.pos) || !self.movable(from) || !self.movable(to) {
+            return Vec::new();
+        }
+        let wildcard = [(from, to), (to, from)]
+            .into_iter()
+            .find(|(slot, _)| slot.gem.is_some_and(|gem| self.wildcards.get(gem).is_ok()));
+        match wildcard {
+            Some((wildcard, other)) => {
+                let target = board_types(&self.board).get(&other.pos).copied();
+                let mut positions = self
+                    .board
+                    .iter()
+                    .filter(|(_, typ)| Some(**typ) == target)
+                    .map(|(pos, _)| *pos)
+                    .collect::<Vec<_>>();
+                positions.push(wildcard.pos);
+                positions
+            }
+            None => swap_matches(&self.board, from.pos, to.pos),
+        }
+    }
+}
+
+// How far a dragged gem follows the cursor, a little under half the distance between slots
+const MAX_DRAG_OFFSET: f32 = 0.08;
+
+// The gem being dragged and the slot it was picked up from
+struct Drag {
+    slot: Entity,
+    gem: Entity,
+}
+
+// Swaps a gem pressed on with the adjacent gem the mouse is released over, nudging the gem
+// toward the cursor while held
+fn drag_swap(
+    mouse_buttons: Res<Input<MouseButton>>,
+    settings: Res<Settings>,
+    mut selected: ResMut<SelectedSlot>,
+    mut board_commands: ResMut<BoardCommands>,
+    mut turn_state: ResMut<State<TurnState>>,
+    from: Query<&RayCastSource<RaycastSet>>,
+    slots: Query<(&GemSlot, &Transform), Without<GemType>>,
+    mut gems: Query<&mut Transform, With<GemType>>,
+    blockers: InputBlockers,
+    turn: Res<Turn>,
+    player: Query<(), With<Player>>,
+    swapper: Swapper,
+    mut dragging: Local<Option<Drag>>,
+) {
+    let hit = from
+        .iter()
+        .find_map(|source| source.intersect_top())
+        .and_then(|(entity, intersection)| {
+            slots
+                .get(entity)
+                .ok()
+                .map(|(slot, _)| (entity, slot, intersection.position()))
+        });
+
+    if mouse_buttons.just_pressed(MouseButton::Left)
+        && settings.swap_input != SwapInput::Click
+        && turn_state.current() == &TurnState::AwaitingMove
+        && !blockers.blocked()
+        && player.get(**turn).is_ok()
+    {
+        *dragging = hit
+            .filter(|(_, hit_slot, _)| swapper.movable(hit_slot))
+            .and_then(|(slot, hit_slot, _)| hit_slot.gem.map(|gem| Drag { slot, gem }));
+    }
+    let drag = match dragging.as_ref() {
+        Some(drag) => drag,
+        None => return,
+    };
+    let (drag_slot, rest) = match slots.get(drag.slot) {
+        Ok((slot, transform)) if slot.gem == Some(drag.gem) => (slot, transform.translation),
+        _ => {
+            *dragging = None;
+            return;
+        }
+    };
+
+    if mouse_buttons.pressed(MouseButton::Left) {
+        // Only start moving the gem once the cursor leaves its slot, a plain click is left to `select`
+        if let Some((.., position)) = hit.filter(|(slot, ..)| *slot != drag.slot) {
+            if let Ok(mut transform) = gems.get_mut(drag.gem) {
+                if selected.is_some() {
+                    **selected = None;
+                }
+                let offset = (position - rest)
+                    .truncate()
+                    .clamp_length_max(MAX_DRAG_OFFSET);
+                transform.translation = rest + offset.extend(0.0);
+            }
+        }
+        return;
+    }
+
+    // Released, snap back and swap if that's where the cursor ended up
+    if let Ok(mut transform) = gems.get_mut(drag.gem) {
+        transform.translation = rest;
+    }
+    if let Some((_, hit_slot, _)) = hit {
+        if drag_slot.pos.cardinally_adjacent(&hit_slot.pos)
+            && turn_state.current() == &TurnState::AwaitingMove
+            && swapper.swap(drag_slot, hit_slot, &mut board_commands)
+        {
+            turn_state.set(TurnState::Resolving).unwrap();
+            **selected = None;
+        }
+    }
+    *dragging = None;
+}
+
 trait BoardPosition {
     fn left(&self) -> Self;
     fn right(&self) -> Self;
@@ -669,34 +2588,115 @@ impl BoardPosition for UVec2 {
     }
 }
 
+const HINT_IDLE_SECONDS: f32 = 6.0;
+
+// Marks a gem mesh pulsing as a hint, so clearing the hint only takes out its own animator
+#[derive(Component)]
+struct HintPulse;
+
+// Pulses the two gems of a valid swap once the player has sat idle for a while on their turn,
+// any mouse input clears it again. Like the hover, the pulse scales the gem's mesh rather than
+// the gem, leaving its swap and selection animators alone
+fn hint(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut idle: Local<f32>,
+    mut hinted: Local<Vec<Entity>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    mut cursor: EventReader<CursorMoved>,
+    turn: Res<Turn>,
+    turn_state: Res<State<TurnState>>,
+    player: Query<(), With<Player>>,
+    board: Res<Board>,
+    mut valid_moves: ResMut<ValidMoves>,
+    index: Res<SlotIndex>,
+    slots: Query<&GemSlot>,
+    gems: Query<&Children, With<GemType>>,
+    mut pulses: Query<&mut Transform, With<HintPulse>>,
+    paused: Res<Paused>,
+    settings: Res<Settings>,
+) {
+    let moved = cursor.iter().count() > 0 || mouse_buttons.get_just_pressed().next().is_some();
+    let waiting =
+        player.get(**turn).is_ok() && turn_state.current() == &TurnState::AwaitingMove && !**paused;
+    if moved || !waiting {
+        *idle = 0.0;
+        // A mesh respawned for a new quality or taken over by the hover isn't pulsing anymore
+        for mesh in hinted.drain(..) {
+            if let Ok(mut transform) = pulses.get_mut(mesh) {
+                transform.scale = Vec3::ONE;
+                commands
+                    .entity(mesh)
+                    .remove::<Animator<Transform>>()
+                    .remove::<HintPulse>();
+            }
+        }
+        return;
+    }
+
+    *idle += time.delta_seconds();
+    if *idle < HINT_IDLE_SECONDS || !hinted.is_empty() {
+        return;
+    }
+    // With no moves at all there's nothing to hint at, the board gets reshuffled instead
+    let (from, to) = match valid_moves.get(&board).first() {
+        Some(valid_move) => *valid_move,
+        None => return,
+    };
+    for pos in [from, to] {
+        let mesh = slots
+            .get(index[&pos])
+            .ok()
+            .and_then(|slot| slot.gem)
+            .and_then(|gem| gems.get(gem).ok())
+            .and_then(|children| children.first().copied());
+        if let Some(mesh) = mesh {
+            commands
+                .entity(mesh)
+                .insert(HintPulse)
+                .insert(Animator::new(Tween::new(
+                    EaseFunction::SineInOut,
+                    if settings.reduced_motion {
+                        TweeningType::Once
+                    } else {
+                        TweeningType::PingPong
+                    },
+                    settings.motion_duration(0.6),
+                    TransformScaleLens {
+                        start: Vec3::ONE,
+                        end: Vec3::splat(1.15),
+                    },
+                )));
+            hinted.push(mesh);
+        }
+    }
+}
+
+// Wobbles the selected gem, putting the last one back upright first. Follows the gem rather than
+// its slot, so one swapped away or popped mid-wobble is never left with a stale animator
 fn animate_selected(
     mut commands: Commands,
+    settings: Res<Settings>,
     selected: Res<SelectedSlot>,
-    mut prev_selected: Local<Option<SelectedSlot>>,
+    mut animated: Local<Option<Entity>>,
     slots: Query<&GemSlot>,
-    mut animators: Query<(&mut Transform, &mut Animator<Transform>)>,
+    mut gems: Query<(&mut Transform, Option<&mut Animator<Transform>>), With<GemType>>,
 ) {
-    if !selected.is_changed() {
+    let popped = animated.is_some_and(|gem| gems.get(gem).is_err());
+    if !selected.is_changed() && !settings.is_changed() && !popped {
         return;
     }
 
     // stop old animation, if any
-    if let Some((mut transform, mut animator, entity)) = (*prev_selected)
-        .as_deref()
-        .copied()
-        .flatten()
-        .and_then(|prev_selected| slots.get(prev_selected).ok())
-        .and_then(|selected_gem| selected_gem.gem)
-        .and_then(|selected_gem| {
-            animators
-                .get_mut(selected_gem)
-                .ok()
-                .map(|gem| (gem.0, gem.1, selected_gem))
-        })
-    {
-        animator.stop();
-        transform.rotation = Quat::from_euler(EulerRot::XYZ, 0.0, 0.0, 0.0);
-        commands.entity(entity).remove::<Animator<Transform>>();
+    if let Some(gem) = animated.take() {
+        if let Ok((mut transform, animator)) = gems.get_mut(gem) {
+            if let Some(mut animator) = animator {
+                animator.stop();
+            }
+            transform.rotation = Quat::IDENTITY;
+            transform.scale = Vec3::ONE;
+            commands.entity(gem).remove::<Animator<Transform>>();
+        }
     }
 
     // animate new selection
@@ -706,201 +2706,1691 @@ fn animate_selected(
             .expect("Selected slot entity is not a gem??")
             .gem
     }) {
+        // Reduced motion settles on the raised pose instead of wobbling back and forth
         let seq = Tween::new(
             EaseFunction::SineInOut,
-            TweeningType::PingPong,
-            Duration::from_secs_f32(0.3),
-            TransformRotateZLens {
-                start: -0.5,
-                end: 0.5,
+            if settings.reduced_motion {
+                TweeningType::Once
+            } else {
+                TweeningType::PingPong
             },
+            settings.motion_duration(WOBBLE_SECONDS / settings.wobble_speed()),
+            SelectionLens::new(settings.selection_style, settings.wobble_amplitude()),
         );
         commands.entity(selected_gem).insert(Animator::new(seq));
-        *prev_selected = Some(*selected);
+        *animated = Some(selected_gem);
     }
 }
 
 #[derive(Deref, DerefMut, Clone, Copy)]
 struct SelectedSlot(Option<Entity>);
 
+// Tints the slots next to the selected gem so new players pick up that swaps only go sideways
+// or up and down, and outlines the slot under the keyboard cursor
+fn highlight_swap_targets(
+    settings: Res<Settings>,
+    selected: Res<SelectedSlot>,
+    focused: Res<FocusedSlot>,
+    assets: Res<GemAssets>,
+    mut slots: Query<(&GemSlot, &mut Handle<StandardMaterial>, Option<&Locked>)>,
+    mut locked_count: Local<usize>,
+) {
+    // Locks only ever come off during a match, so a change in their number catches every unlock
+    let locked = slots.iter().filter(|(.., locked)| locked.is_some()).count();
+    let locks_changed = std::mem::replace(&mut *locked_count, locked) != locked;
+    if !selected.is_changed() && !settings.is_changed() && !focused.is_changed() && !locks_changed {
+        return;
+    }
+    let selected_pos = selected
+        .and_then(|selected| slots.get(selected).ok())
+        .map(|(slot, ..)| slot.pos)
+        .filter(|_| settings.swap_hints);
+    for (slot, mut material, locked) in slots.iter_mut() {
+        *material = if **focused == Some(slot.pos) {
+            assets.focus.clone_weak()
+        } else if locked.is_some() {
+            assets.locked.clone_weak()
+        } else if selected_pos.is_some_and(|pos| pos.cardinally_adjacent(&slot.pos)) {
+            assets.highlight.clone_weak()
+        } else {
+            assets.transparent.clone_weak()
+        };
+    }
+}
+
+const HOVER_SCALE: f32 = 1.12;
+const HOVER_GLOW: f32 = 0.3;
+
+// Grows the gem under the mouse a little so it's clear what a click would pick, or lights it up
+// under reduced motion. The gem's mesh is scaled rather than the gem itself so the selection
+// wobble never gets overwritten
+fn hover_highlight(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    assets: Res<GemAssets>,
+    mut mats: ResMut<Assets<StandardMaterial>>,
+    mut hovered: Local<Option<Entity>>,
+    from: Query<&RayCastSource<RaycastSet>>,
+    slots: Query<&GemSlot>,
+    gems: Query<(&GemType, &Children)>,
+    mut children_query: MeshChildren,
+    blockers: InputBlockers,
+) {
+    let hit = if blockers.blocked() {
+        None
+    } else {
+        from.iter()
+            .find_map(|source| source.intersect_top())
+            .and_then(|(entity, _)| slots.get(entity).ok())
+            .and_then(|slot| slot.gem)
+    };
+    if hit == *hovered {
+        return;
+    }
+    if let Some(gem) = std::mem::replace(&mut *hovered, hit) {
+        if let Ok((typ, children)) = gems.get(gem) {
+            if settings.reduced_motion {
+                // Reinserting the type has `apply_material` put the gem's own material back
+                commands.entity(gem).insert(*typ);
+            } else {
+                scale_mesh(&mut commands, children, HOVER_SCALE, 1.0);
+            }
+        }
+    }
+    if let Some((typ, children)) = hit.and_then(|gem| gems.get(gem).ok()) {
+        if settings.reduced_motion {
+            let color = assets.color(*typ, settings.palette);
+            let material = mats.add(StandardMaterial {
+                base_color: color,
+                emissive: color * HOVER_GLOW,
+                ..default()
+            });
+            paint(children, &material, &mut children_query);
+        } else {
+            scale_mesh(&mut commands, children, 1.0, HOVER_SCALE);
+        }
+    }
+}
+
+// Tweens the scale of the child holding a gem's mesh
+fn scale_mesh(commands: &mut Commands, children: &Children, from: f32, to: f32) {
+    if let Some(mesh) = children.first() {
+        commands
+            .entity(*mesh)
+            .remove::<HintPulse>()
+            .insert(Animator::new(Tween::new(
+                EaseFunction::QuadraticOut,
+                TweeningType::Once,
+                Duration::from_secs_f32(0.1),
+                TransformScaleLens {
+                    start: Vec3::splat(from),
+                    end: Vec3::splat(to),
+                },
+            )));
+    }
+}
+
+/// Seconds for the selected gem to swing from one side to the other at normal wobble speed
+const WOBBLE_SECONDS: f32 = 0.3;
+
+// Wobbles the selected gem around Z and/or lifts it by scaling it up
+struct SelectionLens {
+    wobble: f32,
+    lift: f32,
+}
+
+impl SelectionLens {
+    /// Wobbles up to `amplitude` radians to either side, if `style` wobbles at all
+    fn new(style: SelectionStyle, amplitude: f32) -> Self {
+        let (wobble, lift) = match style {
+            SelectionStyle::Wobble => (amplitude, 0.0),
+            SelectionStyle::Lift => (0.0, 0.2),
+            SelectionStyle::WobbleAndLift => (amplitude, 0.2),
+        };
+        Self { wobble, lift }
+    }
+}
+
+impl Lens<Transform> for SelectionLens {
+    fn lerp(&mut self, target: &mut Transform, ratio: f32) {
+        target.rotation = Quat::from_rotation_z(self.wobble * (ratio * 2.0 - 1.0));
+        target.scale = Vec3::splat(1.0 + self.lift * ratio);
+    }
+}
+
 fn left_sidebar(
     mut skills: EventWriter<Skill>,
+    mut auto_match: EventWriter<AutoMatch>,
+    settings: Res<Settings>,
+    mut auto_cast: ResMut<AutoCast>,
+    mut warning: ResMut<ManaWarning>,
+    mut targeting: ResMut<Targeting>,
+    book: Res<SkillBook>,
     mut egui_ctx: ResMut<EguiContext>,
     state: Res<State<TurnState>>,
     windows: Res<Windows>,
     turn: Res<Turn>,
-    resources: Query<(Entity, &Resources), With<Player>>,
+    resources: Query<
+        (
+            Entity,
+            &Resources,
+            &Health,
+            &CombatStats,
+            &StatusEffects,
+            &Score,
+            &Collected,
+            &Experience,
+        ),
+        With<Player>,
+    >,
+    paused: Res<Paused>,
+    combo: Res<Combo>,
+    moves_left: Res<MovesLeft>,
+    progress: Res<Progress>,
 ) {
     let window = windows.primary();
-    let (player, resources) = resources.single();
+    let (player, resources, health, stats, status, score, collected, experience) =
+        resources.single();
     egui::SidePanel::left("Player panel")
         .resizable(false)
         .show(egui_ctx.ctx_mut(), |ui| {
-            ui.set_enabled((**turn == player) && (state.current() == &TurnState::AwaitingMove));
+            ui.set_enabled(
+                (**turn == player) && (state.current() == &TurnState::AwaitingMove) && !**paused,
+            );
             ui.set_width(window.width() / 4.0);
             ui.with_layout(
                 egui::Layout::default().with_cross_align(egui::Align::Center),
                 |ui| {
-                    ui.heading(RichText::new("Player").font(FontId::monospace(50.0)));
-                    ui.separator();
-                    ui.add(resources);
+                    ui.heading(turn_heading("Player", **turn == player));
+                    ui.add(health);
+                    combat_stats_ui(ui, stats, status);
+                    ui.add(status);
+                    ui.add(experience);
+                    score_ui(ui, score, (**turn == player).then_some(**combo));
+                    if let Some(moves_left) = **moves_left {
+                        ui.label(format!("Moves left: {moves_left}"));
+                    }
                     ui.separator();
-                    if ui
-                        .add(egui::Button::new(RichText::new("Bamboozle: free")))
-                        .clicked()
+                    ui.add(ResourcesWidget {
+                        resources,
+                        warning: Some(&*warning),
+                        book: Some(&*book),
+                    });
+                    ui.add(collected);
+                    if settings.auto_match_assist
+                        && ui
+                            .button("Auto-match")
+                            .on_hover_text("Makes the best swap on the board for you")
+                            .clicked()
                     {
-                        skills.send(Skill {
-                            typ: SkillType::Bamboozle,
-                            source: player,
-                        });
+                        auto_match.send(AutoMatch);
                     }
-                    if ui
-                        .add_enabled(
-                            resources
-                                .mana
-                                .get(&GemType::Amethyst)
-                                .copied()
-                                .unwrap_or_default()
-                                >= 3,
-                            egui::Button::new(RichText::new("Heal: 3amethyst")),
-                        )
-                        .clicked()
+                    ui.separator();
+                    for definition in book
+                        .iter()
+                        .filter(|definition| progress.knows(definition.typ))
                     {
-                        skills.send(Skill {
-                            typ: SkillType::Heal,
-                            source: player,
+                        let (skill, cost) = (definition.typ, definition.cost.as_slice());
+                        let targeted = !definition.effect.target_types().is_empty();
+                        ui.horizontal(|ui| {
+                            // Stays clickable when unaffordable so the player can be shown why
+                            // it doesn't work
+                            let label = RichText::new(format!("{skill}: {}", cost_label(cost)));
+                            let label = if resources.can_afford(cost) {
+                                label
+                            } else {
+                                label.weak()
+                            };
+                            if ui
+                                .button(label)
+                                .on_hover_text(skill_tooltip(definition, resources))
+                                .clicked()
+                            {
+                                if !resources.can_afford(cost) {
+                                    *warning = ManaWarning {
+                                        missing: resources.missing(cost),
+                                        timer: Timer::from_seconds(0.6, false),
+                                    };
+                                } else if targeted {
+                                    **targeting = Some(skill);
+                                } else {
+                                    skills.send(Skill {
+                                        typ: skill,
+                                        source: player,
+                                        target: None,
+                                    });
+                                }
+                            }
+                            if !cost.is_empty() && !targeted {
+                                auto_cast_toggle(ui, &mut auto_cast, skill);
+                            }
                         });
                     }
+                    if targeting.is_some() {
+                        ui.label("Click a gem to pick its type, Escape cancels");
+                    }
                 },
             );
         });
 }
 
+// The running score, with the multiplier of the cascade being resolved while it's above 1
+fn score_ui(ui: &mut egui::Ui, score: &Score, combo: Option<u32>) {
+    ui.horizontal(|ui| {
+        ui.label(format!("Score: {}", **score));
+        if let Some(multiplier) = combo.filter(|multiplier| *multiplier > 1) {
+            ui.colored_label(Color32::GOLD, format!("x{multiplier}"));
+        }
+    });
+}
+
+fn turn_heading(name: &str, their_turn: bool) -> RichText {
+    let heading = RichText::new(name).font(FontId::monospace(50.0));
+    if their_turn {
+        heading.color(Color32::GOLD)
+    } else {
+        heading
+    }
+}
+
 fn right_sidebar(
     mut egui_ctx: ResMut<EguiContext>,
+    mode: Res<GameMode>,
     windows: Res<Windows>,
     turn: Res<Turn>,
-    opponent: Query<(Entity, &Resources), Without<Player>>,
+    lineup: Res<Lineup>,
+    mut targeted: ResMut<TargetedEnemy>,
+    opponents: Query<
+        (
+            &Resources,
+            &Health,
+            &CombatStats,
+            &StatusEffects,
+            &Score,
+            &Collected,
+            &OpponentDefinition,
+        ),
+        Without<Player>,
+    >,
+    combo: Res<Combo>,
+    intent: Res<OpponentIntent>,
+    board: Res<Board>,
+    book: Res<SkillBook>,
+    time: Res<Time>,
+    settings: Res<Settings>,
+    turn_state: Res<State<TurnState>>,
+    reactions: Res<PortraitReactions>,
 ) {
+    if *mode == GameMode::Zen {
+        return;
+    }
     let window = windows.primary();
-    let (opponent, resources) = opponent.single();
     egui::SidePanel::right("Opponent panel")
         .resizable(false)
         .show(egui_ctx.ctx_mut(), |ui| {
-            ui.set_enabled(**turn == opponent);
             ui.set_width(window.width() / 4.0);
-            ui.with_layout(
-                egui::Layout::default().with_cross_align(egui::Align::Center),
-                |ui| {
-                    ui.heading(RichText::new("Opponent").font(FontId::monospace(50.0)));
-                    ui.separator();
-                    ui.add(resources);
-                },
-            );
+            for opponent in lineup.iter().copied() {
+                let (resources, health, stats, status, score, collected, definition) =
+                    match opponents.get(opponent) {
+                        Ok(opponent) => opponent,
+                        Err(_) => continue,
+                    };
+                let standing = health.current > 0;
+                // A portrait for every opponent, clicking one picks it as the target of the
+                // player's skulls and attacks
+                let mut stroke = if **targeted == Some(opponent) {
+                    egui::Stroke::new(2.0, Color32::GOLD)
+                } else {
+                    ui.visuals().widgets.noninteractive.bg_stroke
+                };
+                // The outline pulses while the opponent makes up its mind
+                let thinking = **turn == opponent
+                    && turn_state.current() == &TurnState::AwaitingMove
+                    && intent.is_some();
+                if thinking {
+                    stroke.color = stroke.color.linear_multiply(thinking_pulse(&time));
+                }
+                // Flinches when hurt, only flashing under reduced motion
+                let (flash, offset) = reactions.hurt(opponent, !settings.reduced_motion);
+                // Every portrait gets its own id so their collapsing sections don't collide
+                let portrait = ui
+                    .push_id(opponent, |ui| {
+                        portrait_frame(ui.style(), flash, offset)
+                            .stroke(stroke)
+                            .show(ui, |ui| {
+                                ui.set_enabled(standing);
+                                ui.set_width(ui.available_width());
+                                ui.with_layout(
+                                    egui::Layout::default().with_cross_align(egui::Align::Center),
+                                    |ui| {
+                                        opponent_portrait(
+                                            ui,
+                                            OpponentPortrait {
+                                                mode: *mode,
+                                                their_turn: **turn == opponent,
+                                                resources,
+                                                health,
+                                                stats,
+                                                status,
+                                                score,
+                                                collected,
+                                                definition,
+                                                combo: **combo,
+                                                intent: &intent,
+                                                thinking_dots: if thinking {
+                                                    thinking_dots(&time)
+                                                } else {
+                                                    ""
+                                                },
+                                                board: &board,
+                                                book: &book,
+                                            },
+                                        );
+                                    },
+                                );
+                            })
+                            .response
+                    })
+                    .inner;
+                if standing
+                    && portrait
+                        .interact(egui::Sense::click())
+                        .on_hover_text("Click to target")
+                        .clicked()
+                {
+                    **targeted = Some(opponent);
+                }
+            }
         });
 }
 
-#[derive(Component, Default)]
+// Everything shown about one opponent in the lineup
+struct OpponentPortrait<'a> {
+    mode: GameMode,
+    their_turn: bool,
+    resources: &'a Resources,
+    health: &'a Health,
+    stats: &'a CombatStats,
+    status: &'a StatusEffects,
+    score: &'a Score,
+    collected: &'a Collected,
+    definition: &'a OpponentDefinition,
+    combo: u32,
+    intent: &'a OpponentIntent,
+    /// Trailing the intent while the opponent is still thinking
+    thinking_dots: &'static str,
+    board: &'a Board,
+    book: &'a SkillBook,
+}
+
+fn opponent_portrait(ui: &mut egui::Ui, portrait: OpponentPortrait) {
+    let name = if portrait.mode == GameMode::Practice {
+        "Practice dummy"
+    } else {
+        &portrait.definition.name
+    };
+    ui.heading(turn_heading(name, portrait.their_turn));
+    if let Some(greeting) = &portrait.definition.greeting {
+        ui.label(RichText::new(greeting).italics());
+    }
+    ui.add(portrait.health);
+    combat_stats_ui(ui, portrait.stats, portrait.status);
+    ui.add(portrait.status);
+    score_ui(
+        ui,
+        portrait.score,
+        portrait.their_turn.then_some(portrait.combo),
+    );
+    if portrait.their_turn {
+        ui.label(
+            RichText::new(format!(
+                "{}{}",
+                portrait.intent.describe(portrait.board),
+                portrait.thinking_dots
+            ))
+            .strong(),
+        );
+    }
+    ui.collapsing("Mana", |ui| {
+        ui.add(portrait.resources);
+        ui.add(portrait.collected);
+    });
+    for ability in &portrait.definition.abilities {
+        let cost = portrait.book.cost(*ability);
+        ui.add(
+            ProgressBar::new(portrait.resources.progress_towards(cost))
+                .text(format!("{ability}: {}", cost_label(cost))),
+        );
+    }
+}
+
+const MANA_CAP: u32 = 20;
+
+// The most mana of a type a side can hold, 0 for types that aren't mana at all
+fn mana_cap(typ: GemType) -> u32 {
+    match typ {
+        GemType::Skull | GemType::Equipment => 0,
+        GemType::Ruby
+        | GemType::Emerald
+        | GemType::Sapphire
+        | GemType::Topaz
+        | GemType::Diamond
+        | GemType::Amethyst => MANA_CAP,
+    }
+}
+
+#[derive(Component, Clone, Default)]
 struct Resources {
     mana: HashMap<GemType, u32>,
+    /// Paid out by equipment gems instead of mana, and spent wherever a cost asks for equipment
+    gold: u32,
 }
 
 impl Resources {
-    fn add(&mut self, typ: GemType) {
-        if typ == GemType::Skull {
-            return;
+    // How much of `typ` there is to spend, gold for equipment
+    fn amount(&self, typ: GemType) -> u32 {
+        if typ == GemType::Equipment {
+            self.gold
+        } else {
+            self.mana.get(&typ).copied().unwrap_or_default()
         }
-        self.mana
-            .insert(typ, self.mana.get(&typ).copied().unwrap_or_default() + 1);
+    }
+
+    // Adds mana up to the type's cap, returning how much didn't fit. Equipment adds gold, which
+    // has no cap
+    fn add(&mut self, typ: GemType, amount: u32) -> u32 {
+        if typ == GemType::Equipment {
+            self.gold = self.gold.saturating_add(amount);
+            return 0;
+        }
+        let cap = mana_cap(typ);
+        let total = self
+            .mana
+            .get(&typ)
+            .copied()
+            .unwrap_or_default()
+            .saturating_add(amount);
+        if cap > 0 {
+            self.mana.insert(typ, total.min(cap));
+        }
+        total.saturating_sub(cap)
     }
 
     fn pay(&mut self, typ: GemType, amount: u32) -> bool {
         if typ == GemType::Skull {
             unimplemented!("Skulls are not a resource");
         }
-        let mana = self.mana.get(&typ).copied().unwrap_or_default();
-        if mana >= amount {
-            self.mana.insert(typ, mana - amount);
-            true
+        let available = self.amount(typ);
+        if available < amount {
+            return false;
+        }
+        if typ == GemType::Equipment {
+            self.gold -= amount;
         } else {
-            false
+            self.mana.insert(typ, available - amount);
         }
+        true
     }
 
+    // Drains all mana, gold is kept
     fn clear(&mut self) {
         self.mana.clear();
     }
+
+    fn missing(&self, cost: &[(GemType, u32)]) -> Vec<GemType> {
+        cost.iter()
+            .filter(|(typ, amount)| self.amount(*typ) < *amount)
+            .map(|(typ, _)| *typ)
+            .collect()
+    }
+
+    fn can_afford(&self, cost: &[(GemType, u32)]) -> bool {
+        cost.iter()
+            .all(|(typ, amount)| self.amount(*typ) >= *amount)
+    }
+
+    /// How much of `cost` is already covered, from 0 to 1
+    fn progress_towards(&self, cost: &[(GemType, u32)]) -> f32 {
+        let needed: u32 = cost.iter().map(|(_, amount)| amount).sum();
+        if needed == 0 {
+            return 1.0;
+        }
+        let covered: u32 = cost
+            .iter()
+            .map(|(typ, amount)| self.amount(*typ).min(*amount))
+            .sum();
+        covered as f32 / needed as f32
+    }
+
+    // Pays the whole cost, or nothing at all if any part of it can't be afforded
+    fn try_spend(&mut self, cost: &[(GemType, u32)]) -> bool {
+        if !self.can_afford(cost) {
+            return false;
+        }
+        for (typ, amount) in cost {
+            self.pay(*typ, *amount);
+        }
+        true
+    }
 }
 
 impl egui::Widget for &Resources {
     fn ui(self, ui: &mut egui::Ui) -> egui::Response {
-        ui.group(|ui| {
-            for typ in GemType::iter() {
-                if typ == GemType::Skull {
-                    continue;
-                }
-                let amount = self.mana.get(&typ).unwrap_or(&0);
-                ui.horizontal(|ui| {
-                    ui.visuals_mut().selection.bg_fill = typ.into();
-                    ui.colored_label(typ, format!("{amount}"));
-                    ui.add(ProgressBar::new(*amount as f32 / 20.0));
-                });
-            }
-        })
-        .response
+        ResourcesWidget {
+            resources: self,
+            warning: None,
+            book: None,
+        }
+        .ui(ui)
     }
 }
 
+const MAX_HEALTH: u32 = 100;
+
 #[derive(Component)]
-struct Player;
+struct Health {
+    current: u32,
+    max: u32,
+}
+
+impl Health {
+    fn new(max: u32) -> Self {
+        Self { current: max, max }
+    }
 
-fn setup_resources(mut commands: Commands) {
-    // Player resources
-    let player = commands.spawn_bundle((Player, Resources::default())).id();
-    // Opponent resources
-    commands.spawn_bundle((Resources::default(),));
+    fn damage(&mut self, amount: u32) {
+        self.current = self.current.saturating_sub(amount);
+    }
 
-    determine_starter(&mut commands, player);
+    fn heal(&mut self, amount: u32) {
+        self.current = (self.current + amount).min(self.max);
+    }
 }
 
-struct Skill {
-    typ: SkillType,
-    source: Entity,
-}
+/// Least damage a hit deals however well defended its target is
+const MIN_DAMAGE: u32 = 1;
 
-enum SkillType {
-    Bamboozle,
-    Heal,
+/// Chance of the player's hits landing as critical hits
+const PLAYER_CRIT_CHANCE: f32 = 0.1;
+
+/// How well a side holds up in a fight
+#[derive(Component, Clone, Copy)]
+struct CombatStats {
+    /// Taken off every hit of skull or skill damage
+    defense: u32,
+    /// Chance from 0 to 1 of the side's skull and skill damage being multiplied by
+    /// `CRIT_MULTIPLIER`
+    crit_chance: f32,
 }
 
-fn skills(
-    mut board_commands: ResMut<BoardCommands>,
-    mut state: ResMut<State<TurnState>>,
-    mut skills: EventReader<Skill>,
-    mut users: Query<&mut Resources>,
-) {
-    for skill in skills.iter() {
-        match skill.typ {
-            SkillType::Bamboozle => {
-                info!("{:?} did a heckin bamboozle", skill.source);
-                if users.get_mut(skill.source).is_ok() {
-                    board_commands.push(BoardCommand::Shuffle).unwrap();
-                    state.set(TurnState::Resolving).unwrap();
-                }
-            }
-            SkillType::Heal => {
-                info!("{:?} did a healz", skill.source);
-                if let Ok(mut resources) = users.get_mut(skill.source) {
-                    resources.pay(GemType::Amethyst, 3);
-                }
-            }
+impl CombatStats {
+    fn player() -> Self {
+        Self {
+            defense: 0,
+            crit_chance: PLAYER_CRIT_CHANCE,
         }
     }
-}
 
-// Resource containing the entity whose turn it is
-#[derive(DerefMut, Deref)]
-struct Turn(Entity);
+    fn opponent(definition: &OpponentDefinition) -> Self {
+        Self {
+            defense: definition.defense,
+            crit_chance: definition.crit_chance,
+        }
+    }
+
+    /// Defense including any guard the side has up
+    fn defense(&self, status: &StatusEffects) -> u32 {
+        self.defense + status.magnitude(StatusKind::Guard)
+    }
+}
+
+/// What's left of a hit of `damage` once `defense` has soaked up its share, a hit always lands for
+/// at least `MIN_DAMAGE`
+fn damage_after_defense(damage: u32, defense: u32) -> u32 {
+    if damage == 0 {
+        0
+    } else {
+        damage.saturating_sub(defense).max(MIN_DAMAGE)
+    }
+}
+
+fn combat_stats_ui(ui: &mut egui::Ui, stats: &CombatStats, status: &StatusEffects) {
+    let bonus = stats.defense(status) - stats.defense;
+    if bonus > 0 {
+        ui.label(format!("Defense: {} (+{bonus})", stats.defense));
+    } else {
+        ui.label(format!("Defense: {}", stats.defense));
+    }
+    ui.label(format!("Crit chance: {:.0}%", stats.crit_chance * 100.0));
+}
+
+impl egui::Widget for &Health {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        ui.horizontal(|ui| {
+            ui.visuals_mut().selection.bg_fill = Color32::DARK_RED;
+            ui.label("Health");
+            ui.add(
+                ProgressBar::new(self.current as f32 / self.max as f32)
+                    .text(format!("{}/{}", self.current, self.max)),
+            );
+        })
+        .response
+    }
+}
+
+/// Moves a limited match starts with until another limit is picked on the main menu
+const DEFAULT_MOVE_LIMIT: u32 = 20;
+
+// Resource containing the move limit picked on the main menu, duels without one only end when
+// someone runs out of health
+struct MoveLimit {
+    moves: u32,
+    enabled: bool,
+}
+
+impl Default for MoveLimit {
+    fn default() -> Self {
+        Self {
+            moves: DEFAULT_MOVE_LIMIT,
+            enabled: false,
+        }
+    }
+}
+
+// Resource containing how many more moves the player gets this match, `None` without a limit
+#[derive(Default, Deref, DerefMut)]
+struct MovesLeft(Option<u32>);
+
+// Event sent whenever a move and everything it set off has resolved, with the side that made it
+struct MoveResolved(Entity);
+
+// Continued matches pick up the moves they had left from their save instead
+fn reset_moves_left(mode: Res<GameMode>, limit: Res<MoveLimit>, mut left: ResMut<MovesLeft>) {
+    **left = (limit.enabled && *mode == GameMode::Classic).then_some(limit.moves);
+}
+
+fn count_moves(
+    mut resolved: EventReader<MoveResolved>,
+    mut left: ResMut<MovesLeft>,
+    player: Query<(), With<Player>>,
+) {
+    for MoveResolved(side) in resolved.iter() {
+        if player.get(*side).is_err() {
+            continue;
+        }
+        if let Some(moves) = &mut **left {
+            *moves = moves.saturating_sub(1);
+        }
+    }
+}
+
+// Ends the game once either side runs out of health, outside the classic duel nobody can lose.
+// Waits for the board to settle so no board events are left over for the next game
+fn check_defeat(
+    mode: Res<GameMode>,
+    mut state: ResMut<State<GameState>>,
+    turn_state: Res<State<TurnState>>,
+    moves_left: Res<MovesLeft>,
+    player: Query<&Health, With<Player>>,
+    opponents: Query<&Health, Without<Player>>,
+) {
+    let player_down = player.iter().any(|health| health.current == 0);
+    let opponents_down = opponents.iter().all(|health| health.current == 0);
+    if matches!(*mode, GameMode::Classic | GameMode::Blitz)
+        && turn_state.current() == &TurnState::AwaitingMove
+        && (player_down || opponents_down || **moves_left == Some(0))
+    {
+        state.set(GameState::GameOver).unwrap();
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Win,
+    Loss,
+    Draw,
+}
+
+// Whoever is still standing wins, the player has to bring down every opponent. A duel cut short by
+// its move limit goes to whoever has more health left between them, while running out of time in
+// blitz is a loss
+fn outcome<'a>(
+    mode: GameMode,
+    player: &Health,
+    opponents: impl IntoIterator<Item = &'a Health>,
+) -> Outcome {
+    let opponents_left: u32 = opponents.into_iter().map(|health| health.current).sum();
+    if player.current == 0 {
+        Outcome::Loss
+    } else if opponents_left == 0 {
+        Outcome::Win
+    } else if mode == GameMode::Blitz {
+        Outcome::Loss
+    } else {
+        match player.current.cmp(&opponents_left) {
+            Ordering::Greater => Outcome::Win,
+            Ordering::Less => Outcome::Loss,
+            Ordering::Equal => Outcome::Draw,
+        }
+    }
+}
+
+fn game_over(
+    mut egui_ctx: ResMut<EguiContext>,
+    mut state: ResMut<State<GameState>>,
+    mode: Res<GameMode>,
+    mut board_seed: ResMut<BoardSeed>,
+    match_seed: Res<MatchSeed>,
+    config: Res<Match3Config>,
+    gravity: Res<GravityDirection>,
+    player: Query<(&Health, &Resources, &Score, &Collected), With<Player>>,
+    opponents: Query<(&Health, &OpponentDefinition), Without<Player>>,
+) {
+    let (health, resources, score, collected) = player.single();
+    let result = outcome(*mode, health, opponents.iter().map(|(health, _)| health));
+    let heading = match result {
+        Outcome::Win => "Player wins".to_string(),
+        Outcome::Loss => match opponents.get_single() {
+            Ok((_, opponent)) => format!("{} wins", opponent.name),
+            Err(_) => "The opponents win".to_string(),
+        },
+        Outcome::Draw => "Draw".to_string(),
+    };
+    egui::CentralPanel::default().show(egui_ctx.ctx_mut(), |ui| {
+        ui.with_layout(
+            egui::Layout::default().with_cross_align(egui::Align::Center),
+            |ui| {
+                ui.heading(RichText::new(heading).font(FontId::monospace(50.0)));
+                ui.separator();
+                ui.label(format!("Score: {}", **score));
+                ui.add(resources);
+                ui.add(collected);
+                share_code_label(ui, ShareCode::of(**match_seed, &config, *gravity));
+                ui.separator();
+                if result == Outcome::Win {
+                    if ui.button("Next Battle").clicked() {
+                        state.set(GameState::Shop).unwrap();
+                    }
+                } else {
+                    // Retry lays the same board out again, a new board gets a fresh seed
+                    if ui.button("Retry").clicked() {
+                        **board_seed = Some(**match_seed);
+                        state.set(GameState::Game).unwrap();
+                    }
+                    if ui.button("New Board").clicked() {
+                        **board_seed = None;
+                        state.set(GameState::Game).unwrap();
+                    }
+                }
+                if ui.button("Main Menu").clicked() {
+                    state.set(GameState::MainMenu).unwrap();
+                }
+            },
+        );
+    });
+}
+
+// Everything spawned for a game, from the board down to the sides playing it
+type GameEntities<'w, 's> =
+    Query<'w, 's, Entity, Or<(With<GemSlot>, With<GemType>, With<Shatter>, With<Resources>)>>;
+
+// Clears away the finished game, entering `GameState::Game` spawns a fresh board and sides
+fn despawn_game(mut commands: Commands, entities: GameEntities) {
+    for entity in entities.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// Resource freezing the board, the opponent and every animation while set
+#[derive(Default, Deref, DerefMut)]
+struct Paused(bool);
+
+fn toggle_pause(
+    keys: Res<Input<KeyCode>>,
+    targeting: Res<Targeting>,
+    confirming: Res<ConfirmingQuit>,
+    mut paused: ResMut<Paused>,
+) {
+    // Escape cancels targeting or closes the quit confirmation before it pauses
+    if keys.just_pressed(KeyCode::Escape) && targeting.is_none() && !**confirming {
+        **paused = !**paused;
+    }
+}
+
+// Holds every running animation while paused and picks them back up where they were on resume
+fn freeze_animations(
+    paused: Res<Paused>,
+    mut frozen: Local<Vec<Entity>>,
+    mut animators: Query<(Entity, &mut Animator<Transform>)>,
+) {
+    if !paused.is_changed() {
+        return;
+    }
+    if **paused {
+        for (entity, mut animator) in animators.iter_mut() {
+            if animator.state == AnimatorState::Playing {
+                animator.state = AnimatorState::Paused;
+                frozen.push(entity);
+            }
+        }
+    } else {
+        for entity in frozen.drain(..) {
+            if let Ok((_, mut animator)) = animators.get_mut(entity) {
+                animator.state = AnimatorState::Playing;
+            }
+        }
+    }
+}
+
+fn pause_menu(
+    commands: Commands,
+    mut egui_ctx: ResMut<EguiContext>,
+    mut paused: ResMut<Paused>,
+    mut settings: ResMut<Settings>,
+    mut state: ResMut<State<GameState>>,
+    entities: GameEntities,
+    snapshot: MatchSnapshot,
+    mut undo: ResMut<Undo>,
+    keys: Res<Input<KeyCode>>,
+    mut confirming: ResMut<ConfirmingQuit>,
+    mut abandoned: ResMut<Abandoned>,
+    mut saved: Local<bool>,
+    seed: Res<MatchSeed>,
+    config: Res<Match3Config>,
+    gravity: Res<GravityDirection>,
+    assets: Res<GemAssets>,
+) {
+    // Nothing moves while paused, so a save made during this pause still holds when quitting
+    if !**paused {
+        *saved = false;
+        return;
+    }
+    let mut quit = false;
+    egui::Window::new("Paused")
+        .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+        .collapsible(false)
+        .resizable(false)
+        .show(egui_ctx.ctx_mut(), |ui| {
+            ui.set_enabled(!**confirming);
+            ui.vertical_centered_justified(|ui| {
+                if ui.button("Resume").clicked() {
+                    **paused = false;
+                }
+                if ui
+                    .add_enabled(undo.available(), egui::Button::new("Undo Last Move"))
+                    .on_hover_text("Ctrl+Z")
+                    .clicked()
+                {
+                    undo.request();
+                    **paused = false;
+                }
+                if ui.button("Save").clicked() {
+                    snapshot.save();
+                    *saved = true;
+                }
+                share_code_label(ui, ShareCode::of(**seed, &config, *gravity));
+                ui.collapsing("Settings", |ui| {
+                    settings_ui(ui, &mut settings, assets.low_meshes_shipped);
+                });
+                if ui.button("Quit to Menu").clicked() {
+                    **confirming = true;
+                }
+            });
+        });
+    if **confirming {
+        if let Some(answer) = confirm_dialog(egui_ctx.ctx_mut(), &keys, "Quit to the menu?") {
+            quit = answer;
+            **confirming = false;
+        }
+    }
+    if quit {
+        if !*saved {
+            abandoned.keep(snapshot.capture());
+        }
+        **paused = false;
+        despawn_game(commands, entities);
+        state.set(GameState::MainMenu).unwrap();
+    }
+}
+
+// Below this width the bars give way to plain text
+const COMPACT_RESOURCES_WIDTH: f32 = 120.0;
+
+// Resources with any mana the player is short on shaking in red, and the bars of mana that pays for
+// a skill outlined once there's enough
+struct ResourcesWidget<'a> {
+    resources: &'a Resources,
+    warning: Option<&'a ManaWarning>,
+    book: Option<&'a SkillBook>,
+}
+
+impl ResourcesWidget<'_> {
+    // The amounts of `typ` the skills cost
+    fn thresholds(&self, typ: GemType) -> Vec<u32> {
+        self.book
+            .iter()
+            .flat_map(|book| book.iter())
+            .flat_map(|definition| &definition.cost)
+            .filter(|(cost_typ, _)| *cost_typ == typ)
+            .map(|(_, amount)| *amount)
+            .collect()
+    }
+}
+
+impl egui::Widget for ResourcesWidget<'_> {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        let compact = ui.available_width() < COMPACT_RESOURCES_WIDTH;
+        ui.group(|ui| {
+            for typ in GemType::iter() {
+                if mana_cap(typ) == 0 {
+                    continue;
+                }
+                let amount = self.resources.amount(typ);
+                if compact {
+                    ui.colored_label(typ, format!("{typ}: {amount}"));
+                    continue;
+                }
+                let warning = self
+                    .warning
+                    .filter(|warning| !warning.timer.finished() && warning.missing.contains(&typ));
+                let thresholds = self.thresholds(typ);
+                let cap = mana_cap(typ);
+                let affordable = thresholds.iter().any(|threshold| amount >= *threshold);
+                ui.horizontal(|ui| {
+                    ui.visuals_mut().selection.bg_fill = typ.into();
+                    if let Some(warning) = warning {
+                        let strength = 1.0 - warning.timer.percent();
+                        ui.add_space(
+                            4.0 + (warning.timer.elapsed_secs() * 60.0).sin() * 4.0 * strength,
+                        );
+                        ui.colored_label(Color32::RED, format!("{amount}"));
+                    } else {
+                        ui.add_space(4.0);
+                        ui.colored_label(typ, format!("{amount}"));
+                    }
+                    let stroke = if affordable {
+                        egui::Stroke::new(2.0, Color32::GOLD)
+                    } else {
+                        egui::Stroke::none()
+                    };
+                    egui::Frame::none().stroke(stroke).show(ui, |ui| {
+                        ui.add(ProgressBar::new(amount as f32 / cap as f32));
+                    });
+                });
+            }
+            let short = self.warning.is_some_and(|warning| {
+                !warning.timer.finished() && warning.missing.contains(&GemType::Equipment)
+            });
+            let color = if short { Color32::RED } else { Color32::GOLD };
+            ui.colored_label(color, format!("Gold: {}", self.resources.gold));
+        })
+        .response
+    }
+}
+
+// Resource containing the mana types the player lacked when they last tried to cast
+#[derive(Default)]
+struct ManaWarning {
+    missing: Vec<GemType>,
+    timer: Timer,
+}
+
+fn tick_mana_warning(time: Res<Time>, mut warning: ResMut<ManaWarning>) {
+    if warning.missing.is_empty() {
+        return;
+    }
+    if warning.timer.tick(time.delta()).finished() {
+        warning.missing.clear();
+    }
+}
+
+// How many gems of each type a side has popped this match, regardless of what they spent
+#[derive(Component, Default, Deref, DerefMut)]
+struct Collected(HashMap<GemType, u32>);
+
+impl egui::Widget for &Collected {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Collected:");
+            for typ in GemType::iter() {
+                let amount = self.get(&typ).unwrap_or(&0);
+                ui.colored_label(typ, format!("{amount}"));
+            }
+        })
+        .response
+    }
+}
+
+#[derive(Component)]
+struct Player;
+
+fn setup_resources(
+    mut commands: Commands,
+    mode: Res<GameMode>,
+    roster: Res<OpponentRoster>,
+    mut rng: ResMut<EncounterRng>,
+    saved: Option<Res<SavedGame>>,
+    progress: Res<Progress>,
+) {
+    if let Some(saved) = saved {
+        saved.spawn_sides(&mut commands, &roster, &mut rng);
+        commands.remove_resource::<SavedGame>();
+        return;
+    }
+    // Player resources, carried over from the last battle
+    let mut resources = Resources {
+        gold: progress.gold,
+        ..default()
+    };
+    for typ in GemType::iter().filter(|typ| mana_cap(*typ) > 0) {
+        resources.add(typ, progress.starting_mana);
+    }
+    let player = commands
+        .spawn_bundle((
+            Player,
+            resources,
+            Health::new(MAX_HEALTH + progress.bonus_health + progress.experience.bonus_health()),
+            Score::default(),
+            Collected::default(),
+            StatusEffects::default(),
+            CombatStats::player(),
+            progress.experience,
+        ))
+        .id();
+    // Opponent resources, only real duels put up more than one
+    let size = if matches!(*mode, GameMode::Classic | GameMode::Blitz) {
+        rng.usize(1..=MAX_LINEUP)
+    } else {
+        1
+    };
+    let lineup = (0..size)
+        .map(|_| {
+            let opponent = roster.pick(&mut rng).clone();
+            let mut resources = Resources::default();
+            for (typ, amount) in &opponent.starting_mana {
+                resources.add(*typ, *amount);
+            }
+            commands
+                .spawn_bundle((
+                    resources,
+                    Health::new(opponent.health),
+                    Score::default(),
+                    Collected::default(),
+                    StatusEffects::default(),
+                    CombatStats::opponent(&opponent),
+                    opponent,
+                ))
+                .id()
+        })
+        .collect::<Vec<_>>();
+    commands.insert_resource(TargetedEnemy(lineup.first().copied()));
+    commands.insert_resource(Lineup(lineup));
+
+    determine_starter(&mut commands, player);
+}
+
+struct Skill {
+    typ: SkillType,
+    source: Entity,
+    /// The gem type picked for skills that need one
+    target: Option<GemType>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, EnumIter, Display, Serialize, Deserialize)]
+enum SkillType {
+    Bamboozle,
+    Bonk,
+    Heal,
+    Purge,
+    Cataclysm,
+    Transmute,
+    Venom,
+    Scorch,
+    Daze,
+    Stoneskin,
+}
+
+/// What casting a skill does
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum SkillEffect {
+    Shuffle,
+    /// Damages the side that didn't cast it
+    Damage(u32),
+    /// Heals the side that cast it
+    Heal(u32),
+    /// Pops every gem of a type the caster picks
+    PopType,
+    /// Pops every gem on the board in a wave from the centre
+    PopAll,
+    /// Turns every gem of one type into another, popping any lines that makes
+    Convert {
+        from: GemType,
+        to: GemType,
+    },
+    /// Puts a status effect on the side that didn't cast it
+    Afflict(StatusEffect),
+    /// Puts a status effect on the side that cast it
+    Bolster(StatusEffect),
+}
+
+impl SkillEffect {
+    /// Gem types the skill can be aimed at, empty for skills that don't need a target
+    fn target_types(self) -> &'static [GemType] {
+        match self {
+            SkillEffect::PopType => &[
+                GemType::Ruby,
+                GemType::Emerald,
+                GemType::Sapphire,
+                GemType::Topaz,
+                GemType::Diamond,
+                GemType::Amethyst,
+            ],
+            SkillEffect::Shuffle
+            | SkillEffect::Damage(_)
+            | SkillEffect::Heal(_)
+            | SkillEffect::PopAll
+            | SkillEffect::Convert { .. }
+            | SkillEffect::Afflict(_)
+            | SkillEffect::Bolster(_) => &[],
+        }
+    }
+
+    fn description(self) -> String {
+        match self {
+            SkillEffect::Shuffle => "Shuffles the board".to_string(),
+            SkillEffect::Damage(amount) => format!("Deals {amount} damage to the other side"),
+            SkillEffect::Heal(amount) => format!("Restores {amount} health"),
+            SkillEffect::PopType => "Pops every gem of a type you pick".to_string(),
+            SkillEffect::PopAll => "Pops the whole board".to_string(),
+            SkillEffect::Convert { from, to } => format!(
+                "Turns every {} into {}",
+                from.to_string().to_lowercase(),
+                to.to_string().to_lowercase()
+            ),
+            SkillEffect::Afflict(effect) | SkillEffect::Bolster(effect) => effect.description(),
+        }
+    }
+}
+
+// Cost, effect and whether the skill can be cast right now, shown when hovering its button
+fn skill_tooltip(skill: &SkillDefinition, resources: &Resources) -> String {
+    let cost = skill.cost.as_slice();
+    let affordability = if resources.can_afford(cost) {
+        "You can afford it".to_string()
+    } else {
+        let missing = resources
+            .missing(cost)
+            .iter()
+            .map(|typ| resource_name(*typ))
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("Not enough {missing}")
+    };
+    format!(
+        "Costs {}\n{}\n{affordability}",
+        cost_label(cost),
+        skill.effect.description()
+    )
+}
+
+// Resource containing the skill waiting on the player to pick a gem type for it
+#[derive(Default, Deref, DerefMut)]
+struct Targeting(Option<SkillType>);
+
+const TARGET_GLOW: f32 = 0.5;
+
+// While a skill is waiting on a target, clicking a gem picks its type instead of selecting it
+fn pick_target(
+    mut targeting: ResMut<Targeting>,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    mut skills: EventWriter<Skill>,
+    from: Query<&RayCastSource<RaycastSet>>,
+    slots: Query<&GemSlot>,
+    gems: Query<&GemType>,
+    player: Query<Entity, With<Player>>,
+    paused: Res<Paused>,
+    book: Res<SkillBook>,
+) {
+    let skill = match **targeting {
+        Some(skill) if !**paused => skill,
+        _ => return,
+    };
+    if keys.just_pressed(KeyCode::Escape) {
+        **targeting = None;
+        return;
+    }
+    if !mouse_buttons.just_pressed(MouseButton::Left) {
+        return;
+    }
+    let picked = from
+        .iter()
+        .find_map(|source| source.intersect_top())
+        .and_then(|(hit, _)| slots.get(hit).ok())
+        .and_then(|slot| slot.gem)
+        .and_then(|gem| gems.get(gem).ok());
+    if let Some(typ) = picked.filter(|typ| book.target_types(skill).contains(typ)) {
+        skills.send(Skill {
+            typ: skill,
+            source: player.single(),
+            target: Some(*typ),
+        });
+        **targeting = None;
+    }
+}
+
+// Lights up every gem a targeting skill can pick, special ones included, putting back their own glow
+// once targeting ends
+fn highlight_targets(
+    settings: Res<Settings>,
+    book: Res<SkillBook>,
+    targeting: Res<Targeting>,
+    assets: Res<GemAssets>,
+    mut mats: ResMut<Assets<StandardMaterial>>,
+    gems: Query<(&GemType, &GemMaterial)>,
+    repainted: Query<(), Changed<GemMaterial>>,
+) {
+    // Gems repainted mid-targeting, like a cracked lock or a fresh power gem, need lighting up too
+    if !targeting.is_changed() && repainted.is_empty() {
+        return;
+    }
+    let candidates = targeting.map_or(&[][..], |skill| book.target_types(skill));
+    for (typ, material) in gems.iter() {
+        if let Some(mat) = mats.get_mut(&material.handle) {
+            mat.emissive = if candidates.contains(typ) {
+                assets.color(*typ, settings.palette) * TARGET_GLOW
+            } else {
+                material.emissive
+            };
+        }
+    }
+}
+
+// What a skill costs and does
+struct SkillDefinition {
+    typ: SkillType,
+    cost: Vec<(GemType, u32)>,
+    effect: SkillEffect,
+}
+
+// Resource containing every skill that can be cast, in the order the sidebar lists them
+#[derive(Deref, DerefMut)]
+struct SkillBook(Vec<SkillDefinition>);
+
+/// Skill costs read from the assets in place of the built-in ones, the skills it leaves out keep
+/// theirs
+const SKILLS_CONFIG: &str = "skills.ron";
+
+/// A skill's cost as written in `SKILLS_CONFIG`
+#[derive(Deserialize)]
+struct SkillCost {
+    skill: SkillType,
+    cost: Vec<(GemType, u32)>,
+}
+
+impl SkillBook {
+    /// The built-in skills, costing whatever `SKILLS_CONFIG` sets for them
+    fn load() -> Self {
+        let mut book = Self::default();
+        let config = asset_path(SKILLS_CONFIG);
+        if !config.exists() {
+            info!("No {SKILLS_CONFIG} found, using the built-in skill costs");
+            return book;
+        }
+        let configured = fs::read_to_string(config)
+            .map_err(|err| err.to_string())
+            .and_then(|contents| {
+                ron::from_str::<Vec<SkillCost>>(&contents).map_err(|err| err.to_string())
+            });
+        let costs = match configured {
+            Ok(costs) => costs,
+            Err(err) => {
+                warn!("Ignoring {SKILLS_CONFIG}, using the built-in skill costs: {err}");
+                return book;
+            }
+        };
+        for SkillCost { skill, cost } in costs {
+            if let Some((typ, _)) = cost.iter().find(|(typ, _)| mana_cap(*typ) == 0) {
+                warn!("Ignoring the {skill} cost in {SKILLS_CONFIG}, {typ} is never stored");
+                continue;
+            }
+            if let Some(definition) = book.iter_mut().find(|definition| definition.typ == skill) {
+                definition.cost = cost;
+            }
+        }
+        book
+    }
+
+    fn get(&self, skill: SkillType) -> Option<&SkillDefinition> {
+        self.iter().find(|definition| definition.typ == skill)
+    }
+
+    /// The mana `skill` costs, nothing if it's free or not in the book
+    fn cost(&self, skill: SkillType) -> &[(GemType, u32)] {
+        self.get(skill)
+            .map_or(&[], |definition| definition.cost.as_slice())
+    }
+
+    fn target_types(&self, skill: SkillType) -> &'static [GemType] {
+        self.get(skill)
+            .map_or(&[], |definition| definition.effect.target_types())
+    }
+}
+
+impl Default for SkillBook {
+    fn default() -> Self {
+        Self(vec![
+            SkillDefinition {
+                typ: SkillType::Bamboozle,
+                cost: Vec::new(),
+                effect: SkillEffect::Shuffle,
+            },
+            SkillDefinition {
+                typ: SkillType::Bonk,
+                cost: vec![(GemType::Equipment, 3)],
+                effect: SkillEffect::Damage(BONK_DAMAGE),
+            },
+            SkillDefinition {
+                typ: SkillType::Heal,
+                cost: vec![(GemType::Amethyst, 3)],
+                effect: SkillEffect::Heal(HEAL_AMOUNT),
+            },
+            SkillDefinition {
+                typ: SkillType::Purge,
+                cost: vec![(GemType::Sapphire, 6)],
+                effect: SkillEffect::PopType,
+            },
+            SkillDefinition {
+                typ: SkillType::Cataclysm,
+                cost: vec![
+                    (GemType::Ruby, 10),
+                    (GemType::Emerald, 10),
+                    (GemType::Sapphire, 10),
+                    (GemType::Topaz, 10),
+                ],
+                effect: SkillEffect::PopAll,
+            },
+            SkillDefinition {
+                typ: SkillType::Transmute,
+                cost: vec![(GemType::Diamond, 8)],
+                effect: SkillEffect::Convert {
+                    from: GemType::Skull,
+                    to: GemType::Ruby,
+                },
+            },
+            SkillDefinition {
+                typ: SkillType::Venom,
+                cost: vec![(GemType::Emerald, 5)],
+                effect: SkillEffect::Afflict(StatusEffect {
+                    kind: StatusKind::Poison,
+                    magnitude: 3,
+                    turns: 4,
+                }),
+            },
+            SkillDefinition {
+                typ: SkillType::Scorch,
+                cost: vec![(GemType::Ruby, 5)],
+                effect: SkillEffect::Afflict(StatusEffect {
+                    kind: StatusKind::Burn,
+                    magnitude: 4,
+                    turns: 3,
+                }),
+            },
+            SkillDefinition {
+                typ: SkillType::Daze,
+                cost: vec![(GemType::Topaz, 7)],
+                effect: SkillEffect::Afflict(StatusEffect {
+                    kind: StatusKind::Stun,
+                    magnitude: 0,
+                    turns: 1,
+                }),
+            },
+            SkillDefinition {
+                typ: SkillType::Stoneskin,
+                cost: vec![(GemType::Sapphire, 4)],
+                effect: SkillEffect::Bolster(StatusEffect {
+                    kind: StatusKind::Guard,
+                    magnitude: 3,
+                    turns: 3,
+                }),
+            },
+        ])
+    }
+}
+
+fn cost_label(cost: &[(GemType, u32)]) -> String {
+    if cost.is_empty() {
+        return "free".to_string();
+    }
+    cost.iter()
+        .map(|(typ, amount)| format!("{amount}{}", resource_name(*typ)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// What a cost in `typ` is paid with, equipment costs are paid in gold
+fn resource_name(typ: GemType) -> String {
+    match typ {
+        GemType::Equipment => "gold".to_string(),
+        typ => typ.to_string().to_lowercase(),
+    }
+}
+
+const BONK_DAMAGE: u32 = 10;
+const HEAL_AMOUNT: u32 = 15;
+
+fn skills(
+    mut commands: Commands,
+    mut board_commands: ResMut<BoardCommands>,
+    mut state: ResMut<State<TurnState>>,
+    mut skills: EventReader<Skill>,
+    book: Res<SkillBook>,
+    settings: Res<Settings>,
+    mut board: ResMut<Board>,
+    mut users: Query<&mut Resources>,
+    mut sides: Query<(&mut Health, &CombatStats, &mut StatusEffects)>,
+    mut crits: Crits,
+    gems: Query<(Entity, &Transform), With<GemType>>,
+    mut spawner: GemSpawner,
+    index: Res<SlotIndex>,
+    mut slots: Query<(&Transform, &mut GemSlot)>,
+    mut tweens: ResMut<BoardTweens>,
+    foes: Foes,
+) {
+    for skill in skills.iter() {
+        let mut resources = match users.get_mut(skill.source) {
+            Ok(resources) => resources,
+            Err(_) => continue,
+        };
+        let definition = match book.get(skill.typ) {
+            Some(definition) => definition,
+            None => continue,
+        };
+        if (!definition.effect.target_types().is_empty() && skill.target.is_none())
+            || !resources.try_spend(&definition.cost)
+        {
+            continue;
+        }
+        match definition.effect {
+            SkillEffect::Shuffle => {
+                info!("{:?} did a heckin bamboozle", skill.source);
+                board_commands.push(BoardCommand::Shuffle).unwrap();
+                state.set(TurnState::Resolving).unwrap();
+            }
+            SkillEffect::Damage(amount) => {
+                info!("{:?} bonked", skill.source);
+                let crit_chance = sides
+                    .get(skill.source)
+                    .map_or(0.0, |(_, stats, _)| stats.crit_chance);
+                let (amount, critical) = crits.roll(amount, crit_chance);
+                if let Some(Ok((mut health, stats, status))) =
+                    foes.of(skill.source).map(|target| sides.get_mut(target))
+                {
+                    let amount = damage_after_defense(amount, stats.defense(&status));
+                    health.damage(amount);
+                    // Skills don't hit anywhere in particular, show it over the middle of the board
+                    if critical {
+                        crits.announce(Vec3::ZERO, amount);
+                    }
+                }
+            }
+            SkillEffect::Heal(amount) => {
+                info!("{:?} did a healz", skill.source);
+                if let Ok((mut health, _, _)) = sides.get_mut(skill.source) {
+                    health.heal(amount);
+                }
+            }
+            SkillEffect::PopType => {
+                let target = skill.target.unwrap();
+                info!("{:?} purged every {target}", skill.source);
+                board_commands
+                    .push(BoardCommand::Pop(
+                        board
+                            .iter()
+                            .filter(|(_, typ)| GemType::from(**typ as u8) == target)
+                            .map(|(pos, _)| *pos)
+                            .collect(),
+                    ))
+                    .unwrap();
+                state.set(TurnState::Resolving).unwrap();
+            }
+            SkillEffect::PopAll => {
+                info!("{:?} brought about the cataclysm", skill.source);
+                // Swell every gem in a wave outwards from the center, the pops are only
+                // processed once the last gem has finished
+                for (gem, transform) in gems.iter() {
+                    let distance = transform.translation.truncate().length();
+                    tweens.start(
+                        &mut commands,
+                        gem,
+                        Tween::new(
+                            EaseFunction::QuadraticIn,
+                            TweeningType::Once,
+                            settings.motion_duration(0.2 + distance * 0.5),
+                            TransformScaleLens {
+                                start: Vec3::ONE,
+                                end: Vec3::splat(1.3),
+                            },
+                        ),
+                    );
+                }
+                board_commands
+                    .push(BoardCommand::Pop(
+                        board.iter().map(|(pos, _)| *pos).collect(),
+                    ))
+                    .unwrap();
+                state.set(TurnState::Resolving).unwrap();
+            }
+            SkillEffect::Convert { from, to } => {
+                info!("{:?} turned every {from} into {to}", skill.source);
+                let (converted_board, converted) = convert(&board, from, to);
+                *board = converted_board;
+                for pos in converted {
+                    let (transform, mut slot) = slots.get_mut(index[&pos]).unwrap();
+                    if let Some(gem) = slot.gem {
+                        commands.entity(gem).despawn_recursive();
+                    }
+                    let state = spawner.roll_lock();
+                    slot.gem = Some(spawn_gem(
+                        &mut commands,
+                        transform.translation,
+                        to,
+                        state,
+                        &spawner,
+                    ));
+                }
+                // The board only looks for matches after its own moves, pop the new lines for it
+                // and let the cascade that follows resolve like any other
+                let lined = lines_of_three(&board);
+                if !lined.is_empty() {
+                    board_commands.push(BoardCommand::Pop(lined)).unwrap();
+                    state.set(TurnState::Resolving).unwrap();
+                }
+            }
+            SkillEffect::Afflict(effect) => {
+                info!("{:?} inflicted {}", skill.source, effect.kind);
+                if let Some(Ok((_, _, mut status))) =
+                    foes.of(skill.source).map(|target| sides.get_mut(target))
+                {
+                    status.apply(effect);
+                }
+            }
+            SkillEffect::Bolster(effect) => {
+                info!("{:?} took on {}", skill.source, effect.kind);
+                if let Ok((_, _, mut status)) = sides.get_mut(skill.source) {
+                    status.apply(effect);
+                }
+            }
+        }
+    }
+}
+
+// The board with every gem of type `from` turned into `to`, and where they were
+fn convert(board: &Board, from: GemType, to: GemType) -> (Board, Vec<UVec2>) {
+    let mut rows = board_rows(board);
+    let mut converted = Vec::new();
+    for (y, row) in rows.iter_mut().enumerate() {
+        for (x, typ) in row.iter_mut().enumerate() {
+            if GemType::from(*typ as u8) == from {
+                *typ = to as u32;
+                converted.push(UVec2::new(x as u32, y as u32));
+            }
+        }
+    }
+    (Board::from(rows), converted)
+}
+
+// Resource containing the skills the player wants cast as soon as they can afford them
+#[derive(Default, Deref, DerefMut)]
+struct AutoCast(HashSet<SkillType>);
+
+fn auto_cast_toggle(ui: &mut egui::Ui, auto_cast: &mut AutoCast, skill: SkillType) {
+    let mut enabled = auto_cast.contains(&skill);
+    if ui.checkbox(&mut enabled, "Auto").changed() {
+        if enabled {
+            auto_cast.insert(skill);
+        } else {
+            auto_cast.remove(&skill);
+        }
+    }
+}
+
+fn auto_cast(
+    auto_cast: Res<AutoCast>,
+    book: Res<SkillBook>,
+    state: Res<State<TurnState>>,
+    paused: Res<Paused>,
+    turn: Res<Turn>,
+    mut skills: EventWriter<Skill>,
+    player: Query<(Entity, &Resources, &Health), With<Player>>,
+) {
+    let (player, resources, health) = player.single();
+    if **paused || **turn != player || state.current() != &TurnState::AwaitingMove {
+        return;
+    }
+    // Cast at most one skill per frame so the same mana is never spent twice before `skills` runs
+    if let Some(skill) = auto_cast.iter().copied().find(|skill| {
+        let cost = book.cost(*skill);
+        // Healing at full health would only throw the mana away
+        let wasted = book.get(*skill).is_some_and(|definition| {
+            matches!(definition.effect, SkillEffect::Heal(_)) && health.current >= health.max
+        });
+        !cost.is_empty()
+            && !wasted
+            && book.target_types(*skill).is_empty()
+            && resources.can_afford(cost)
+    }) {
+        skills.send(Skill {
+            typ: skill,
+            source: player,
+            target: None,
+        });
+    }
+}
+
+// Resource containing the entity whose turn it is
+#[derive(DerefMut, Deref)]
+struct Turn(Entity);
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
 enum TurnState {
@@ -912,13 +4402,7 @@ fn determine_starter(commands: &mut Commands, starter: Entity) {
     commands.insert_resource(Turn(starter));
 }
 
-fn turn_switched(
-    turn: Res<Turn>,
-    board: Res<Board>,
-    mut board_commands: ResMut<BoardCommands>,
-    mut resources: Query<&mut Resources>,
-    player: Query<(), With<Player>>,
-) {
+fn turn_switched(turn: Res<Turn>, player: Query<(), With<Player>>) {
     if turn.is_changed() {
         info!(
             "Turn changed to {:?}",
@@ -928,36 +4412,790 @@ fn turn_switched(
                 "opponent"
             }
         );
-        if board.get_matching_moves().is_empty() {
-            for mut resource in resources.iter_mut() {
-                resource.clear();
+    }
+}
+
+const MAX_RESHUFFLES: u32 = 10;
+
+// Shuffles the board whenever it settles without a single valid move, giving up after
+// `MAX_RESHUFFLES` fruitless shuffles in a row rather than looping forever
+fn reshuffle_when_stuck(
+    mut settled: EventReader<BoardSettled>,
+    mode: Res<GameMode>,
+    mut turn_state: ResMut<State<TurnState>>,
+    mut attempts: Local<u32>,
+    board: Res<Board>,
+    mut valid_moves: ResMut<ValidMoves>,
+    mut board_commands: ResMut<BoardCommands>,
+    mut resources: Query<&mut Resources>,
+) {
+    if settled.iter().count() == 0 {
+        return;
+    }
+    if !valid_moves.get(&board).is_empty() {
+        *attempts = 0;
+        return;
+    }
+    if *attempts >= MAX_RESHUFFLES {
+        warn!("Still no valid moves after {MAX_RESHUFFLES} reshuffles, giving up");
+        return;
+    }
+    *attempts += 1;
+    // A dead board drains everyone's mana in a real duel
+    if *mode == GameMode::Classic && *attempts == 1 {
+        for mut resource in resources.iter_mut() {
+            resource.clear();
+        }
+    }
+    board_commands.push(BoardCommand::Shuffle).unwrap();
+    turn_state.set(TurnState::Resolving).unwrap();
+}
+
+const OPPONENT_THINK_SECONDS: f32 = 0.8;
+
+/// What the opponent has decided to do once it's done thinking
+#[derive(Clone, Copy)]
+enum OpponentPlan {
+    Swap(UVec2, UVec2),
+    Cast(SkillType, Option<GemType>),
+}
+
+// Resource containing the opponent's next action, so it can be shown before it happens
+#[derive(Default, Deref, DerefMut)]
+struct OpponentIntent(Option<OpponentPlan>);
+
+impl OpponentIntent {
+    fn describe(&self, board: &Board) -> String {
+        match **self {
+            None => "Waiting".to_string(),
+            Some(OpponentPlan::Cast(skill, Some(target))) => format!("Casting {skill} on {target}"),
+            Some(OpponentPlan::Cast(skill, None)) => format!("Casting {skill}"),
+            Some(OpponentPlan::Swap(from, to)) => {
+                let direction = match (to.x.cmp(&from.x), to.y.cmp(&from.y)) {
+                    (Ordering::Less, _) => "left",
+                    (Ordering::Greater, _) => "right",
+                    (_, Ordering::Less) => "up",
+                    (_, Ordering::Equal | Ordering::Greater) => "down",
+                };
+                match board.iter().find(|(pos, _)| **pos == from) {
+                    Some((_, typ)) => {
+                        format!("Swapping a {} {direction}", GemType::from(*typ as u8))
+                    }
+                    None => format!("Swapping {direction}"),
+                }
             }
-            board_commands.push(BoardCommand::Shuffle).unwrap();
         }
     }
 }
 
 fn opponent_ai(
+    time: Res<Time>,
+    mut thinking: Local<Option<Timer>>,
+    mut intent: ResMut<OpponentIntent>,
+    mode: Res<GameMode>,
     turn: Res<Turn>,
     mut turn_state: ResMut<State<TurnState>>,
     board: Res<Board>,
+    mut valid_moves: ResMut<ValidMoves>,
     mut board_commands: ResMut<BoardCommands>,
-    opponent: Query<(), (With<Resources>, Without<Player>)>,
+    book: Res<SkillBook>,
+    mut skills: EventWriter<Skill>,
+    opponent: Query<(&Resources, &Health, &OpponentDefinition), Without<Player>>,
+    player_health: Query<&Health, With<Player>>,
+    paused: Res<Paused>,
 ) {
-    if opponent.get(turn.0).is_err() || turn_state.current() == &TurnState::Resolving {
+    if **paused {
         return;
     }
-    let possible_matches = board.get_matching_moves();
-    let matching_moves = possible_matches.iter().collect::<Vec<_>>();
-    let choice = fastrand::usize(..matching_moves.len());
-    let choice = matching_moves.get(choice).unwrap();
-    board_commands
-        .push(BoardCommand::Swap(choice.0, choice.1))
-        .unwrap();
-    turn_state.set(TurnState::Resolving).unwrap();
+    let (resources, health, definition) = match opponent.get(turn.0) {
+        Ok(opponent) => opponent,
+        Err(_) => {
+            *thinking = None;
+            **intent = None;
+            return;
+        }
+    };
+    if *mode != GameMode::Classic || turn_state.current() == &TurnState::Resolving {
+        *thinking = None;
+        **intent = None;
+        return;
+    }
+    // Decide straight away and wait a moment before acting so the player can see it coming
+    if thinking.is_none() {
+        **intent = plan_opponent_move(
+            definition,
+            resources,
+            health,
+            player_health.single(),
+            &book,
+            &board,
+            &mut valid_moves,
+        );
+    }
+    if !thinking
+        .get_or_insert_with(|| Timer::from_seconds(OPPONENT_THINK_SECONDS, false))
+        .tick(time.delta())
+        .finished()
+    {
+        return;
+    }
+    *thinking = None;
+    match intent.take() {
+        Some(OpponentPlan::Cast(typ, target)) => skills.send(Skill {
+            typ,
+            source: turn.0,
+            target,
+        }),
+        Some(OpponentPlan::Swap(from, to)) => {
+            board_commands.push(BoardCommand::Swap(from, to)).unwrap();
+            turn_state.set(TurnState::Resolving).unwrap();
+        }
+        None => {}
+    }
+}
+
+/// Worth of a plain match to the AI, a cast has to beat this to be picked over matching
+const MATCH_VALUE: u32 = 5;
+
+// Picks the opponent's next action by weighing its best affordable ability against its best match
+fn plan_opponent_move(
+    definition: &OpponentDefinition,
+    resources: &Resources,
+    health: &Health,
+    player_health: &Health,
+    book: &SkillBook,
+    board: &Board,
+    valid_moves: &mut ValidMoves,
+) -> Option<OpponentPlan> {
+    let mut on_board: HashMap<GemType, u32> = HashMap::default();
+    for (_, typ) in board.iter() {
+        *on_board.entry(GemType::from(*typ as u8)).or_default() += 1;
+    }
+    let count = |typ: GemType| on_board.get(&typ).copied().unwrap_or_default();
+
+    let best_match = best_swap(board, valid_moves)
+        .map(|((from, to), value)| (OpponentPlan::Swap(from, to), value));
+
+    // Free abilities are left alone, nothing would stop them being cast every frame
+    let best_cast = (definition.difficulty == AiDifficulty::Normal)
+        .then(|| {
+            definition
+                .abilities
+                .iter()
+                .filter_map(|ability| book.get(*ability))
+                .filter(|ability| !ability.cost.is_empty() && resources.can_afford(&ability.cost))
+                .map(|ability| {
+                    let target = ability
+                        .effect
+                        .target_types()
+                        .iter()
+                        .copied()
+                        .max_by_key(|typ| count(*typ));
+                    let value = match ability.effect {
+                        SkillEffect::Shuffle => 0,
+                        // Always take the killing blow
+                        SkillEffect::Damage(amount) if amount >= player_health.current => u32::MAX,
+                        SkillEffect::Damage(amount) => amount,
+                        SkillEffect::Heal(amount) => amount.min(health.max - health.current),
+                        SkillEffect::PopType => target.map_or(0, count),
+                        SkillEffect::PopAll => on_board.values().sum::<u32>() / 2,
+                        SkillEffect::Convert { from, .. } => count(from),
+                        SkillEffect::Afflict(effect) | SkillEffect::Bolster(effect) => {
+                            effect.value()
+                        }
+                    };
+                    (OpponentPlan::Cast(ability.typ, target), value)
+                })
+                .max_by_key(|(_, value)| *value)
+        })
+        .flatten();
+
+    match (best_cast, best_match) {
+        (Some((cast, cast_value)), Some((_, match_value))) if cast_value > match_value => {
+            Some(cast)
+        }
+        (Some((cast, cast_value)), None) if cast_value > 0 => Some(cast),
+        (_, Some((swap, _))) => Some(swap),
+        _ => None,
+    }
+}
+
+/// The matching move worth the most along with its worth, shared by the opponent AI and the
+/// player's auto-match assist
+fn best_swap(board: &Board, valid_moves: &mut ValidMoves) -> Option<((UVec2, UVec2), u32)> {
+    // Lining up skulls hurts the other side, anything else only builds mana
+    valid_moves
+        .get(board)
+        .iter()
+        .copied()
+        .map(|(from, to)| {
+            let moves_skull = board
+                .iter()
+                .any(|(pos, typ)| (*pos == from || *pos == to) && *typ == GemType::Skull as u32);
+            let value = if moves_skull {
+                3 * SKULL_DAMAGE
+            } else {
+                MATCH_VALUE
+            };
+            ((from, to), value)
+        })
+        .max_by_key(|(_, value)| *value)
 }
 
 fn approx_equal(a: f32, b: f32) -> bool {
     let margin = f32::EPSILON;
     (a - b).abs() < margin
 }
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use bevy::asset::AssetPlugin;
+    use bevy_tweening::{component_animator_system, TweenCompleted};
+
+    use super::*;
+    use crate::assets::GemLook;
+
+    /// Most frames a test waits for the board to come to rest before giving up on it
+    const MAX_FRAMES: usize = 2000;
+
+    /// A windowless game holding just the board, the systems resolving it and a player whose turn
+    /// it always is. Animations snap to their end as under reduced motion so cascades run quickly
+    fn board_app() -> App {
+        board_app_with(6)
+    }
+
+    /// Like `board_app`, with the first `gem_types` gem types on the board
+    fn board_app_with(gem_types: u32) -> App {
+        board_app_from(gem_types, BoardSeed::default())
+    }
+
+    /// Like `board_app_with`, laid out from `seed`
+    fn board_app_from(gem_types: u32, seed: BoardSeed) -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(AssetPlugin)
+            .add_asset::<Gltf>()
+            .add_event::<TweenCompleted>()
+            .add_system(component_animator_system::<Transform>)
+            .insert_resource(Match3Config {
+                gem_types,
+                board_dimensions: UVec2::new(8, 8),
+            })
+            .add_plugin(Match3Plugin)
+            .insert_resource(Settings {
+                reduced_motion: true,
+                ..default()
+            })
+            .insert_resource(GameMode::Zen)
+            .insert_resource(GemAssets {
+                looks: GemType::iter()
+                    .map(|typ| (typ, GemLook::from(typ)))
+                    .collect(),
+                ..default()
+            })
+            .init_resource::<GravityDirection>()
+            .insert_resource(seed)
+            .init_resource::<MatchSeed>()
+            .init_resource::<CombatRng>()
+            .init_resource::<SpawnRng>()
+            .init_resource::<EncounterRng>()
+            .init_resource::<Obstacles>()
+            .init_resource::<RarityTable>()
+            .init_resource::<GemEffects>()
+            .init_resource::<ActiveScoreRule>()
+            .init_resource::<Paused>()
+            .init_resource::<ChainReplay>()
+            .init_resource::<ValidMoves>()
+            .init_resource::<BoardTweens>()
+            .init_resource::<Lineup>()
+            .init_resource::<TargetedEnemy>()
+            .add_event::<GemsPopped>()
+            .add_event::<CriticalHit>()
+            .add_event::<MoveResolved>()
+            .add_event::<BoardSettled>()
+            .add_state(TurnState::AwaitingMove)
+            .add_startup_system(generate_board)
+            .add_startup_system_to_stage(StartupStage::PostStartup, spawn_board)
+            .add_system(settle_board_tweens.before(gem_events))
+            .add_system(gem_events);
+        let player = app
+            .world
+            .spawn()
+            .insert_bundle((
+                Player,
+                Resources::default(),
+                Health::new(MAX_HEALTH),
+                Score::default(),
+                Collected::default(),
+                StatusEffects::default(),
+                CombatStats::player(),
+            ))
+            .id();
+        app.insert_resource(Turn(player));
+        app.update();
+        app
+    }
+
+    /// Runs frames until everything set off has resolved and the board has come to rest
+    fn resolve(app: &mut App) {
+        for _ in 0..MAX_FRAMES {
+            thread::sleep(Duration::from_millis(1));
+            app.update();
+            let settled = app.world.get_resource::<BoardTweens>().unwrap().settled();
+            let awaiting = app
+                .world
+                .get_resource::<State<TurnState>>()
+                .unwrap()
+                .current()
+                == &TurnState::AwaitingMove;
+            if settled && awaiting {
+                return;
+            }
+        }
+        panic!("The board never came to rest");
+    }
+
+    /// Makes the matching move nearest the top left of the board the way the player would, `false`
+    /// if there aren't any
+    fn swap_any(app: &mut App) -> bool {
+        let swap = app
+            .world
+            .get_resource::<Board>()
+            .unwrap()
+            .get_matching_moves()
+            .iter()
+            .map(|matching| (matching.0, matching.1))
+            .min_by_key(|(from, to)| (from.y, from.x, to.y, to.x));
+        let (from, to) = match swap {
+            Some(swap) => swap,
+            None => return false,
+        };
+        app.world
+            .get_resource_mut::<BoardCommands>()
+            .unwrap()
+            .push(BoardCommand::Swap(from, to))
+            .unwrap();
+        app.world
+            .get_resource_mut::<State<TurnState>>()
+            .unwrap()
+            .set(TurnState::Resolving)
+            .unwrap();
+        true
+    }
+
+    /// Positions whose slot doesn't hold a gem of the type the board has there
+    fn mirror_mismatches(app: &App) -> Vec<UVec2> {
+        let world = &app.world;
+        let index = world.get_resource::<SlotIndex>().unwrap();
+        world
+            .get_resource::<Board>()
+            .unwrap()
+            .iter()
+            .filter(|(pos, typ)| {
+                let shown = world
+                    .get::<GemSlot>(index[*pos])
+                    .and_then(|slot| slot.gem)
+                    .and_then(|gem| world.get::<GemType>(gem))
+                    .map(|gem| *gem as u32);
+                shown != Some(**typ)
+            })
+            .map(|(pos, _)| *pos)
+            .collect()
+    }
+
+    #[test]
+    fn slots_mirror_the_board_through_swaps_and_cascades() {
+        let mut app = board_app();
+        assert_eq!(mirror_mismatches(&app), Vec::<UVec2>::new());
+        for _ in 0..25 {
+            if !swap_any(&mut app) {
+                break;
+            }
+            resolve(&mut app);
+            assert_eq!(mirror_mismatches(&app), Vec::<UVec2>::new());
+        }
+    }
+
+    #[test]
+    fn a_seeded_match_refills_the_same_way() {
+        let play = || {
+            let mut seed = BoardSeed::default();
+            *seed = Some(7);
+            let mut app = board_app_from(6, seed);
+            for _ in 0..10 {
+                if !swap_any(&mut app) {
+                    break;
+                }
+                resolve(&mut app);
+            }
+            assert_eq!(mirror_mismatches(&app), Vec::<UVec2>::new());
+            board_rows(app.world.get_resource::<Board>().unwrap())
+        };
+        assert_eq!(play(), play());
+    }
+
+    #[test]
+    fn lines_are_counted_without_the_wildcards_in_them() {
+        let row = |xs: &[u32]| {
+            xs.iter()
+                .map(|x| (UVec2::new(*x, 2), GemType::Ruby))
+                .collect::<Vec<_>>()
+        };
+        // A wildcard in the middle of three leaves two apart
+        assert_eq!(lined_up(&row(&[0, 2])), Vec::<UVec2>::new());
+        // One at the end of four still leaves a line of three
+        assert_eq!(
+            lined_up(&row(&[0, 1, 2])),
+            row(&[0, 1, 2])
+                .iter()
+                .map(|(pos, _)| *pos)
+                .collect::<Vec<_>>()
+        );
+        // And a line of another type next to it isn't one of them
+        let mut mixed = row(&[0, 1]);
+        mixed.push((UVec2::new(2, 2), GemType::Emerald));
+        assert_eq!(lined_up(&mixed), Vec::<UVec2>::new());
+    }
+
+    /// Positions whose indexed slot isn't the slot at that position
+    fn index_mismatches(app: &App) -> Vec<UVec2> {
+        let world = &app.world;
+        world
+            .get_resource::<SlotIndex>()
+            .unwrap()
+            .iter()
+            .filter(|(pos, slot)| world.get::<GemSlot>(**slot).map(|slot| slot.pos) != Some(**pos))
+            .map(|(pos, _)| *pos)
+            .collect()
+    }
+
+    #[test]
+    fn slot_index_points_at_every_slot_through_swaps_and_drops() {
+        let mut app = board_app();
+        let dimensions = app
+            .world
+            .get_resource::<Match3Config>()
+            .unwrap()
+            .board_dimensions;
+        let index = app.world.get_resource::<SlotIndex>().unwrap();
+        assert_eq!(index.len() as u32, dimensions.x * dimensions.y);
+        assert_eq!(index_mismatches(&app), Vec::<UVec2>::new());
+        for _ in 0..25 {
+            if !swap_any(&mut app) {
+                break;
+            }
+            resolve(&mut app);
+            assert_eq!(index_mismatches(&app), Vec::<UVec2>::new());
+        }
+        let index = app.world.get_resource::<SlotIndex>().unwrap();
+        assert_eq!(index.len() as u32, dimensions.x * dimensions.y);
+    }
+
+    #[test]
+    fn a_tall_board_is_centred_on_the_origin() {
+        let dimensions = UVec2::new(6, 10);
+        let corners = [
+            (UVec2::new(0, 0), Vec3::new(-0.5, 0.9, 0.0)),
+            (UVec2::new(5, 0), Vec3::new(0.5, 0.9, 0.0)),
+            (UVec2::new(0, 9), Vec3::new(-0.5, -0.9, 0.0)),
+            (UVec2::new(5, 9), Vec3::new(0.5, -0.9, 0.0)),
+        ];
+        for (pos, expected) in corners {
+            let actual = gem_pos_from(pos, dimensions, GravityDirection::Down);
+            assert!(actual.abs_diff_eq(expected, 1e-5), "{pos} at {actual}");
+        }
+        // Falling left turns the top left corner to the top right
+        let turned = gem_pos_from(UVec2::ZERO, dimensions, GravityDirection::Left);
+        assert!(
+            turned.abs_diff_eq(Vec3::new(0.9, 0.5, 0.0), 1e-5),
+            "{turned}"
+        );
+    }
+
+    #[test]
+    fn gem_types_come_from_their_discriminants() {
+        // Gem types only print through `Display`, so they're compared rather than asserted equal
+        assert!(GemType::try_from(0) == Ok(GemType::Ruby));
+        assert!(GemType::try_from(7) == Ok(GemType::Equipment));
+        assert!(GemType::try_from(8) == Err(8));
+        for typ in GemType::iter() {
+            assert!(GemType::try_from(typ as u8) == Ok(typ), "{typ}");
+        }
+    }
+
+    #[test]
+    fn mana_stops_at_its_cap() {
+        let mut resources = Resources::default();
+        assert_eq!(resources.add(GemType::Ruby, 18), 0);
+        assert_eq!(resources.add(GemType::Ruby, 5), 3);
+        assert_eq!(resources.amount(GemType::Ruby), MANA_CAP);
+        assert_eq!(resources.add(GemType::Ruby, 1), 1);
+        assert_eq!(resources.amount(GemType::Ruby), MANA_CAP);
+    }
+
+    #[test]
+    fn skulls_are_never_stored() {
+        let mut resources = Resources::default();
+        assert_eq!(resources.add(GemType::Skull, 4), 4);
+        assert_eq!(resources.amount(GemType::Skull), 0);
+        assert!(!resources.mana.contains_key(&GemType::Skull));
+    }
+
+    #[test]
+    fn affording_a_skill_needs_every_part_of_its_cost() {
+        let mut resources = Resources::default();
+        resources.add(GemType::Ruby, 5);
+        resources.add(GemType::Sapphire, 2);
+        assert!(resources.can_afford(&[]));
+        assert!(resources.can_afford(&[(GemType::Ruby, 5)]));
+        assert!(resources.can_afford(&[(GemType::Ruby, 4), (GemType::Sapphire, 2)]));
+        assert!(!resources.can_afford(&[(GemType::Ruby, 6)]));
+        assert!(!resources.can_afford(&[(GemType::Ruby, 1), (GemType::Sapphire, 3)]));
+        assert!(!resources.can_afford(&[(GemType::Topaz, 1)]));
+    }
+
+    #[test]
+    fn converting_turns_only_the_one_type() {
+        let skull = GemType::Skull as u32;
+        let ruby = GemType::Ruby as u32;
+        let rows = vec![vec![skull, 1, 2], vec![3, skull, 4], vec![5, 1, skull]];
+        let (board, converted) = convert(&Board::from(rows.clone()), GemType::Skull, GemType::Ruby);
+        assert_eq!(
+            converted,
+            vec![UVec2::new(0, 0), UVec2::new(1, 1), UVec2::new(2, 2)]
+        );
+        let mut expected = rows;
+        for pos in &converted {
+            expected[pos.y as usize][pos.x as usize] = ruby;
+        }
+        assert_eq!(board_rows(&board), expected);
+    }
+
+    #[test]
+    fn transmuting_skulls_refills_the_board() {
+        // Skulls are only on a board with seven gem types, and even then not on every one
+        let mut app = (0..10)
+            .map(|_| board_app_with(7))
+            .find(|app| {
+                let board = app.world.get_resource::<Board>().unwrap();
+                board.iter().any(|(_, typ)| *typ == GemType::Skull as u32)
+            })
+            .expect("No board with skulls on it");
+        app.init_resource::<SkillBook>()
+            .add_event::<Skill>()
+            .add_system(skills.before(gem_events));
+        let player = **app.world.get_resource::<Turn>().unwrap();
+        let cost = app
+            .world
+            .get_resource::<SkillBook>()
+            .unwrap()
+            .cost(SkillType::Transmute)
+            .to_vec();
+        let mut resources = app.world.get_mut::<Resources>(player).unwrap();
+        for (typ, amount) in &cost {
+            resources.add(*typ, *amount);
+        }
+        app.world
+            .get_resource_mut::<Events<Skill>>()
+            .unwrap()
+            .send(Skill {
+                typ: SkillType::Transmute,
+                source: player,
+                target: None,
+            });
+        app.update();
+        let resources = app.world.get::<Resources>(player).unwrap();
+        assert!(!resources.can_afford(&cost), "Transmute was never cast");
+        resolve(&mut app);
+
+        let board = app.world.get_resource::<Board>().unwrap();
+        assert_eq!(board.iter().count(), 64);
+        assert_eq!(lines_of_three(board), Vec::<UVec2>::new());
+        assert_eq!(mirror_mismatches(&app), Vec::<UVec2>::new());
+    }
+
+    #[test]
+    fn a_stray_animator_doesnt_stall_the_board() {
+        let mut app = board_app();
+        // Loops forever like the selection wobble, so a board waiting on it would never settle
+        let gem = app
+            .world
+            .query_filtered::<Entity, With<GemType>>()
+            .iter(&app.world)
+            .next()
+            .unwrap();
+        app.world.entity_mut(gem).insert(Animator::new(Tween::new(
+            EaseFunction::SineInOut,
+            TweeningType::PingPong,
+            Duration::from_secs(1),
+            TransformScaleLens {
+                start: Vec3::ONE,
+                end: Vec3::splat(1.1),
+            },
+        )));
+        for _ in 0..5 {
+            if !swap_any(&mut app) {
+                break;
+            }
+            resolve(&mut app);
+        }
+        assert_eq!(mirror_mismatches(&app), Vec::<UVec2>::new());
+    }
+
+    #[test]
+    fn paused_tweens_hold_the_board_until_they_finish() {
+        let mut app = board_app();
+        let index = app.world.get_resource::<SlotIndex>().unwrap();
+        let slot = index[&UVec2::ZERO];
+        let gem = app.world.get::<GemSlot>(slot).unwrap().gem.unwrap();
+        let start = app.world.get::<Transform>(gem).unwrap().translation;
+        let mut animator = Animator::new(Tween::new(
+            EaseFunction::QuadraticIn,
+            TweeningType::Once,
+            Duration::from_millis(50),
+            TransformPositionLens {
+                start,
+                end: start + Vec3::X,
+            },
+        ));
+        // Frozen by the pause menu, which has already been closed again
+        animator.state = AnimatorState::Paused;
+        app.world.entity_mut(gem).insert(animator);
+        app.world
+            .get_resource_mut::<BoardTweens>()
+            .unwrap()
+            .gems
+            .insert(gem);
+        for _ in 0..10 {
+            thread::sleep(Duration::from_millis(10));
+            app.update();
+        }
+        assert!(!app.world.get_resource::<BoardTweens>().unwrap().settled());
+        assert!(app.world.get::<Animator<Transform>>(gem).is_some());
+
+        app.world.get_mut::<Animator<Transform>>(gem).unwrap().state = AnimatorState::Playing;
+        resolve(&mut app);
+        assert!(app.world.get::<Animator<Transform>>(gem).is_none());
+    }
+
+    /// How many times the board has announced it settled
+    #[derive(Default)]
+    struct SettledCount(usize);
+
+    fn count_settled(mut count: ResMut<SettledCount>, mut settled: EventReader<BoardSettled>) {
+        count.0 += settled.iter().count();
+    }
+
+    #[test]
+    fn the_board_settles_once_per_move() {
+        let mut app = board_app();
+        app.init_resource::<SettledCount>()
+            .add_system(count_settled.after(settle_board_tweens));
+        for _ in 0..25 {
+            app.world.get_resource_mut::<SettledCount>().unwrap().0 = 0;
+            if !swap_any(&mut app) {
+                break;
+            }
+            resolve(&mut app);
+            // Some frames more for the announcement and anything that might follow it
+            for _ in 0..20 {
+                thread::sleep(Duration::from_millis(1));
+                app.update();
+            }
+            assert_eq!(app.world.get_resource::<SettledCount>().unwrap().0, 1);
+        }
+    }
+
+    #[test]
+    fn defense_soaks_up_damage_down_to_the_minimum() {
+        assert_eq!(damage_after_defense(10, 0), 10);
+        assert_eq!(damage_after_defense(10, 3), 7);
+        assert_eq!(damage_after_defense(10, 10), MIN_DAMAGE);
+        assert_eq!(damage_after_defense(3, 50), MIN_DAMAGE);
+        // Nothing to hit with stays nothing
+        assert_eq!(damage_after_defense(0, 0), 0);
+        assert_eq!(damage_after_defense(0, 5), 0);
+    }
+
+    /// Rolls of `Crits` in a frame, as damage, chance, and what came of it
+    #[derive(Default)]
+    struct CritRolls(Vec<(u32, f32, Option<(u32, bool)>)>);
+
+    fn roll_crits(mut rolls: ResMut<CritRolls>, mut crits: Crits) {
+        for (damage, chance, rolled) in rolls.0.iter_mut() {
+            *rolled = Some(crits.roll(*damage, *chance));
+        }
+    }
+
+    fn rolled(rolls: Vec<(u32, f32)>) -> Vec<(u32, bool)> {
+        let mut app = App::new();
+        app.insert_resource(CombatRng::with_seed(7))
+            .insert_resource(CritRolls(
+                rolls
+                    .into_iter()
+                    .map(|(damage, chance)| (damage, chance, None))
+                    .collect(),
+            ))
+            .add_event::<CriticalHit>()
+            .add_system(roll_crits);
+        app.update();
+        let rolls = app.world.remove_resource::<CritRolls>().unwrap();
+        rolls
+            .0
+            .into_iter()
+            .map(|(.., rolled)| rolled.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn crits_multiply_the_damage_when_they_land() {
+        assert_eq!(
+            rolled(vec![(5, 1.0), (5, 0.0), (0, 1.0)]),
+            vec![(5 * CRIT_MULTIPLIER, true), (5, false), (0, false)]
+        );
+    }
+
+    #[test]
+    fn a_seeded_rng_rolls_the_same_crits() {
+        let rolls = vec![(4, 0.5); 32];
+        let first = rolled(rolls.clone());
+        assert_eq!(first, rolled(rolls));
+        // Even odds over that many rolls both land and miss
+        assert!(first.iter().any(|(_, critical)| *critical));
+        assert!(first.iter().any(|(_, critical)| !*critical));
+    }
+
+    #[test]
+    fn deselecting_puts_the_gem_back_at_rest() {
+        let mut app = board_app();
+        app.add_system(animate_selected);
+        // Wobbling back and forth rather than settling on the raised pose
+        app.world
+            .get_resource_mut::<Settings>()
+            .unwrap()
+            .reduced_motion = false;
+        let slot = app.world.get_resource::<SlotIndex>().unwrap()[&UVec2::ZERO];
+        let gem = app.world.get::<GemSlot>(slot).unwrap().gem.unwrap();
+
+        **app.world.get_resource_mut::<SelectedSlot>().unwrap() = Some(slot);
+        for _ in 0..10 {
+            thread::sleep(Duration::from_millis(10));
+            app.update();
+        }
+        assert!(app.world.get::<Animator<Transform>>(gem).is_some());
+        let transform = app.world.get::<Transform>(gem).unwrap();
+        assert!(transform.rotation != Quat::IDENTITY || transform.scale != Vec3::ONE);
+
+        **app.world.get_resource_mut::<SelectedSlot>().unwrap() = None;
+        for _ in 0..3 {
+            thread::sleep(Duration::from_millis(10));
+            app.update();
+        }
+        assert!(app.world.get::<Animator<Transform>>(gem).is_none());
+        let transform = app.world.get::<Transform>(gem).unwrap();
+        assert_eq!(transform.rotation, Quat::IDENTITY);
+        assert_eq!(transform.scale, Vec3::ONE);
+    }
+}
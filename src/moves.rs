@@ -0,0 +1,57 @@
+use bevy::{prelude::*, utils::HashSet};
+use bevy_match3::prelude::*;
+
+use crate::{BoardSettled, GemSlot, Locked};
+
+/// The matching moves on the current board, shared so hints, the AI and the stuck board checks
+/// only scan the board once each time it settles
+#[derive(Default)]
+pub struct ValidMoves {
+    moves: Option<Vec<(UVec2, UVec2)>>,
+    /// Slots that can't be swapped, moves involving them don't count
+    locked: HashSet<UVec2>,
+}
+
+impl ValidMoves {
+    pub fn get(&mut self, board: &Board) -> &[(UVec2, UVec2)] {
+        let locked = &self.locked;
+        self.moves.get_or_insert_with(|| {
+            board
+                .get_matching_moves()
+                .iter()
+                .map(|matching| (matching.0, matching.1))
+                .filter(|(from, to)| !locked.contains(from) && !locked.contains(to))
+                .collect()
+        })
+    }
+
+    pub fn invalidate(&mut self) {
+        self.moves = None;
+    }
+}
+
+/// Catches board changes that didn't come through `gem_events`, like the board being created
+pub fn invalidate_valid_moves(board: Res<Board>, mut moves: ResMut<ValidMoves>) {
+    if board.is_changed() {
+        moves.invalidate();
+    }
+}
+
+/// Keeps the locked slots up to date, dropping the cached moves whenever they change
+pub fn track_locked_slots(locked: Query<&GemSlot, With<Locked>>, mut moves: ResMut<ValidMoves>) {
+    let locked = locked.iter().map(|slot| slot.pos).collect::<HashSet<_>>();
+    if locked != moves.locked {
+        moves.locked = locked;
+        moves.invalidate();
+    }
+}
+
+pub fn cache_valid_moves(
+    mut settled: EventReader<BoardSettled>,
+    board: Res<Board>,
+    mut moves: ResMut<ValidMoves>,
+) {
+    if settled.iter().count() > 0 {
+        moves.get(&board);
+    }
+}
@@ -0,0 +1,120 @@
+use bevy::{prelude::*, utils::HashMap};
+
+use crate::{
+    assets::asset_path,
+    settings::{GameTrack, Settings},
+    sounds::Ducking,
+    GameState,
+};
+
+const CROSSFADE_SECONDS: f32 = 1.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Track {
+    Menu,
+    Game(GameTrack),
+}
+
+impl Track {
+    fn path(self) -> &'static str {
+        match self {
+            Track::Menu => "music/menu.wav",
+            Track::Game(GameTrack::Serene) => "music/serene.wav",
+            Track::Game(GameTrack::Battle) => "music/battle.wav",
+        }
+    }
+}
+
+// Resource containing the music that could be found, tracks without a file are left silent
+#[derive(Default)]
+pub struct MusicAssets(HashMap<Track, Handle<AudioSource>>);
+
+pub fn load_music(mut commands: Commands, ass: Res<AssetServer>) {
+    let mut music = MusicAssets::default();
+    for track in [
+        Track::Menu,
+        Track::Game(GameTrack::Serene),
+        Track::Game(GameTrack::Battle),
+    ] {
+        if asset_path(track.path()).exists() {
+            music.0.insert(track, ass.load(track.path()));
+        } else {
+            info!("No {} found, that track stays silent", track.path());
+        }
+    }
+    commands.insert_resource(music);
+}
+
+struct Playing {
+    track: Track,
+    sink: Option<Handle<AudioSink>>,
+    /// How far the track has faded in, from 0 to 1
+    fade: f32,
+}
+
+// Resource containing the track being played and the ones still fading out
+#[derive(Default)]
+pub struct Music {
+    current: Option<Playing>,
+    fading_out: Vec<Playing>,
+}
+
+pub fn play_music(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    state: Res<State<GameState>>,
+    assets: Res<MusicAssets>,
+    audio: Res<Audio>,
+    sinks: Res<Assets<AudioSink>>,
+    ducking: Res<Ducking>,
+    mut music: ResMut<Music>,
+) {
+    let wanted = match state.current() {
+        GameState::MainMenu | GameState::Settings | GameState::BoardSetup | GameState::Shop => {
+            Track::Menu
+        }
+        GameState::Game | GameState::GameOver => Track::Game(settings.game_track),
+    };
+    if music.current.as_ref().map(|playing| playing.track) != Some(wanted) {
+        // Picks up from the current volume, so switching again mid-fade doesn't jump
+        if let Some(previous) = music.current.take() {
+            music.fading_out.push(previous);
+        }
+        music.current =
+            Some(Playing {
+                track: wanted,
+                sink: assets.0.get(&wanted).map(|source| {
+                    sinks.get_handle(audio.play_with_settings(
+                        source.clone(),
+                        PlaybackSettings::LOOP.with_volume(0.0),
+                    ))
+                }),
+                fade: 0.0,
+            });
+    }
+
+    let step = time.delta_seconds() / CROSSFADE_SECONDS;
+    let volume = settings.music_level() * ducking.volume();
+    if let Some(playing) = music.current.as_mut() {
+        playing.fade = (playing.fade + step).min(1.0);
+        set_volume(playing, volume, &sinks);
+    }
+    music.fading_out.retain_mut(|playing| {
+        playing.fade -= step;
+        set_volume(playing, volume, &sinks);
+        if playing.fade > 0.0 {
+            return true;
+        }
+        // Dropping the last handle to the sink stops the track
+        if let Some(sink) = playing.sink.as_ref().and_then(|sink| sinks.get(sink)) {
+            sink.pause();
+        }
+        false
+    });
+}
+
+fn set_volume(playing: &Playing, volume: f32, sinks: &Assets<AudioSink>) {
+    if let Some(sink) = playing.sink.as_ref().and_then(|sink| sinks.get(sink)) {
+        sink.set_volume(volume * playing.fade.max(0.0));
+    }
+}
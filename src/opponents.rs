@@ -0,0 +1,122 @@
+use std::{fs, path::Path};
+
+use bevy::prelude::*;
+use serde::Deserialize;
+
+use crate::{assets::asset_path, seed::EncounterRng, GemType, SkillType, MAX_HEALTH};
+
+const OPPONENTS_DIR: &str = "opponents";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+pub enum AiDifficulty {
+    /// Only ever swaps gems
+    Easy,
+    /// Casts its abilities whenever it can afford them
+    Normal,
+}
+
+/// An opponent as described by a file in `assets/opponents`
+#[derive(Component, Clone, Deserialize)]
+pub struct OpponentDefinition {
+    pub name: String,
+    pub health: u32,
+    /// Taken off every hit the opponent takes
+    #[serde(default)]
+    pub defense: u32,
+    /// Chance from 0 to 1 of the opponent's hits landing as critical hits
+    #[serde(default)]
+    pub crit_chance: f32,
+    #[serde(default)]
+    pub starting_mana: Vec<(GemType, u32)>,
+    #[serde(default)]
+    pub abilities: Vec<SkillType>,
+    pub difficulty: AiDifficulty,
+    /// Said when the encounter starts
+    #[serde(default)]
+    pub greeting: Option<String>,
+}
+
+impl Default for OpponentDefinition {
+    fn default() -> Self {
+        Self {
+            name: "Opponent".to_string(),
+            health: MAX_HEALTH,
+            defense: 0,
+            crit_chance: 0.0,
+            starting_mana: Vec::new(),
+            abilities: Vec::new(),
+            difficulty: AiDifficulty::Easy,
+            greeting: None,
+        }
+    }
+}
+
+impl OpponentDefinition {
+    fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("name is empty".to_string());
+        }
+        if self.health == 0 {
+            return Err("health must be above zero".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.crit_chance) {
+            return Err("crit chance must be between 0 and 1".to_string());
+        }
+        if self
+            .starting_mana
+            .iter()
+            .any(|(typ, _)| *typ == GemType::Skull)
+        {
+            return Err("skulls are not mana".to_string());
+        }
+        for (i, ability) in self.abilities.iter().enumerate() {
+            if self.abilities[..i].contains(ability) {
+                return Err(format!("{ability} is listed twice"));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Resource containing every opponent an encounter can be against, never empty
+#[derive(Deref)]
+pub struct OpponentRoster(Vec<OpponentDefinition>);
+
+impl OpponentRoster {
+    pub fn pick(&self, rng: &mut EncounterRng) -> &OpponentDefinition {
+        &self[rng.usize(..self.len())]
+    }
+}
+
+pub fn load_opponents(mut commands: Commands) {
+    let mut roster = Vec::new();
+    match fs::read_dir(asset_path(OPPONENTS_DIR)) {
+        Ok(entries) => {
+            for path in entries.filter_map(|entry| Some(entry.ok()?.path())) {
+                if path
+                    .extension()
+                    .map_or(true, |extension| extension != "ron")
+                {
+                    continue;
+                }
+                match load_opponent(&path) {
+                    Ok(opponent) => roster.push(opponent),
+                    Err(err) => warn!("Skipping opponent {}: {err}", path.display()),
+                }
+            }
+        }
+        Err(err) => warn!("Couldn't read {OPPONENTS_DIR}: {err}"),
+    }
+    if roster.is_empty() {
+        info!("No opponents loaded, using the built-in one");
+        roster.push(OpponentDefinition::default());
+    }
+    commands.insert_resource(OpponentRoster(roster));
+}
+
+fn load_opponent(path: &Path) -> Result<OpponentDefinition, String> {
+    let contents = fs::read_to_string(path).map_err(|err| err.to_string())?;
+    let opponent: OpponentDefinition = ron::from_str(&contents).map_err(|err| err.to_string())?;
+    opponent.validate()?;
+    Ok(opponent)
+}
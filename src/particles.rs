@@ -0,0 +1,91 @@
+use bevy::prelude::*;
+
+use crate::{assets::GemAssets, settings::Settings, GemsPopped, Paused};
+
+const PARTICLES_PER_GEM: usize = 6;
+/// Most particles alive at once, a big cascade sparkles less rather than dropping frames
+const MAX_PARTICLES: usize = 240;
+const PARTICLE_SECONDS: f32 = 0.5;
+const PARTICLE_SIZE: f32 = 0.03;
+/// Fastest a particle leaves the gem at, slowest is half of it
+const PARTICLE_SPEED: f32 = 1.2;
+/// Share of its speed a particle keeps every second
+const PARTICLE_DRAG: f32 = 0.05;
+/// How far in front of the board particles fly
+const GEM_CLEARANCE: f32 = 0.15;
+
+// Resource containing the mesh every particle shares
+pub struct ParticleMesh(Handle<Mesh>);
+
+pub fn load_particle_mesh(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
+    let quad = meshes.add(Mesh::from(shape::Quad::new(Vec2::splat(PARTICLE_SIZE))));
+    commands.insert_resource(ParticleMesh(quad));
+}
+
+// A sparkle flying out of a popped gem, shrinking away until its timer runs out
+#[derive(Component)]
+pub struct Particle {
+    velocity: Vec3,
+    timer: Timer,
+}
+
+// Bursts sparkles in the gem's color out of every popped gem, as many as the particle density
+// setting asks for and none at all under reduced motion
+pub fn spawn_particles(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    assets: Res<GemAssets>,
+    mesh: Res<ParticleMesh>,
+    mut popped: EventReader<GemsPopped>,
+    particles: Query<(), With<Particle>>,
+) {
+    if settings.reduced_motion {
+        popped.iter().for_each(drop);
+        return;
+    }
+    let density = settings.particle_density.clamp(0.0, 1.0);
+    let per_gem = (PARTICLES_PER_GEM as f32 * density).round() as usize;
+    let max_particles = (MAX_PARTICLES as f32 * density).round() as usize;
+    let mut budget = max_particles.saturating_sub(particles.iter().count());
+    for (pos, typ) in popped.iter().flat_map(|popped| &popped.gems) {
+        for _ in 0..per_gem.min(budget) {
+            let angle = fastrand::f32() * std::f32::consts::TAU;
+            let speed = PARTICLE_SPEED * (0.5 + fastrand::f32() * 0.5);
+            commands
+                .spawn_bundle(PbrBundle {
+                    mesh: mesh.0.clone_weak(),
+                    material: assets.materials[*typ as usize].clone_weak(),
+                    // In front of the gems so they aren't hidden behind the ones around them
+                    transform: Transform::from_translation(*pos + Vec3::Z * GEM_CLEARANCE),
+                    ..default()
+                })
+                .insert(Particle {
+                    velocity: Vec3::new(angle.cos(), angle.sin(), 0.0) * speed,
+                    timer: Timer::from_seconds(PARTICLE_SECONDS, false),
+                });
+        }
+        budget = budget.saturating_sub(per_gem);
+    }
+}
+
+// Flies and shrinks the particles, holding them in place while paused
+pub fn move_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    paused: Res<Paused>,
+    mut particles: Query<(Entity, &mut Particle, &mut Transform)>,
+) {
+    if **paused {
+        return;
+    }
+    let delta = time.delta_seconds();
+    for (entity, mut particle, mut transform) in particles.iter_mut() {
+        if particle.timer.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        transform.translation += particle.velocity * delta;
+        particle.velocity *= PARTICLE_DRAG.powf(delta);
+        transform.scale = Vec3::splat(1.0 - particle.timer.percent());
+    }
+}
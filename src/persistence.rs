@@ -0,0 +1,58 @@
+use std::{fs, path::PathBuf};
+
+use bevy::prelude::*;
+use directories::ProjectDirs;
+use serde::{de::DeserializeOwned, Serialize};
+
+fn config_path(file: &str) -> Option<PathBuf> {
+    ProjectDirs::from("", "", "puzzle_quest_3").map(|dirs| dirs.config_dir().join(file))
+}
+
+/// Reads `file` from the config directory, `None` if it's missing or can't be parsed
+pub fn load<T: DeserializeOwned>(file: &str) -> Option<T> {
+    let path = config_path(file)?;
+    let contents = fs::read_to_string(&path).ok()?;
+    match ron::from_str(&contents) {
+        Ok(value) => Some(value),
+        Err(err) => {
+            warn!("Ignoring malformed {}: {err}", path.display());
+            None
+        }
+    }
+}
+
+/// Whether `file` exists in the config directory
+pub fn exists(file: &str) -> bool {
+    config_path(file).is_some_and(|path| path.exists())
+}
+
+/// Deletes `file` from the config directory if it's there
+pub fn remove(file: &str) {
+    if let Some(path) = config_path(file).filter(|path| path.exists()) {
+        if let Err(err) = fs::remove_file(&path) {
+            warn!("Failed to delete {}: {err}", path.display());
+        }
+    }
+}
+
+/// Writes `value` to `file` in the config directory, logging rather than failing
+pub fn save<T: Serialize>(file: &str, value: &T) {
+    let path = match config_path(file) {
+        Some(path) => path,
+        None => {
+            warn!("No config directory to save {file} to");
+            return;
+        }
+    };
+    let result = ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())
+        .map_err(|err| err.to_string())
+        .and_then(|contents| {
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+            }
+            fs::write(&path, contents).map_err(|err| err.to_string())
+        });
+    if let Err(err) = result {
+        warn!("Failed to save {}: {err}", path.display());
+    }
+}
@@ -0,0 +1,97 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_egui::egui::{self, Color32};
+
+use crate::{Health, Player};
+
+const HURT_SECONDS: f32 = 0.4;
+/// Furthest a hurt portrait shakes to either side, in points
+const SHAKE_AMPLITUDE: f32 = 6.0;
+/// Back and forth swings per second of a hurt portrait
+const SHAKE_FREQUENCY: f32 = 6.0;
+const FLASH_ALPHA: f32 = 0.5;
+/// Seconds for a thinking opponent's portrait to pulse once
+const THINKING_PULSE_SECONDS: f64 = 1.2;
+
+// Event sent whenever a side loses health, whatever took it
+pub struct SideHurt {
+    pub side: Entity,
+}
+
+// Watches every side's health, sending `SideHurt` whenever it drops
+pub fn detect_hurt(
+    mut last_seen: Local<HashMap<Entity, u32>>,
+    sides: Query<(Entity, &Health), Changed<Health>>,
+    mut hurt: EventWriter<SideHurt>,
+) {
+    for (side, health) in sides.iter() {
+        if let Some(before) = last_seen.insert(side, health.current) {
+            if health.current < before {
+                hurt.send(SideHurt { side });
+            }
+        }
+    }
+}
+
+// Resource containing how far along each hurt opponent portrait is in flinching
+#[derive(Default)]
+pub struct PortraitReactions(HashMap<Entity, Timer>);
+
+impl PortraitReactions {
+    /// The red flash over `opponent`'s portrait and how far to nudge it sideways, shaking only
+    /// when `shake` is set
+    pub fn hurt(&self, opponent: Entity, shake: bool) -> (Color32, f32) {
+        let timer = match self.0.get(&opponent) {
+            Some(timer) => timer,
+            None => return (Color32::TRANSPARENT, 0.0),
+        };
+        let fade = 1.0 - timer.percent();
+        let flash = Color32::DARK_RED.linear_multiply(FLASH_ALPHA * fade);
+        let offset = if shake {
+            let swing = timer.elapsed_secs() * SHAKE_FREQUENCY * std::f32::consts::TAU;
+            swing.sin() * SHAKE_AMPLITUDE * fade
+        } else {
+            0.0
+        };
+        (flash, offset)
+    }
+}
+
+pub fn react_to_hurt(
+    time: Res<Time>,
+    mut hurt: EventReader<SideHurt>,
+    mut reactions: ResMut<PortraitReactions>,
+    player: Query<(), With<Player>>,
+) {
+    reactions
+        .0
+        .retain(|_, timer| !timer.tick(time.delta()).finished());
+    for hurt in hurt.iter().filter(|hurt| player.get(hurt.side).is_err()) {
+        reactions
+            .0
+            .insert(hurt.side, Timer::from_seconds(HURT_SECONDS, false));
+    }
+}
+
+/// How strongly a thinking opponent's portrait outline shows right now, from 0.5 to 1
+pub fn thinking_pulse(time: &Time) -> f32 {
+    let phase = time.seconds_since_startup() / THINKING_PULSE_SECONDS * std::f64::consts::TAU;
+    0.75 + 0.25 * phase.sin() as f32
+}
+
+/// Trailing dots for a thinking opponent's intent, cycling from none to three
+pub fn thinking_dots(time: &Time) -> &'static str {
+    let dots = (time.seconds_since_startup() / THINKING_PULSE_SECONDS * 4.0) as usize % 4;
+    &"..."[..dots]
+}
+
+/// The frame a portrait is drawn in, nudged `offset` points sideways while keeping its width
+pub fn portrait_frame(style: &egui::Style, flash: Color32, offset: f32) -> egui::Frame {
+    egui::Frame::group(style)
+        .fill(flash)
+        .outer_margin(egui::style::Margin {
+            left: SHAKE_AMPLITUDE + offset,
+            right: SHAKE_AMPLITUDE - offset,
+            top: 0.0,
+            bottom: 0.0,
+        })
+}
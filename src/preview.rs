@@ -0,0 +1,106 @@
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{self, Color32, RichText},
+    EguiContext,
+};
+use bevy_match3::prelude::*;
+use bevy_mod_raycast::RayCastSource;
+use strum::IntoEnumIterator;
+
+use crate::{
+    damage_after_defense,
+    effects::{GemEffects, PopContext},
+    encounter::Foes,
+    resource_name,
+    status::StatusEffects,
+    CombatStats, GemSlot, GemType, InputBlockers, Player, RarityTable, RaycastSet, Resources,
+    SelectedSlot, Swapper, Turn, TurnState,
+};
+
+/// Distance from the cursor to the preview, in points
+const PREVIEW_OFFSET: egui::Vec2 = egui::vec2(16.0, 16.0);
+
+// Shows what swapping the selected gem with the one under the cursor would earn, as long as the
+// swap makes a match. Cascades can't be known ahead of time so only the first match counts
+pub fn swap_preview(
+    mut egui_ctx: ResMut<EguiContext>,
+    windows: Res<Windows>,
+    selected: Res<SelectedSlot>,
+    turn: Res<Turn>,
+    turn_state: Res<State<TurnState>>,
+    blockers: InputBlockers,
+    from: Query<&RayCastSource<RaycastSet>>,
+    slots: Query<&GemSlot>,
+    swapper: Swapper,
+    board: Res<Board>,
+    rarities: Res<RarityTable>,
+    effects: Res<GemEffects>,
+    foes: Foes,
+    player: Query<(Entity, &Resources), With<Player>>,
+    defenses: Query<(&CombatStats, &StatusEffects)>,
+) {
+    let (player, resources) = match player.get_single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    if **turn != player || turn_state.current() != &TurnState::AwaitingMove || blockers.blocked() {
+        return;
+    }
+    let selected = match selected.and_then(|slot| slots.get(slot).ok()) {
+        Some(selected) => selected,
+        None => return,
+    };
+    let hovered = match from
+        .iter()
+        .find_map(|source| source.intersect_top())
+        .and_then(|(hit, _)| slots.get(hit).ok())
+    {
+        Some(hovered) => hovered,
+        None => return,
+    };
+    let popped = swapper.preview(selected, hovered);
+    if popped.is_empty() {
+        return;
+    }
+
+    let mut after = resources.clone();
+    let mut damage = 0;
+    for (_, typ) in board.iter().filter(|(pos, _)| popped.contains(pos)) {
+        let typ = GemType::from(*typ as u8);
+        let mut ctx = PopContext {
+            typ,
+            rarity: rarities.rarity(typ),
+            resources: &mut after,
+            damage: 0,
+        };
+        effects.apply(&mut ctx);
+        damage += ctx.damage;
+    }
+    if let Some(Ok((stats, status))) = foes.of(player).map(|target| defenses.get(target)) {
+        damage = damage_after_defense(damage, stats.defense(status));
+    }
+    let gains = GemType::iter()
+        .map(|typ| (typ, after.amount(typ).saturating_sub(resources.amount(typ))))
+        .filter(|(_, gain)| *gain > 0)
+        .collect::<Vec<_>>();
+
+    let window = windows.primary();
+    let cursor = match window.cursor_position() {
+        Some(cursor) => egui::pos2(cursor.x, window.height() - cursor.y),
+        None => return,
+    };
+    egui::Area::new("Swap preview")
+        .fixed_pos(cursor + PREVIEW_OFFSET)
+        .order(egui::Order::Tooltip)
+        .interactable(false)
+        .show(egui_ctx.ctx_mut(), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                if damage > 0 {
+                    ui.label(RichText::new(format!("{damage} damage")).color(Color32::RED));
+                }
+                for (typ, gain) in gains {
+                    ui.label(RichText::new(format!("+{gain} {}", resource_name(typ))).color(typ));
+                }
+            });
+        });
+}
@@ -0,0 +1,140 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_egui::{
+    egui::{self, Color32},
+    EguiContext,
+};
+
+use crate::{settings::Settings, GemType};
+
+/// Chains at least this long offer a replay once they resolve
+const MIN_REPLAY_CHAIN: usize = 3;
+const REPLAY_STEP_SECONDS: f32 = 0.8;
+const REPLAY_CELL_SIZE: f32 = 24.0;
+
+/// The board as it looked when a match in a chain was made
+pub struct CascadeStep {
+    pub gems: HashMap<UVec2, GemType>,
+    pub matched: Vec<UVec2>,
+}
+
+#[derive(Default)]
+enum ReplayState {
+    #[default]
+    Hidden,
+    Offered,
+    Playing {
+        step: usize,
+        timer: Timer,
+    },
+}
+
+/// Records the matches of the current move so big chains can be replayed afterwards
+#[derive(Default)]
+pub struct ChainReplay {
+    recording: Vec<CascadeStep>,
+    chain: Vec<CascadeStep>,
+    state: ReplayState,
+}
+
+impl ChainReplay {
+    pub fn start(&mut self) {
+        self.recording.clear();
+        self.state = ReplayState::Hidden;
+    }
+
+    pub fn record(&mut self, step: CascadeStep) {
+        self.recording.push(step);
+    }
+
+    pub fn finish(&mut self, settings: &Settings) {
+        if settings.chain_replay && self.recording.len() >= MIN_REPLAY_CHAIN {
+            self.chain = std::mem::take(&mut self.recording);
+            self.state = ReplayState::Offered;
+        }
+    }
+}
+
+pub fn chain_replay(
+    mut egui_ctx: ResMut<EguiContext>,
+    time: Res<Time>,
+    mut replay: ResMut<ChainReplay>,
+) {
+    let replay = &mut *replay;
+    match &mut replay.state {
+        ReplayState::Hidden => {}
+        ReplayState::Offered => {
+            let mut next = None;
+            egui::Window::new("Chain!")
+                .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -10.0])
+                .collapsible(false)
+                .resizable(false)
+                .show(egui_ctx.ctx_mut(), |ui| {
+                    ui.label(format!("{} matches in a row", replay.chain.len()));
+                    ui.horizontal(|ui| {
+                        if ui.button("Replay").clicked() {
+                            next = Some(ReplayState::Playing {
+                                step: 0,
+                                timer: Timer::from_seconds(REPLAY_STEP_SECONDS, true),
+                            });
+                        }
+                        if ui.button("Dismiss").clicked() {
+                            next = Some(ReplayState::Hidden);
+                        }
+                    });
+                });
+            if let Some(next) = next {
+                replay.state = next;
+            }
+        }
+        ReplayState::Playing { step, timer } => {
+            if timer.tick(time.delta()).just_finished() {
+                *step += 1;
+            }
+            let mut skipped = false;
+            if let Some(current) = replay.chain.get(*step) {
+                egui::Window::new(format!("Chain {}/{}", *step + 1, replay.chain.len()))
+                    .id(egui::Id::new("Chain replay"))
+                    .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -10.0])
+                    .collapsible(false)
+                    .resizable(false)
+                    .show(egui_ctx.ctx_mut(), |ui| {
+                        draw_step(ui, current);
+                        skipped = ui.button("Skip").clicked();
+                    });
+            }
+            if skipped || *step >= replay.chain.len() {
+                replay.state = ReplayState::Hidden;
+            }
+        }
+    }
+}
+
+fn draw_step(ui: &mut egui::Ui, step: &CascadeStep) {
+    let size = step
+        .gems
+        .keys()
+        .fold(UVec2::ZERO, |size, pos| size.max(*pos + UVec2::ONE));
+    let (response, painter) = ui.allocate_painter(
+        egui::vec2(
+            size.x as f32 * REPLAY_CELL_SIZE,
+            size.y as f32 * REPLAY_CELL_SIZE,
+        ),
+        egui::Sense::hover(),
+    );
+    for (pos, typ) in &step.gems {
+        let rect = egui::Rect::from_min_size(
+            response.rect.min
+                + egui::vec2(
+                    pos.x as f32 * REPLAY_CELL_SIZE,
+                    pos.y as f32 * REPLAY_CELL_SIZE,
+                ),
+            egui::vec2(REPLAY_CELL_SIZE - 2.0, REPLAY_CELL_SIZE - 2.0),
+        );
+        if step.matched.contains(pos) {
+            painter.rect_filled(rect, 4.0, Color32::from(*typ));
+            painter.rect_stroke(rect, 4.0, egui::Stroke::new(2.0, Color32::WHITE));
+        } else {
+            painter.rect_filled(rect, 4.0, Color32::from(*typ).linear_multiply(0.25));
+        }
+    }
+}
@@ -0,0 +1,439 @@
+use bevy::{
+    ecs::system::{EntityCommands, SystemParam},
+    prelude::*,
+};
+use bevy_match3::prelude::*;
+use serde::{Deserialize, Serialize};
+use strum::IntoEnumIterator;
+
+use crate::{
+    blitz::BlitzClock,
+    board_rows,
+    encounter::{Lineup, TargetedEnemy},
+    experience::Experience,
+    gravity::GravityDirection,
+    opponents::{OpponentDefinition, OpponentRoster},
+    persistence,
+    score::Score,
+    seed::{EncounterRng, MatchSeed},
+    status::{StatusEffect, StatusEffects},
+    Collected, CombatStats, CrackedState, GameMode, GemSlot, GemType, Health, Locked, MovesLeft,
+    Player, Resources, Turn,
+};
+
+const SAVE_FILE: &str = "save.ron";
+
+/// The components making up one side of a match
+pub type SideComponents = (Resources, Health, StatusEffects, Score, Collected);
+
+/// Everything about one side of a saved match
+#[derive(Clone, Serialize, Deserialize)]
+struct SavedSide {
+    mana: Vec<(GemType, u32)>,
+    #[serde(default)]
+    gold: u32,
+    health: u32,
+    max_health: u32,
+    #[serde(default)]
+    status: Vec<StatusEffect>,
+    score: u32,
+    collected: Vec<(GemType, u32)>,
+}
+
+impl SavedSide {
+    fn new(
+        resources: &Resources,
+        health: &Health,
+        status: &StatusEffects,
+        score: &Score,
+        collected: &Collected,
+    ) -> Self {
+        Self {
+            mana: resources
+                .mana
+                .iter()
+                .map(|(typ, amount)| (*typ, *amount))
+                .collect(),
+            gold: resources.gold,
+            health: health.current,
+            max_health: health.max,
+            status: status.to_vec(),
+            score: **score,
+            collected: collected
+                .iter()
+                .map(|(typ, amount)| (*typ, *amount))
+                .collect(),
+        }
+    }
+
+    fn components(&self) -> SideComponents {
+        (
+            Resources {
+                mana: self.mana.iter().copied().collect(),
+                gold: self.gold,
+            },
+            Health {
+                current: self.health.min(self.max_health),
+                max: self.max_health,
+            },
+            StatusEffects(self.status.clone()),
+            Score(self.score),
+            Collected(self.collected.iter().copied().collect()),
+        )
+    }
+
+    fn spawn<'w, 's, 'a>(&self, commands: &'a mut Commands<'w, 's>) -> EntityCommands<'w, 's, 'a> {
+        commands.spawn_bundle(self.components())
+    }
+}
+
+/// An opponent of the lineup besides the first
+#[derive(Clone, Serialize, Deserialize)]
+struct SavedOpponent {
+    name: String,
+    side: SavedSide,
+}
+
+// Resource containing a match loaded from disk, waiting for the game to start so it can be set up
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SavedGame {
+    mode: GameMode,
+    /// Gem types by row, top to bottom
+    board: Vec<Vec<u32>>,
+    player: SavedSide,
+    /// The first opponent of the lineup
+    opponent: SavedSide,
+    opponent_name: String,
+    /// The opponents after the first, in lineup order
+    #[serde(default)]
+    other_opponents: Vec<SavedOpponent>,
+    players_turn: bool,
+    /// Position in the lineup of the opponent whose turn it is, when it isn't the player's
+    #[serde(default)]
+    opponent_turn: usize,
+    /// Position in the lineup of the opponent the player is targeting
+    #[serde(default)]
+    targeted: usize,
+    /// Moves the player had left, `None` for a match without a move limit
+    #[serde(default)]
+    moves_left: Option<u32>,
+    /// Seconds left on the clock, `None` outside of blitz
+    #[serde(default)]
+    blitz_seconds_left: Option<f32>,
+    /// Positions of the slots still locked, as (x, y)
+    #[serde(default)]
+    locked: Vec<(u32, u32)>,
+    /// Locked gems by the (x, y) of their slot, and whether they've cracked yet
+    #[serde(default)]
+    locked_gems: Vec<((u32, u32), CrackedState)>,
+    #[serde(default)]
+    experience: Experience,
+    #[serde(default)]
+    gravity: GravityDirection,
+    /// The seed the board was laid out from, `None` in saves from before seeds were kept
+    #[serde(default)]
+    seed: Option<u64>,
+}
+
+impl SavedGame {
+    pub fn exists() -> bool {
+        persistence::exists(SAVE_FILE)
+    }
+
+    /// Reads the saved match, `None` if there isn't one or it doesn't hold a playable board
+    pub fn load() -> Option<Self> {
+        let saved: Self = persistence::load(SAVE_FILE)?;
+        let gem_types = GemType::iter().count() as u32;
+        let width = saved.board.first().map_or(0, Vec::len);
+        if width == 0
+            || saved.board.iter().any(|row| row.len() != width)
+            || saved.board.iter().flatten().any(|typ| *typ >= gem_types)
+        {
+            warn!("Ignoring saved match with a malformed board");
+            return None;
+        }
+        Some(saved)
+    }
+
+    pub fn mode(&self) -> GameMode {
+        self.mode
+    }
+
+    pub fn gravity(&self) -> GravityDirection {
+        self.gravity
+    }
+
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    pub fn moves_left(&self) -> Option<u32> {
+        self.moves_left
+    }
+
+    pub fn blitz_seconds_left(&self) -> Option<f32> {
+        self.blitz_seconds_left
+    }
+
+    /// Width and height of the saved board
+    pub fn dimensions(&self) -> UVec2 {
+        UVec2::new(
+            self.board.first().map_or(0, Vec::len) as u32,
+            self.board.len() as u32,
+        )
+    }
+
+    pub fn board(&self) -> Board {
+        Board::from(self.board.clone())
+    }
+
+    pub fn experience(&self) -> Experience {
+        self.experience
+    }
+
+    pub fn locked_slots(&self) -> Vec<UVec2> {
+        self.locked
+            .iter()
+            .map(|(x, y)| UVec2::new(*x, *y))
+            .collect()
+    }
+
+    pub fn locked_gems(&self) -> Vec<(UVec2, CrackedState)> {
+        self.locked_gems
+            .iter()
+            .map(|((x, y), state)| (UVec2::new(*x, *y), *state))
+            .collect()
+    }
+
+    /// Every saved opponent's name and side, in lineup order
+    fn opponents(&self) -> impl Iterator<Item = (&String, &SavedSide)> {
+        std::iter::once((&self.opponent_name, &self.opponent)).chain(
+            self.other_opponents
+                .iter()
+                .map(|opponent| (&opponent.name, &opponent.side)),
+        )
+    }
+
+    /// The player's side and every opponent's, in lineup order, as they were saved
+    pub fn sides(&self) -> (SideComponents, Vec<SideComponents>) {
+        (
+            self.player.components(),
+            self.opponents()
+                .map(|(_, side)| side.components())
+                .collect(),
+        )
+    }
+
+    /// Spawns every side as it was saved and hands the turn, the target and any move limit back
+    /// to them
+    pub fn spawn_sides(
+        &self,
+        commands: &mut Commands,
+        roster: &OpponentRoster,
+        rng: &mut EncounterRng,
+    ) {
+        let player = self
+            .player
+            .spawn(commands)
+            .insert_bundle((Player, self.experience, CombatStats::player()))
+            .id();
+        let lineup = self
+            .opponents()
+            .map(|(name, side)| {
+                let definition = roster
+                    .iter()
+                    .find(|definition| definition.name == *name)
+                    .cloned()
+                    .unwrap_or_else(|| {
+                        warn!("No opponent named {name} anymore, picking another");
+                        roster.pick(rng).clone()
+                    });
+                side.spawn(commands)
+                    .insert_bundle((CombatStats::opponent(&definition), definition))
+                    .id()
+            })
+            .collect::<Vec<_>>();
+        let turn = if self.players_turn {
+            player
+        } else {
+            lineup.get(self.opponent_turn).copied().unwrap_or(lineup[0])
+        };
+        commands.insert_resource(Turn(turn));
+        commands.insert_resource(TargetedEnemy(
+            lineup.get(self.targeted).or(lineup.first()).copied(),
+        ));
+        commands.insert_resource(Lineup(lineup));
+        commands.insert_resource(MovesLeft(self.moves_left));
+    }
+}
+
+// Everything about the match in progress that goes into a save
+#[derive(SystemParam)]
+pub struct MatchSnapshot<'w, 's> {
+    mode: Res<'w, GameMode>,
+    gravity: Res<'w, GravityDirection>,
+    board: Res<'w, Board>,
+    turn: Res<'w, Turn>,
+    lineup: Res<'w, Lineup>,
+    targeted: Res<'w, TargetedEnemy>,
+    moves_left: Res<'w, MovesLeft>,
+    blitz_clock: Res<'w, BlitzClock>,
+    seed: Res<'w, MatchSeed>,
+    locked: Query<'w, 's, &'static GemSlot, With<Locked>>,
+    slots: Query<'w, 's, &'static GemSlot>,
+    locked_gems: Query<'w, 's, &'static CrackedState>,
+    player: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static Resources,
+            &'static Health,
+            &'static StatusEffects,
+            &'static Score,
+            &'static Collected,
+            &'static Experience,
+        ),
+        With<Player>,
+    >,
+    opponents: Query<
+        'w,
+        's,
+        (
+            &'static Resources,
+            &'static Health,
+            &'static StatusEffects,
+            &'static Score,
+            &'static Collected,
+            &'static OpponentDefinition,
+        ),
+        Without<Player>,
+    >,
+}
+
+impl MatchSnapshot<'_, '_> {
+    pub fn save(&self) {
+        match self.capture() {
+            Some(saved) => {
+                persistence::save(SAVE_FILE, &saved);
+                info!("Saved the match");
+            }
+            None => warn!("Nothing to save, the match hasn't started"),
+        }
+    }
+
+    /// The match as it stands, `None` before it has started
+    pub fn capture(&self) -> Option<SavedGame> {
+        let (entity, resources, health, status, score, collected, experience) =
+            self.player.get_single().ok()?;
+        let mut opponents = self
+            .lineup
+            .iter()
+            .filter_map(|opponent| self.opponents.get(*opponent).ok())
+            .map(
+                |(resources, health, status, score, collected, definition)| SavedOpponent {
+                    name: definition.name.clone(),
+                    side: SavedSide::new(resources, health, status, score, collected),
+                },
+            )
+            .collect::<Vec<_>>();
+        if opponents.is_empty() {
+            return None;
+        }
+        let first = opponents.remove(0);
+        let position = |side: Option<Entity>| {
+            self.lineup
+                .iter()
+                .position(|opponent| Some(*opponent) == side)
+                .unwrap_or_default()
+        };
+
+        Some(SavedGame {
+            mode: *self.mode,
+            board: board_rows(&self.board),
+            player: SavedSide::new(resources, health, status, score, collected),
+            opponent: first.side,
+            opponent_name: first.name,
+            other_opponents: opponents,
+            players_turn: **self.turn == entity,
+            opponent_turn: position(Some(**self.turn)),
+            targeted: position(**self.targeted),
+            moves_left: **self.moves_left,
+            blitz_seconds_left: (*self.mode == GameMode::Blitz)
+                .then(|| self.blitz_clock.seconds_left()),
+            experience: *experience,
+            gravity: *self.gravity,
+            locked: self
+                .locked
+                .iter()
+                .map(|slot| (slot.pos.x, slot.pos.y))
+                .collect(),
+            locked_gems: self
+                .slots
+                .iter()
+                .filter_map(|slot| {
+                    let state = self.locked_gems.get(slot.gem?).ok()?;
+                    Some(((slot.pos.x, slot.pos.y), *state))
+                })
+                .collect(),
+            seed: Some(**self.seed),
+        })
+    }
+}
+
+/// Resource containing the match last quit to the menu without being saved, until another one starts
+#[derive(Default)]
+pub struct Abandoned(Option<SavedGame>);
+
+impl Abandoned {
+    pub fn keep(&mut self, saved: Option<SavedGame>) {
+        self.0 = saved;
+    }
+
+    pub fn is_some(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Writes the abandoned match out so it can be continued after all
+    pub fn save(&mut self) {
+        if let Some(saved) = self.0.take() {
+            persistence::save(SAVE_FILE, &saved);
+            info!("Saved the match");
+        }
+    }
+}
+
+// Starting a match leaves whatever was abandoned before it behind
+pub fn forget_abandoned(mut abandoned: ResMut<Abandoned>) {
+    *abandoned = Abandoned::default();
+}
+
+// A finished match can't be continued
+pub fn delete_save() {
+    persistence::remove(SAVE_FILE);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIDE: &str = "(mana: [], health: 10, max_health: 10, score: 0, collected: [])";
+
+    #[test]
+    fn board_rows_survive_a_save() {
+        // Wider than it's tall and no two rows alike, so a board read in sideways shows up
+        let rows = vec![vec![0, 1, 2, 3], vec![4, 5, 0, 1], vec![2, 2, 4, 6]];
+        let saved: SavedGame = ron::from_str(&format!(
+            "(mode: Classic, board: {rows:?}, player: {SIDE}, opponent: {SIDE}, \
+             opponent_name: \"\", players_turn: true)"
+        ))
+        .unwrap();
+        assert_eq!(saved.dimensions(), UVec2::new(4, 3));
+        let board = saved.board();
+        assert_eq!(board.iter().count(), 12);
+        for (pos, typ) in board.iter() {
+            assert_eq!(*typ, rows[pos.y as usize][pos.x as usize], "at {pos}");
+        }
+        assert_eq!(board_rows(&board), rows);
+    }
+}
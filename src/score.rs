@@ -0,0 +1,94 @@
+use bevy::prelude::*;
+
+use crate::{GemType, GemsPopped, TurnState};
+
+/// Decides how many points a match is worth.
+///
+/// `size` is the number of gems of `typ` in the match and `depth` is how many cascades deep the
+/// match happened, starting at 0 for the match caused by the swap itself.
+pub trait ScoreRule: Send + Sync {
+    /// What every gem in a match `depth` cascades deep is worth
+    fn multiplier(&self, _depth: u32) -> u32 {
+        1
+    }
+
+    fn score(&self, size: u32, _typ: GemType, depth: u32) -> u32 {
+        size.saturating_mul(self.multiplier(depth))
+    }
+}
+
+/// One point per matched gem
+pub struct ClassicScore;
+
+impl ScoreRule for ClassicScore {}
+
+/// Doubles the points for every cascade step so long chains pay off
+pub struct ComboScore;
+
+impl ScoreRule for ComboScore {
+    fn multiplier(&self, depth: u32) -> u32 {
+        2_u32.saturating_pow(depth)
+    }
+}
+
+#[derive(Deref, DerefMut)]
+pub struct ActiveScoreRule(pub Box<dyn ScoreRule>);
+
+impl Default for ActiveScoreRule {
+    fn default() -> Self {
+        Self(Box::new(ComboScore))
+    }
+}
+
+#[derive(Component, Default, Deref, DerefMut)]
+pub struct Score(pub u32);
+
+// Resource containing the multiplier of the cascade being resolved, back to 1 once the board settles
+#[derive(Deref)]
+pub struct Combo(u32);
+
+impl Default for Combo {
+    fn default() -> Self {
+        Self(1)
+    }
+}
+
+pub fn track_combo(
+    rule: Res<ActiveScoreRule>,
+    turn_state: Res<State<TurnState>>,
+    mut popped: EventReader<GemsPopped>,
+    mut combo: ResMut<Combo>,
+) {
+    // Pops arrive after their match has been counted, so they're one cascade step ahead of scoring
+    if let Some(depth) = popped.iter().map(|popped| popped.cascade_depth).max() {
+        combo.0 = rule.multiplier(depth.saturating_sub(1));
+    } else if turn_state.current() == &TurnState::AwaitingMove && combo.0 != 1 {
+        combo.0 = 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classic_scores_a_point_per_gem_at_any_depth() {
+        assert_eq!(ClassicScore.score(3, GemType::Ruby, 0), 3);
+        assert_eq!(ClassicScore.score(5, GemType::Skull, 4), 5);
+    }
+
+    #[test]
+    fn combo_doubles_every_cascade_step() {
+        assert_eq!(ComboScore.score(3, GemType::Ruby, 0), 3);
+        assert_eq!(ComboScore.score(3, GemType::Ruby, 1), 6);
+        assert_eq!(ComboScore.score(3, GemType::Ruby, 2), 12);
+        assert_eq!(ComboScore.score(4, GemType::Emerald, 3), 32);
+    }
+
+    #[test]
+    fn combo_saturates_deep_cascades() {
+        assert_eq!(ComboScore.multiplier(40), u32::MAX);
+        assert_eq!(ComboScore.score(3, GemType::Ruby, 40), u32::MAX);
+        assert_eq!(ComboScore.score(0, GemType::Ruby, 40), 0);
+    }
+}
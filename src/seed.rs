@@ -0,0 +1,225 @@
+use std::ops::RangeBounds;
+
+use bevy::prelude::*;
+use bevy_egui::egui;
+use bevy_match3::{prelude::*, Match3Config};
+
+use crate::save::SavedGame;
+
+/// Resource containing the seed the next match is generated from, a fresh random one for every
+/// match if unset.
+///
+/// The seed decides the starting board and its locked slots, the gems spawned in to refill it and
+/// whether they're locked, critical hits, and how many opponents the match is against and who.
+/// Shuffles are bevy_match3's own and not seeded, so two matches drift apart at their first one.
+#[derive(Default, Deref, DerefMut)]
+pub struct BoardSeed(Option<u64>);
+
+/// Resource containing the seed the match in progress was laid out from, whether picked or random
+#[derive(Deref)]
+pub struct MatchSeed(u64);
+
+impl Default for MatchSeed {
+    fn default() -> Self {
+        Self(fastrand::u64(..))
+    }
+}
+
+/// Resource containing the state of the random numbers combat rolls, seeded along with the board
+/// so a seeded match rolls the same critical hits
+pub struct CombatRng(u64);
+
+impl Default for CombatRng {
+    fn default() -> Self {
+        Self(fastrand::u64(..))
+    }
+}
+
+impl CombatRng {
+    pub fn with_seed(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// Whether something with `chance` of happening, from 0 to 1, does
+    pub fn roll(&mut self, chance: f32) -> bool {
+        draw(&mut self.0, |rng| rng.f32() < chance)
+    }
+}
+
+/// Resource containing the state of the random numbers behind the gems spawned onto the board,
+/// seeded along with the board so a seeded match locks the same gems
+pub struct SpawnRng(u64);
+
+impl Default for SpawnRng {
+    fn default() -> Self {
+        Self(fastrand::u64(..))
+    }
+}
+
+impl SpawnRng {
+    pub fn with_seed(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// Whether something with `chance` of happening, from 0 to 1, does
+    pub fn roll(&mut self, chance: f32) -> bool {
+        draw(&mut self.0, |rng| rng.f32() < chance)
+    }
+
+    /// One of the first `gem_types` gem types, for a gem refilling the board
+    pub fn gem_type(&mut self, gem_types: u32) -> u32 {
+        draw(&mut self.0, |rng| rng.u32(..gem_types))
+    }
+}
+
+/// Resource containing the state of the random numbers that make up the opponents of a match,
+/// seeded along with the board so a seeded match is against the same lineup
+pub struct EncounterRng(u64);
+
+impl Default for EncounterRng {
+    fn default() -> Self {
+        Self(fastrand::u64(..))
+    }
+}
+
+impl EncounterRng {
+    pub fn with_seed(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn usize(&mut self, range: impl RangeBounds<usize>) -> usize {
+        draw(&mut self.0, |rng| rng.usize(range))
+    }
+}
+
+/// Draws from the generator whose state is `state`, moving the state on
+fn draw<T>(state: &mut u64, pick: impl FnOnce(&fastrand::Rng) -> T) -> T {
+    // `fastrand::Rng` can't be shared between systems, so only its state is kept
+    let rng = fastrand::Rng::with_seed(*state);
+    let drawn = pick(&rng);
+    *state = rng.u64(..);
+    drawn
+}
+
+/// Rows of gem types, top to bottom, with no lines of three already on the board
+fn seeded_rows(seed: u64, config: &Match3Config) -> Vec<Vec<u32>> {
+    let rng = fastrand::Rng::with_seed(seed);
+    let (width, height) = (
+        config.board_dimensions.x as usize,
+        config.board_dimensions.y as usize,
+    );
+    let mut rows = vec![vec![0; width]; height];
+    for y in 0..height {
+        for x in 0..width {
+            let lined = |typ: u32| {
+                (x >= 2 && rows[y][x - 1] == typ && rows[y][x - 2] == typ)
+                    || (y >= 2 && rows[y - 1][x] == typ && rows[y - 2][x] == typ)
+            };
+            // At most two types can be ruled out, so with three or more this always finds one
+            let typ = (0..config.gem_types)
+                .cycle()
+                .skip(rng.u32(..config.gem_types) as usize)
+                .take(config.gem_types as usize)
+                .find(|typ| !lined(*typ))
+                .unwrap_or_default();
+            rows[y][x] = typ;
+        }
+    }
+    rows
+}
+
+/// `count` positions on the board to lock, the same ones every time for a seed
+pub fn seeded_locks(seed: u64, board: &Board, count: u32) -> Vec<UVec2> {
+    let rng = fastrand::Rng::with_seed(seed);
+    let mut positions = board.iter().map(|(pos, _)| *pos).collect::<Vec<_>>();
+    // The board doesn't keep its gems in any order, so they're put in one before shuffling
+    positions.sort_by_key(|pos| (pos.y, pos.x));
+    rng.shuffle(&mut positions);
+    positions.truncate(count as usize);
+    positions
+}
+
+/// Lays out a fresh board for a new match, continued matches keep their saved one and roll
+/// combat, spawns and any opponent that's gone missing afresh
+pub fn generate_board(
+    seed: Res<BoardSeed>,
+    saved: Option<Res<SavedGame>>,
+    config: Res<Match3Config>,
+    mut board: ResMut<Board>,
+    mut rng: ResMut<CombatRng>,
+    mut spawn_rng: ResMut<SpawnRng>,
+    mut encounter_rng: ResMut<EncounterRng>,
+    mut match_seed: ResMut<MatchSeed>,
+) {
+    if let Some(saved) = saved {
+        *rng = CombatRng::default();
+        *spawn_rng = SpawnRng::default();
+        *encounter_rng = EncounterRng::default();
+        if let Some(seed) = saved.seed() {
+            *match_seed = MatchSeed(seed);
+        }
+        return;
+    }
+    let seed = seed.unwrap_or_else(|| fastrand::u64(..));
+    info!("Starting board seed {seed}");
+    *board = Board::from(seeded_rows(seed, &config));
+    *rng = CombatRng::with_seed(seed);
+    *spawn_rng = SpawnRng::with_seed(seed);
+    *encounter_rng = EncounterRng::with_seed(seed);
+    *match_seed = MatchSeed(seed);
+}
+
+/// Text field for entering a board seed, left empty for a random board every match
+pub fn seed_field(ui: &mut egui::Ui, seed: &mut BoardSeed, text: &mut String) {
+    ui.horizontal(|ui| {
+        ui.label("Board seed");
+        if ui.text_edit_singleline(text).changed() {
+            **seed = text.trim().parse().ok();
+        }
+    });
+    if !text.trim().is_empty() && seed.is_none() {
+        ui.colored_label(egui::Color32::RED, "Not a valid seed, boards stay random");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{board_rows, lines_of_three};
+
+    fn config() -> Match3Config {
+        Match3Config {
+            gem_types: 6,
+            board_dimensions: UVec2::new(8, 10),
+        }
+    }
+
+    #[test]
+    fn a_seed_always_lays_out_the_same_board() {
+        let rows = seeded_rows(42, &config());
+        assert_eq!(rows, seeded_rows(42, &config()));
+        assert_ne!(rows, seeded_rows(43, &config()));
+        assert_eq!(rows.len(), 10);
+        assert!(rows.iter().all(|row| row.len() == 8));
+    }
+
+    #[test]
+    fn seeded_boards_start_without_lines() {
+        for seed in 0..50 {
+            let rows = seeded_rows(seed, &config());
+            let board = Board::from(rows.clone());
+            assert_eq!(board_rows(&board), rows, "seed {seed}");
+            assert_eq!(lines_of_three(&board), Vec::<UVec2>::new(), "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn a_seed_always_locks_the_same_slots() {
+        let board = Board::from(seeded_rows(7, &config()));
+        let locks = seeded_locks(7, &board, 5);
+        assert_eq!(locks.len(), 5);
+        assert_eq!(locks, seeded_locks(7, &board, 5));
+        assert!(locks.iter().all(|pos| pos.x < 8 && pos.y < 10));
+        assert_eq!(seeded_locks(7, &board, 0), Vec::<UVec2>::new());
+    }
+}
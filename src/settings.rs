@@ -0,0 +1,510 @@
+use std::time::{Duration, Instant};
+
+use bevy::{prelude::*, window::PresentMode};
+use bevy_egui::{
+    egui::{self, FontId, RichText},
+    EguiContext,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    assets::{GemAssets, MeshQuality},
+    board_setup::BoardSize,
+    gamepad::ActiveGamepad,
+    persistence, GameState, GemType,
+};
+
+const SETTINGS_FILE: &str = "settings.ron";
+
+/// MSAA sample counts wgpu guarantees on every GPU, the only ones offered. Anything else read from a
+/// hand edited file falls back to the closest lower count
+const SUPPORTED_MSAA: [u32; 2] = [1, 4];
+
+/// Radians the selected gem can wobble to either side before it reads as spinning
+const MAX_WOBBLE_AMPLITUDE: f32 = 1.0;
+const MIN_WOBBLE_SPEED: f32 = 0.25;
+const MAX_WOBBLE_SPEED: f32 = 3.0;
+
+const FRAME_CAPS: [Option<u32>; 4] = [None, Some(30), Some(60), Some(144)];
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub msaa: u32,
+    pub vsync: bool,
+    /// Frames per second to stay under, on top of whatever vsync already limits it to
+    pub frame_cap: Option<u32>,
+    pub mesh_quality: MeshQuality,
+    /// Share of the sparkles popped gems burst into, from none at 0 to all of them at 1
+    pub particle_density: f32,
+    pub shadows: bool,
+    pub selection_style: SelectionStyle,
+    /// Radians the selected gem wobbles to either side
+    pub wobble_amplitude: f32,
+    /// How many times faster than normal the selected gem wobbles
+    pub wobble_speed: f32,
+    pub palette: Palette,
+    /// Draw a letter on every gem so types can be told apart without relying on color
+    pub gem_symbols: bool,
+    pub post_swap_selection: PostSwapSelection,
+    pub swap_input: SwapInput,
+    /// Highlight the slots the selected gem can be swapped into
+    pub swap_hints: bool,
+    pub camera_view: CameraView,
+    /// Sweep the camera into place when a game starts
+    pub camera_intro: bool,
+    /// Snap straight to the end of animations and drop wobbling and shaking altogether
+    pub reduced_motion: bool,
+    /// Offer to replay long chains once they resolve
+    pub chain_replay: bool,
+    /// Offer a button that makes the best swap on the player's turn for them
+    pub auto_match_assist: bool,
+    /// Ceiling for the music and sound effects, their own volumes are shares of it
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    pub game_track: GameTrack,
+    /// Silences the music alone, leaving sound effects playing
+    pub music_muted: bool,
+    /// Silences all audio regardless of the volume settings
+    pub muted: bool,
+    /// The board last picked before a match
+    pub board: BoardSize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            msaa: 4,
+            vsync: true,
+            frame_cap: None,
+            mesh_quality: MeshQuality::default(),
+            particle_density: 1.0,
+            shadows: true,
+            selection_style: SelectionStyle::default(),
+            wobble_amplitude: 0.5,
+            wobble_speed: 1.0,
+            palette: Palette::default(),
+            gem_symbols: false,
+            post_swap_selection: PostSwapSelection::default(),
+            swap_input: SwapInput::default(),
+            swap_hints: true,
+            camera_view: CameraView::default(),
+            camera_intro: true,
+            reduced_motion: false,
+            chain_replay: false,
+            auto_match_assist: false,
+            master_volume: 1.0,
+            music_volume: 0.5,
+            sfx_volume: 0.8,
+            game_track: GameTrack::default(),
+            music_muted: false,
+            muted: false,
+            board: BoardSize::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        persistence::load(SETTINGS_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        persistence::save(SETTINGS_FILE, self);
+    }
+
+    /// How loud the music plays, never above the master volume
+    pub fn music_level(&self) -> f32 {
+        if self.muted || self.music_muted {
+            0.0
+        } else {
+            self.master_volume * self.music_volume
+        }
+    }
+
+    /// How loud sound effects play, never above the master volume
+    pub fn sfx_level(&self) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.master_volume * self.sfx_volume
+        }
+    }
+
+    pub fn supported_msaa(&self) -> u32 {
+        SUPPORTED_MSAA
+            .iter()
+            .copied()
+            .filter(|samples| *samples <= self.msaa)
+            .max()
+            .unwrap_or(1)
+    }
+
+    /// How long an animation meant to take `seconds` should run, next to nothing with reduced
+    /// motion so it snaps to its end while anything waiting on it still sees it finish
+    pub fn motion_duration(&self, seconds: f32) -> Duration {
+        if self.reduced_motion {
+            Duration::from_millis(1)
+        } else {
+            Duration::from_secs_f32(seconds)
+        }
+    }
+
+    /// The wobble amount pulled back within the limits, for amounts read from a hand edited file
+    pub fn wobble_amplitude(&self) -> f32 {
+        self.wobble_amplitude.clamp(0.0, MAX_WOBBLE_AMPLITUDE)
+    }
+
+    pub fn wobble_speed(&self) -> f32 {
+        self.wobble_speed.clamp(MIN_WOBBLE_SPEED, MAX_WOBBLE_SPEED)
+    }
+
+    fn apply_preset(&mut self, preset: GraphicsPreset) {
+        let (msaa, mesh_quality, particle_density, shadows) = match preset {
+            GraphicsPreset::Low => (1, MeshQuality::Low, 0.25, false),
+            GraphicsPreset::Medium => (1, MeshQuality::High, 0.5, true),
+            GraphicsPreset::High => (4, MeshQuality::High, 1.0, true),
+        };
+        self.msaa = msaa;
+        self.mesh_quality = mesh_quality;
+        self.particle_density = particle_density;
+        self.shadows = shadows;
+    }
+}
+
+#[derive(Clone, Copy)]
+enum GraphicsPreset {
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum CameraView {
+    #[default]
+    Flat,
+    Tilted,
+}
+
+/// How the selected gem is animated
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum SelectionStyle {
+    #[default]
+    Wobble,
+    Lift,
+    WobbleAndLift,
+}
+
+/// The colors of the gem types, with palettes that stay distinct under the common kinds of color
+/// blindness
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum Palette {
+    #[default]
+    Standard,
+    Deuteranopia,
+    Protanopia,
+    Tritanopia,
+}
+
+impl Palette {
+    pub fn color(self, typ: GemType) -> Color {
+        // Ruby, Emerald, Sapphire, Topaz, Diamond, Amethyst, Skull, Equipment
+        let colors: [u32; 8] = match self {
+            Palette::Standard => return typ.into(),
+            // Okabe-Ito
+            Palette::Deuteranopia => [
+                0xD55E00, 0x009E73, 0x0072B2, 0xF0E442, 0xFFFFFF, 0xCC79A7, 0x999999, 0x56B4E9,
+            ],
+            // IBM design library
+            Palette::Protanopia => [
+                0xFE6100, 0x785EF0, 0x648FFF, 0xFFB000, 0xFFFFFF, 0xDC267F, 0x999999, 0x222222,
+            ],
+            Palette::Tritanopia => [
+                0xD81B60, 0x004D40, 0x1E88E5, 0xFFC107, 0xFFFFFF, 0x6A1B9A, 0x999999, 0x222222,
+            ],
+        };
+        let [_, r, g, b] = colors[typ as usize].to_be_bytes();
+        Color::rgb_u8(r, g, b)
+    }
+}
+
+/// The music played during a game, the main menu always has its own track
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Hash, Serialize, Deserialize)]
+pub enum GameTrack {
+    #[default]
+    Serene,
+    Battle,
+}
+
+/// What happens to the selection once a swap has been made
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum PostSwapSelection {
+    #[default]
+    Clear,
+    /// Keep the swapped gem selected in its new slot so moves can be chained
+    KeepTarget,
+}
+
+impl PostSwapSelection {
+    /// The slot to select once a swap into `target` has resolved, `holds_gem` being whether it
+    /// still has a gem after everything the swap set off popped and refilled
+    pub fn reselect(self, target: Entity, holds_gem: bool) -> Option<Entity> {
+        match self {
+            PostSwapSelection::Clear => None,
+            PostSwapSelection::KeepTarget => holds_gem.then_some(target),
+        }
+    }
+}
+
+/// How the player swaps gems with the mouse
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum SwapInput {
+    /// Click a gem, then click the gem to swap it with
+    Click,
+    /// Press on a gem and release over the gem to swap it with
+    Drag,
+    #[default]
+    Both,
+}
+
+/// Shows the settings controls, only touching the resource when something was actually changed
+/// so systems reacting to `Changed<Settings>` don't run every frame. Mesh quality is left out
+/// unless the low quality meshes are there to switch to
+pub fn settings_ui(ui: &mut egui::Ui, settings: &mut ResMut<Settings>, low_meshes: bool) {
+    let mut edited = (**settings).clone();
+
+    ui.horizontal(|ui| {
+        ui.label("Preset");
+        for (preset, name) in [
+            (GraphicsPreset::Low, "Low"),
+            (GraphicsPreset::Medium, "Medium"),
+            (GraphicsPreset::High, "High"),
+        ] {
+            if ui.button(name).clicked() {
+                edited.apply_preset(preset);
+            }
+        }
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Anti-aliasing");
+        for samples in SUPPORTED_MSAA {
+            let name = if samples == 1 {
+                "Off".to_string()
+            } else {
+                format!("{samples}x")
+            };
+            ui.radio_value(&mut edited.msaa, samples, name);
+        }
+    });
+    if edited.supported_msaa() != edited.msaa {
+        ui.label(format!(
+            "{}x isn't supported, using {}x",
+            edited.msaa,
+            edited.supported_msaa()
+        ));
+    }
+
+    ui.checkbox(&mut edited.vsync, "Vsync");
+    ui.horizontal(|ui| {
+        ui.label("Frame cap");
+        for cap in FRAME_CAPS {
+            let name = cap.map_or_else(|| "Off".to_string(), |fps| fps.to_string());
+            ui.radio_value(&mut edited.frame_cap, cap, name);
+        }
+    });
+
+    if low_meshes {
+        ui.horizontal(|ui| {
+            ui.label("Mesh quality");
+            ui.radio_value(&mut edited.mesh_quality, MeshQuality::Low, "Low");
+            ui.radio_value(&mut edited.mesh_quality, MeshQuality::High, "High");
+        });
+    }
+    ui.add(egui::Slider::new(&mut edited.particle_density, 0.0..=1.0).text("Particles"));
+    ui.checkbox(&mut edited.shadows, "Shadows");
+
+    ui.horizontal(|ui| {
+        ui.label("Selection");
+        ui.radio_value(
+            &mut edited.selection_style,
+            SelectionStyle::Wobble,
+            "Wobble",
+        );
+        ui.radio_value(&mut edited.selection_style, SelectionStyle::Lift, "Lift");
+        ui.radio_value(
+            &mut edited.selection_style,
+            SelectionStyle::WobbleAndLift,
+            "Both",
+        );
+    });
+    ui.add(
+        egui::Slider::new(&mut edited.wobble_amplitude, 0.0..=MAX_WOBBLE_AMPLITUDE)
+            .text("Wobble amount"),
+    );
+    ui.add(
+        egui::Slider::new(
+            &mut edited.wobble_speed,
+            MIN_WOBBLE_SPEED..=MAX_WOBBLE_SPEED,
+        )
+        .text("Wobble speed"),
+    );
+
+    ui.horizontal(|ui| {
+        ui.label("Colors");
+        ui.radio_value(&mut edited.palette, Palette::Standard, "Standard");
+        ui.radio_value(&mut edited.palette, Palette::Deuteranopia, "Deuteranopia");
+        ui.radio_value(&mut edited.palette, Palette::Protanopia, "Protanopia");
+        ui.radio_value(&mut edited.palette, Palette::Tritanopia, "Tritanopia");
+    });
+    ui.checkbox(&mut edited.gem_symbols, "Gem symbols");
+
+    ui.horizontal(|ui| {
+        ui.label("After swap");
+        ui.radio_value(
+            &mut edited.post_swap_selection,
+            PostSwapSelection::Clear,
+            "Deselect",
+        );
+        ui.radio_value(
+            &mut edited.post_swap_selection,
+            PostSwapSelection::KeepTarget,
+            "Keep selected",
+        );
+    });
+
+    ui.horizontal(|ui| {
+        ui.label("Swap by");
+        ui.radio_value(&mut edited.swap_input, SwapInput::Click, "Clicking");
+        ui.radio_value(&mut edited.swap_input, SwapInput::Drag, "Dragging");
+        ui.radio_value(&mut edited.swap_input, SwapInput::Both, "Either");
+    });
+
+    ui.checkbox(&mut edited.swap_hints, "Highlight possible swaps");
+
+    ui.horizontal(|ui| {
+        ui.label("View");
+        ui.radio_value(&mut edited.camera_view, CameraView::Flat, "Flat");
+        ui.radio_value(&mut edited.camera_view, CameraView::Tilted, "Tilted");
+    });
+
+    ui.checkbox(&mut edited.camera_intro, "Camera intro");
+    ui.checkbox(&mut edited.reduced_motion, "Reduced motion");
+    ui.checkbox(&mut edited.chain_replay, "Offer chain replays");
+    ui.checkbox(&mut edited.auto_match_assist, "Assist: auto-match button")
+        .on_hover_text("Lets the game make your swaps for you, for enjoying the battles alone");
+
+    ui.add(egui::Slider::new(&mut edited.master_volume, 0.0..=1.0).text("Master volume"));
+    ui.add(egui::Slider::new(&mut edited.music_volume, 0.0..=1.0).text("Music volume"));
+    ui.add(egui::Slider::new(&mut edited.sfx_volume, 0.0..=1.0).text("Sound volume"));
+    ui.horizontal(|ui| {
+        ui.label("Game music");
+        ui.radio_value(&mut edited.game_track, GameTrack::Serene, "Serene");
+        ui.radio_value(&mut edited.game_track, GameTrack::Battle, "Battle");
+    });
+    ui.checkbox(&mut edited.music_muted, "Mute music");
+    ui.checkbox(&mut edited.muted, "Mute");
+
+    if edited != **settings {
+        **settings = edited;
+    }
+}
+
+pub fn apply_msaa(settings: Res<Settings>, mut msaa: ResMut<Msaa>) {
+    if settings.is_changed() && msaa.samples != settings.supported_msaa() {
+        msaa.samples = settings.supported_msaa();
+    }
+}
+
+// Lights spawned after the setting changed pick it up too, so this checks every light every frame
+pub fn apply_shadows(settings: Res<Settings>, mut lights: Query<&mut DirectionalLight>) {
+    for mut light in lights.iter_mut() {
+        if light.shadows_enabled != settings.shadows {
+            light.shadows_enabled = settings.shadows;
+        }
+    }
+}
+
+pub fn apply_vsync(settings: Res<Settings>, mut windows: ResMut<Windows>) {
+    if !settings.is_changed() {
+        return;
+    }
+    let present_mode = if settings.vsync {
+        PresentMode::Fifo
+    } else {
+        PresentMode::Immediate
+    };
+    if let Some(window) = windows.get_primary_mut() {
+        if window.present_mode() != present_mode {
+            window.set_present_mode(present_mode);
+        }
+    }
+}
+
+/// Sleeps off whatever is left of the frame's budget, runs last so the whole frame counts
+pub fn limit_frame_rate(settings: Res<Settings>, mut frame_start: Local<Option<Instant>>) {
+    if let (Some(fps), Some(start)) = (settings.frame_cap, *frame_start) {
+        let budget = Duration::from_secs_f64(1.0 / f64::from(fps));
+        if let Some(remaining) = budget.checked_sub(start.elapsed()) {
+            std::thread::sleep(remaining);
+        }
+    }
+    *frame_start = Some(Instant::now());
+}
+
+pub fn settings_menu(
+    mut egui_ctx: ResMut<EguiContext>,
+    mut settings: ResMut<Settings>,
+    mut state: ResMut<State<GameState>>,
+    gamepad: Res<ActiveGamepad>,
+    assets: Res<GemAssets>,
+) {
+    egui::CentralPanel::default().show(egui_ctx.ctx_mut(), |ui| {
+        ui.with_layout(
+            egui::Layout::default().with_cross_align(egui::Align::Center),
+            |ui| {
+                ui.heading(RichText::new("Settings").font(FontId::monospace(50.0)));
+                settings_ui(ui, &mut settings, assets.low_meshes_shipped);
+                ui.label(match **gamepad {
+                    Some(gamepad) => format!("Using gamepad {}", gamepad.0),
+                    None => "No gamepad connected".to_string(),
+                });
+                if ui
+                    .button(RichText::new("Back").font(FontId::monospace(30.0)))
+                    .clicked()
+                {
+                    state.set(GameState::MainMenu).unwrap();
+                }
+            },
+        );
+    });
+}
+
+pub fn save_settings(settings: Res<Settings>) {
+    if settings.is_changed() && !settings.is_added() {
+        settings.save();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clearing_never_reselects() {
+        let target = Entity::from_raw(7);
+        assert_eq!(PostSwapSelection::Clear.reselect(target, true), None);
+        assert_eq!(PostSwapSelection::Clear.reselect(target, false), None);
+    }
+
+    #[test]
+    fn keeping_reselects_the_target_while_it_holds_a_gem() {
+        let target = Entity::from_raw(7);
+        assert_eq!(
+            PostSwapSelection::KeepTarget.reselect(target, true),
+            Some(target)
+        );
+        assert_eq!(PostSwapSelection::KeepTarget.reselect(target, false), None);
+    }
+}
@@ -0,0 +1,224 @@
+use std::{fmt, str::FromStr};
+
+use bevy_egui::egui;
+use bevy_match3::Match3Config;
+use strum::IntoEnumIterator;
+
+use crate::{board_setup::BoardSize, gravity::GravityDirection};
+
+/// Digits of a share code, Crockford's base32 which leaves out the letters mistaken for others
+const DIGITS: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+/// Bytes of the seed and board a code is made from, before its checksum
+const PAYLOAD_LEN: usize = 12;
+/// Digits needed for the payload and its two checksum bytes, five bits each
+const CODE_LEN: usize = ((PAYLOAD_LEN + 2) * 8 + 4) / 5;
+/// Digits written together between the dashes of a code
+const GROUP_LEN: usize = 4;
+
+/// A board seed and the board it was laid out on, everything needed to play the same board again,
+/// written as a short code players can pass around
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ShareCode {
+    pub seed: u64,
+    pub board: BoardSize,
+}
+
+impl ShareCode {
+    /// The code of the match in progress
+    pub fn of(seed: u64, config: &Match3Config, gravity: GravityDirection) -> Self {
+        Self {
+            seed,
+            board: BoardSize {
+                width: config.board_dimensions.x,
+                height: config.board_dimensions.y,
+                gem_types: config.gem_types,
+                gravity,
+            },
+        }
+    }
+
+    fn payload(self) -> [u8; PAYLOAD_LEN] {
+        let mut payload = [0; PAYLOAD_LEN];
+        payload[..8].copy_from_slice(&self.seed.to_le_bytes());
+        payload[8] = self.board.width as u8;
+        payload[9] = self.board.height as u8;
+        payload[10] = self.board.gem_types as u8;
+        payload[11] = GravityDirection::iter()
+            .position(|gravity| gravity == self.board.gravity)
+            .unwrap_or_default() as u8;
+        payload
+    }
+}
+
+// CRC-16/CCITT, which catches any run of up to 16 wrong bits, so any mistyped digit and any two
+// swapped ones
+fn checksum(bytes: &[u8]) -> [u8; 2] {
+    let crc = bytes.iter().fold(0xffff_u16, |crc, byte| {
+        (0..8).fold(crc ^ (u16::from(*byte) << 8), |crc, _| {
+            if crc & 0x8000 == 0 {
+                crc << 1
+            } else {
+                (crc << 1) ^ 0x1021
+            }
+        })
+    });
+    crc.to_be_bytes()
+}
+
+impl fmt::Display for ShareCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let payload = self.payload();
+        let value = payload
+            .iter()
+            .chain(&checksum(&payload))
+            .fold(0u128, |value, byte| (value << 8) | u128::from(*byte));
+        let digits = (0..CODE_LEN)
+            .rev()
+            .map(|i| char::from(DIGITS[(value >> (i * 5)) as usize & 31]))
+            .collect::<Vec<_>>();
+        let groups = digits
+            .chunks(GROUP_LEN)
+            .map(|group| group.iter().collect::<String>())
+            .collect::<Vec<_>>();
+        write!(f, "{}", groups.join("-"))
+    }
+}
+
+impl FromStr for ShareCode {
+    type Err = String;
+
+    /// Reads a code back, forgiving of case, dashes, spaces and letters typed for the digits they
+    /// look like
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        let digits = code
+            .chars()
+            .filter(|c| *c != '-' && !c.is_whitespace())
+            .map(|c| match c.to_ascii_uppercase() {
+                'O' => '0',
+                'I' | 'L' => '1',
+                c => c,
+            })
+            .map(|c| {
+                DIGITS
+                    .iter()
+                    .position(|digit| char::from(*digit) == c)
+                    .ok_or_else(|| format!("{c} isn't part of a board code"))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if digits.len() != CODE_LEN {
+            return Err(format!(
+                "A board code has {CODE_LEN} letters and digits, not {}",
+                digits.len()
+            ));
+        }
+        let value = digits
+            .iter()
+            .fold(0u128, |value, digit| (value << 5) | *digit as u128);
+        let bytes = (0..PAYLOAD_LEN + 2)
+            .rev()
+            .map(|i| (value >> (i * 8)) as u8)
+            .collect::<Vec<_>>();
+        let (payload, sum) = bytes.split_at(PAYLOAD_LEN);
+        if value >> ((PAYLOAD_LEN + 2) * 8) != 0 || checksum(payload) != sum {
+            return Err("Not a valid board code, check for typos".to_string());
+        }
+        let gravity = GravityDirection::iter()
+            .nth(usize::from(payload[11]))
+            .ok_or("Not a valid board code, check for typos")?;
+        let board = BoardSize {
+            width: u32::from(payload[8]),
+            height: u32::from(payload[9]),
+            gem_types: u32::from(payload[10]),
+            gravity,
+        };
+        if board.validated() != board {
+            return Err("The board in this code is outside what can be played".to_string());
+        }
+        let mut seed = [0; 8];
+        seed.copy_from_slice(&payload[..8]);
+        Ok(Self {
+            seed: u64::from_le_bytes(seed),
+            board,
+        })
+    }
+}
+
+/// The match's code with a button to copy it, for passing the board on
+pub fn share_code_label(ui: &mut egui::Ui, code: ShareCode) {
+    ui.horizontal(|ui| {
+        ui.label(format!("Board code: {code}"));
+        if ui.small_button("Copy").clicked() {
+            ui.output().copied_text = code.to_string();
+        }
+    });
+}
+
+/// Text field for entering a board code, the code once a valid one has been typed in
+pub fn share_code_field(ui: &mut egui::Ui, text: &mut String) -> Option<ShareCode> {
+    let mut entered = None;
+    ui.horizontal(|ui| {
+        ui.label("Board code");
+        if ui.text_edit_singleline(text).changed() {
+            entered = text.parse().ok();
+        }
+    });
+    if !text.trim().is_empty() {
+        if let Err(err) = text.parse::<ShareCode>() {
+            ui.colored_label(egui::Color32::RED, err);
+        }
+    }
+    entered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code() -> ShareCode {
+        ShareCode {
+            seed: 0x0123_4567_89ab_cdef,
+            board: BoardSize {
+                width: 9,
+                height: 7,
+                gem_types: 5,
+                gravity: GravityDirection::iter().last().unwrap(),
+            },
+        }
+    }
+
+    #[test]
+    fn codes_read_back_as_written() {
+        for seed in [0, 1, 42, u64::MAX] {
+            let code = ShareCode { seed, ..code() };
+            assert_eq!(code.to_string().parse::<ShareCode>(), Ok(code));
+        }
+        let code = code();
+        let retyped = code.to_string().to_lowercase().replace('-', " ");
+        assert_eq!(retyped.parse::<ShareCode>(), Ok(code));
+    }
+
+    #[test]
+    fn mistyped_codes_are_rejected() {
+        let written = code().to_string();
+        for (i, c) in written.char_indices().filter(|(_, c)| *c != '-') {
+            let typo = if c == 'Z' { '0' } else { 'Z' };
+            let mistyped = format!("{}{typo}{}", &written[..i], &written[i + 1..]);
+            assert!(mistyped.parse::<ShareCode>().is_err(), "{mistyped}");
+        }
+        assert!(written[1..].parse::<ShareCode>().is_err());
+        assert!(format!("{written}0").parse::<ShareCode>().is_err());
+        assert!(format!("U{}", &written[1..]).parse::<ShareCode>().is_err());
+    }
+
+    #[test]
+    fn boards_outside_the_limits_are_rejected() {
+        let code = ShareCode {
+            board: BoardSize {
+                width: 200,
+                ..code().board
+            },
+            ..code()
+        };
+        assert!(code.to_string().parse::<ShareCode>().is_err());
+    }
+}
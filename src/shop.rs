@@ -0,0 +1,155 @@
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{self, FontId, RichText},
+    EguiContext,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    experience::Experience, persistence, GameState, Player, Resources, SkillBook, SkillType,
+};
+
+const PROGRESS_FILE: &str = "progress.ron";
+
+/// Skills the player knows before buying any in the shop
+const STARTING_SKILLS: [SkillType; 4] = [
+    SkillType::Bamboozle,
+    SkillType::Bonk,
+    SkillType::Heal,
+    SkillType::Purge,
+];
+const HEALTH_UPGRADE: u32 = 10;
+const HEALTH_UPGRADE_PRICE: u32 = 15;
+const MANA_UPGRADE: u32 = 2;
+const MANA_UPGRADE_PRICE: u32 = 20;
+const SKILL_PRICE: u32 = 30;
+
+// Resource containing what the player carries from one battle to the next
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Progress {
+    pub gold: u32,
+    /// Max health bought on top of the usual
+    pub bonus_health: u32,
+    /// Mana of every type each battle starts with
+    pub starting_mana: u32,
+    pub skills: Vec<SkillType>,
+    pub experience: Experience,
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Self {
+            gold: 0,
+            bonus_health: 0,
+            starting_mana: 0,
+            skills: STARTING_SKILLS.to_vec(),
+            experience: Experience::default(),
+        }
+    }
+}
+
+impl Progress {
+    pub fn load() -> Self {
+        persistence::load(PROGRESS_FILE).unwrap_or_default()
+    }
+
+    fn save(&self) {
+        persistence::save(PROGRESS_FILE, self);
+    }
+
+    pub fn knows(&self, skill: SkillType) -> bool {
+        self.skills.contains(&skill)
+    }
+}
+
+/// Something the shop sells
+#[derive(Clone, Copy)]
+enum Offer {
+    MaxHealth,
+    StartingMana,
+    Skill(SkillType),
+}
+
+impl Offer {
+    /// Every offer the player doesn't have yet, skills come from the skill book
+    fn available(progress: &Progress, book: &SkillBook) -> Vec<Self> {
+        let skills = book
+            .iter()
+            .map(|definition| definition.typ)
+            .filter(|skill| !progress.knows(*skill))
+            .map(Offer::Skill);
+        [Offer::MaxHealth, Offer::StartingMana]
+            .into_iter()
+            .chain(skills)
+            .collect()
+    }
+
+    fn price(self) -> u32 {
+        match self {
+            Offer::MaxHealth => HEALTH_UPGRADE_PRICE,
+            Offer::StartingMana => MANA_UPGRADE_PRICE,
+            Offer::Skill(_) => SKILL_PRICE,
+        }
+    }
+
+    fn label(self) -> String {
+        match self {
+            Offer::MaxHealth => format!("+{HEALTH_UPGRADE} max health"),
+            Offer::StartingMana => format!("+{MANA_UPGRADE} starting mana of every type"),
+            Offer::Skill(skill) => format!("Learn {skill}"),
+        }
+    }
+
+    fn apply(self, progress: &mut Progress) {
+        match self {
+            Offer::MaxHealth => progress.bonus_health += HEALTH_UPGRADE,
+            Offer::StartingMana => progress.starting_mana += MANA_UPGRADE,
+            Offer::Skill(skill) => progress.skills.push(skill),
+        }
+    }
+}
+
+// Keeps the gold and experience the player finished the battle with for the next one
+pub fn bank_progress(
+    mut progress: ResMut<Progress>,
+    player: Query<(&Resources, &Experience), With<Player>>,
+) {
+    let (resources, experience) = player.single();
+    progress.gold = resources.gold;
+    progress.experience = *experience;
+    progress.save();
+}
+
+// Shown between a won battle and the next, buying something or skipping heads straight into it
+pub fn shop(
+    mut egui_ctx: ResMut<EguiContext>,
+    mut progress: ResMut<Progress>,
+    book: Res<SkillBook>,
+    mut state: ResMut<State<GameState>>,
+) {
+    egui::CentralPanel::default().show(egui_ctx.ctx_mut(), |ui| {
+        ui.with_layout(
+            egui::Layout::default().with_cross_align(egui::Align::Center),
+            |ui| {
+                ui.heading(RichText::new("Shop").font(FontId::monospace(50.0)));
+                ui.label(format!("Gold: {}", progress.gold));
+                ui.separator();
+                for offer in Offer::available(&progress, &book) {
+                    let price = offer.price();
+                    let button = egui::Button::new(format!("{} ({price} gold)", offer.label()));
+                    if ui.add_enabled(progress.gold >= price, button).clicked() {
+                        progress.gold -= price;
+                        offer.apply(&mut progress);
+                        progress.save();
+                        state.set(GameState::Game).unwrap();
+                    }
+                }
+                ui.separator();
+                if ui.button("Skip").clicked() {
+                    state.set(GameState::Game).unwrap();
+                }
+            },
+        );
+    });
+}
@@ -0,0 +1,144 @@
+use bevy::prelude::*;
+
+use crate::{
+    assets::asset_path, portrait::SideHurt, settings::Settings, GemsPopped, Health, Player, Skill,
+    SkillType,
+};
+
+/// Generated placeholder blip until a real pop is recorded, popping goes silent without the file
+const POP_SOUND: &str = "sounds/pop.wav";
+/// Placeholder like `POP_SOUND`
+const HURT_SOUND: &str = "sounds/hurt.wav";
+/// Placeholder like `POP_SOUND`, heard over everything else on a lethal hit or the cataclysm
+const BIG_MOMENT_SOUND: &str = "sounds/big_moment.wav";
+/// Pops closer together than this are heard as one
+const MIN_POP_INTERVAL: f64 = 0.05;
+/// How much higher each step deeper into a cascade pops, as a playback speed multiplier
+const POP_PITCH_STEP: f32 = 0.08;
+const MAX_POP_PITCH: f32 = 1.6;
+/// Share of their volume the music and other sounds keep while a big moment is heard over them
+const DUCKED_VOLUME: f32 = 0.3;
+/// How long a big moment keeps everything else down
+const DUCK_SECONDS: f32 = 1.5;
+/// The last part of `DUCK_SECONDS`, over which everything comes back up
+const DUCK_RELEASE_SECONDS: f32 = 0.5;
+
+// Resource containing the sound effects that could be found, effects without a file stay silent
+#[derive(Default)]
+pub struct SoundAssets {
+    pop: Option<Handle<AudioSource>>,
+    hurt: Option<Handle<AudioSource>>,
+    big_moment: Option<Handle<AudioSource>>,
+}
+
+fn load_sound(ass: &AssetServer, path: &str, silenced: &str) -> Option<Handle<AudioSource>> {
+    if asset_path(path).exists() {
+        Some(ass.load(path))
+    } else {
+        warn!("No assets/{path} found, {silenced} stays silent until one is added");
+        None
+    }
+}
+
+pub fn load_sounds(mut commands: Commands, ass: Res<AssetServer>) {
+    commands.insert_resource(SoundAssets {
+        pop: load_sound(&ass, POP_SOUND, "popping"),
+        hurt: load_sound(&ass, HURT_SOUND, "getting hurt"),
+        big_moment: load_sound(&ass, BIG_MOMENT_SOUND, "a lethal hit or the cataclysm"),
+    });
+}
+
+// Resource containing how much longer the music and other sounds stay lowered for a big moment
+#[derive(Default)]
+pub struct Ducking(f32);
+
+impl Ducking {
+    /// Share of their usual volume the music and other sounds play at right now
+    pub fn volume(&self) -> f32 {
+        let lowered = (self.0 / DUCK_RELEASE_SECONDS).min(1.0);
+        1.0 - (1.0 - DUCKED_VOLUME) * lowered
+    }
+}
+
+// A lethal hit or the cataclysm plays its own sound, with the music and other sounds lowered
+// around it so it stands out
+pub fn duck_for_big_moments(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    sounds: Res<SoundAssets>,
+    audio: Res<Audio>,
+    mut ducking: ResMut<Ducking>,
+    mut hurt: EventReader<SideHurt>,
+    mut skills: EventReader<Skill>,
+    health: Query<&Health>,
+) {
+    ducking.0 = (ducking.0 - time.delta_seconds()).max(0.0);
+    let lethal = hurt.iter().any(|hurt| {
+        health
+            .get(hurt.side)
+            .is_ok_and(|health| health.current == 0)
+    });
+    let ultimate = skills.iter().any(|skill| skill.typ == SkillType::Cataclysm);
+    if !lethal && !ultimate {
+        return;
+    }
+    ducking.0 = DUCK_SECONDS;
+    if let Some(big_moment) = &sounds.big_moment {
+        audio.play_with_settings(
+            big_moment.clone(),
+            PlaybackSettings::ONCE.with_volume(settings.sfx_level()),
+        );
+    }
+}
+
+pub fn play_pop_sound(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    sounds: Res<SoundAssets>,
+    audio: Res<Audio>,
+    ducking: Res<Ducking>,
+    mut popped: EventReader<GemsPopped>,
+    mut last_played: Local<Option<f64>>,
+) {
+    // A single sound per frame, however many batches popped in it
+    let cascade_depth = match popped.iter().map(|popped| popped.cascade_depth).max() {
+        Some(cascade_depth) => cascade_depth,
+        None => return,
+    };
+    let now = time.seconds_since_startup();
+    if settings.muted || last_played.is_some_and(|last| now - last < MIN_POP_INTERVAL) {
+        return;
+    }
+    if let Some(pop) = &sounds.pop {
+        let pitch =
+            (1.0 + POP_PITCH_STEP * cascade_depth.saturating_sub(1) as f32).min(MAX_POP_PITCH);
+        audio.play_with_settings(
+            pop.clone(),
+            PlaybackSettings::ONCE
+                .with_volume(settings.sfx_level() * ducking.volume())
+                .with_speed(pitch),
+        );
+        *last_played = Some(now);
+    }
+}
+
+// A yelp from opponents losing health, once per frame however many got hurt
+pub fn play_hurt_sound(
+    settings: Res<Settings>,
+    sounds: Res<SoundAssets>,
+    audio: Res<Audio>,
+    ducking: Res<Ducking>,
+    mut hurt: EventReader<SideHurt>,
+    player: Query<(), With<Player>>,
+) {
+    let opponent_hurt = hurt.iter().any(|hurt| player.get(hurt.side).is_err());
+    if !opponent_hurt || settings.muted {
+        return;
+    }
+    if let Some(hurt) = &sounds.hurt {
+        audio.play_with_settings(
+            hurt.clone(),
+            PlaybackSettings::ONCE.with_volume(settings.sfx_level() * ducking.volume()),
+        );
+    }
+}
@@ -0,0 +1,91 @@
+use bevy::prelude::*;
+use bevy_egui::{
+    egui::{self, Color32, FontId, RichText},
+    EguiContext,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{outcome, persistence, BoardSettled, GameMode, GemsPopped, Health, Outcome, Player};
+
+const STATS_FILE: &str = "stats.ron";
+
+// Resource containing the player's record across every match played
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Stats {
+    pub wins: u32,
+    pub losses: u32,
+    /// The most matches deep a single cascade has gone
+    pub best_cascade: u32,
+}
+
+impl Stats {
+    pub fn load() -> Self {
+        persistence::load(STATS_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        persistence::save(STATS_FILE, self);
+    }
+}
+
+// Resource containing the deepest cascade of the match being played
+#[derive(Default, Deref, DerefMut)]
+pub struct MatchCascade(u32);
+
+// Resource containing how many cascade waves deep the move being resolved has gone, back to 0
+// once the board settles
+#[derive(Default, Deref, DerefMut)]
+pub struct Chain(u32);
+
+pub fn reset_match_cascade(mut cascade: ResMut<MatchCascade>, mut chain: ResMut<Chain>) {
+    **cascade = 0;
+    **chain = 0;
+}
+
+pub fn track_cascades(
+    mut popped: EventReader<GemsPopped>,
+    mut settled: EventReader<BoardSettled>,
+    mut cascade: ResMut<MatchCascade>,
+    mut chain: ResMut<Chain>,
+) {
+    if settled.iter().count() > 0 {
+        **chain = 0;
+    }
+    for popped in popped.iter() {
+        **cascade = (**cascade).max(popped.cascade_depth);
+        **chain = (**chain).max(popped.cascade_depth);
+    }
+}
+
+// Calls out the chain as it climbs, from the first cascade the initial match sets off
+pub fn chain_ui(chain: Res<Chain>, mut egui_ctx: ResMut<EguiContext>) {
+    if **chain < 2 {
+        return;
+    }
+    egui::Area::new("Chain")
+        .anchor(egui::Align2::CENTER_TOP, egui::vec2(0.0, 90.0))
+        .show(egui_ctx.ctx_mut(), |ui| {
+            ui.label(
+                RichText::new(format!("Chain x{}!", **chain))
+                    .font(FontId::monospace(40.0))
+                    .color(Color32::GOLD),
+            );
+        });
+}
+
+pub fn record_result(
+    mut stats: ResMut<Stats>,
+    mode: Res<GameMode>,
+    cascade: Res<MatchCascade>,
+    player: Query<&Health, With<Player>>,
+    opponents: Query<&Health, Without<Player>>,
+) {
+    match outcome(*mode, player.single(), opponents.iter()) {
+        Outcome::Win => stats.wins += 1,
+        Outcome::Loss => stats.losses += 1,
+        Outcome::Draw => {}
+    }
+    stats.best_cascade = stats.best_cascade.max(**cascade);
+    stats.save();
+}
@@ -0,0 +1,174 @@
+use bevy::prelude::*;
+use bevy_egui::egui::{self, Color32, RichText};
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+use crate::{encounter::Foes, GameMode, GemsPopped, Health, MoveResolved, Turn};
+
+/// How much a turn lost to a stun is worth to the opponent AI, next to a point of damage
+const STUN_VALUE: u32 = 10;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Display, Serialize, Deserialize)]
+pub enum StatusKind {
+    /// Loses health every turn
+    Poison,
+    /// Loses health on every match
+    Burn,
+    /// Skips its turns
+    Stun,
+    /// Has its defense raised
+    Guard,
+}
+
+impl StatusKind {
+    fn icon(self) -> (&'static str, Color32) {
+        match self {
+            StatusKind::Poison => ("☠", Color32::from_rgb(120, 220, 80)),
+            StatusKind::Burn => ("🔥", Color32::from_rgb(255, 140, 0)),
+            StatusKind::Stun => ("💫", Color32::YELLOW),
+            StatusKind::Guard => ("🛡", Color32::LIGHT_BLUE),
+        }
+    }
+}
+
+/// A lasting effect on one side, how strong and how long it is comes from the skill applying it
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    /// Health lost each time poison or burn bites or defense added by guard, unused by stun
+    pub magnitude: u32,
+    /// Turns left before it wears off
+    pub turns: u32,
+}
+
+impl StatusEffect {
+    pub fn description(self) -> String {
+        let turns = self.turns;
+        match self.kind {
+            StatusKind::Poison => format!(
+                "Poisons the other side for {} damage a turn over {turns} turns",
+                self.magnitude
+            ),
+            StatusKind::Burn => format!(
+                "Burns the other side for {} damage on each of its matches over {turns} turns",
+                self.magnitude
+            ),
+            StatusKind::Stun => format!("Stuns the other side, skipping its next {turns} turns"),
+            StatusKind::Guard => format!(
+                "Raises your defense by {} for {turns} turns",
+                self.magnitude
+            ),
+        }
+    }
+
+    /// Roughly how much damage the effect is worth over its whole duration
+    pub fn value(self) -> u32 {
+        match self.kind {
+            StatusKind::Poison | StatusKind::Burn | StatusKind::Guard => {
+                self.magnitude * self.turns
+            }
+            StatusKind::Stun => STUN_VALUE * self.turns,
+        }
+    }
+}
+
+/// Every status effect currently on a side
+#[derive(Component, Clone, Default, Deref, DerefMut)]
+pub struct StatusEffects(pub Vec<StatusEffect>);
+
+impl StatusEffects {
+    /// Adds `effect`, an effect of the same kind already there is refreshed to the stronger and
+    /// longer of the two instead of stacking
+    pub fn apply(&mut self, effect: StatusEffect) {
+        match self.iter_mut().find(|active| active.kind == effect.kind) {
+            Some(active) => {
+                active.magnitude = active.magnitude.max(effect.magnitude);
+                active.turns = active.turns.max(effect.turns);
+            }
+            None => self.push(effect),
+        }
+    }
+
+    fn has(&self, kind: StatusKind) -> bool {
+        self.iter().any(|effect| effect.kind == kind)
+    }
+
+    pub fn magnitude(&self, kind: StatusKind) -> u32 {
+        self.iter()
+            .filter(|effect| effect.kind == kind)
+            .map(|effect| effect.magnitude)
+            .sum()
+    }
+
+    /// Ends a turn for the side, letting poison bite and dropping the effects that have run out
+    fn end_turn(&mut self, health: &mut Health) {
+        health.damage(self.magnitude(StatusKind::Poison));
+        for effect in self.iter_mut() {
+            effect.turns = effect.turns.saturating_sub(1);
+        }
+        self.retain(|effect| effect.turns > 0);
+    }
+}
+
+impl egui::Widget for &StatusEffects {
+    fn ui(self, ui: &mut egui::Ui) -> egui::Response {
+        ui.horizontal(|ui| {
+            for effect in self.iter() {
+                let (icon, color) = effect.kind.icon();
+                ui.label(RichText::new(format!("{icon}{}", effect.turns)).color(color))
+                    .on_hover_text(format!("{}, {} turns left", effect.kind, effect.turns));
+            }
+        })
+        .response
+    }
+}
+
+// Ticks a side's effects once its move has played out
+pub fn tick_status_effects(
+    mut resolved: EventReader<MoveResolved>,
+    mut sides: Query<(&mut StatusEffects, &mut Health)>,
+) {
+    for MoveResolved(side) in resolved.iter() {
+        if let Ok((mut effects, mut health)) = sides.get_mut(*side) {
+            effects.end_turn(&mut health);
+        }
+    }
+}
+
+// Burning sides take damage for every match they make
+pub fn burn(mut popped: EventReader<GemsPopped>, mut sides: Query<(&StatusEffects, &mut Health)>) {
+    for popped in popped.iter() {
+        if let Ok((effects, mut health)) = sides.get_mut(popped.side) {
+            health.damage(effects.magnitude(StatusKind::Burn));
+        }
+    }
+}
+
+// A stunned side loses its turn as soon as it gets it, which counts as a turn for its effects
+pub fn skip_stunned_turns(
+    mode: Res<GameMode>,
+    mut turn: ResMut<Turn>,
+    foes: Foes,
+    mut sides: Query<(&mut StatusEffects, &mut Health)>,
+) {
+    if *mode != GameMode::Classic || !turn.is_changed() {
+        return;
+    }
+    // Everyone could be stunned at once, go round at most once
+    for _ in 0..sides.iter().count() {
+        match sides.get_mut(**turn) {
+            Ok((mut effects, mut health)) if effects.has(StatusKind::Stun) => {
+                info!("{:?} is stunned and loses its turn", **turn);
+                effects.end_turn(&mut health);
+            }
+            _ => return,
+        }
+        let next = foes.next_turn(**turn, |side| {
+            sides.get(side).is_ok_and(|(_, health)| health.current > 0)
+        });
+        match next {
+            Some(next) if next != **turn => **turn = next,
+            _ => return,
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use std::env;
+
+use bevy::{app::AppExit, gltf::Gltf, prelude::*};
+use bevy_match3::prelude::*;
+
+use crate::{assets::GemAssets, BoardSettled, GameMode, GameState, TurnState};
+
+const FLAG: &str = "--stress-test";
+const DEFAULT_SECONDS: f32 = 30.0;
+const REPORT_SECONDS: f32 = 5.0;
+
+/// Runs the game unattended, popping the whole board every time it settles and logging frame times.
+/// Launched with `--stress-test` or `--stress-test=SECONDS`, exits once the time is up
+pub struct StressTestPlugin {
+    seconds: f32,
+}
+
+impl StressTestPlugin {
+    /// The plugin if the stress test was asked for on the command line
+    pub fn from_args() -> Option<Self> {
+        env::args().find_map(|arg| {
+            let seconds = arg.strip_prefix(FLAG)?;
+            let seconds = match seconds.strip_prefix('=') {
+                Some(seconds) => seconds.parse().unwrap_or(DEFAULT_SECONDS),
+                None if seconds.is_empty() => DEFAULT_SECONDS,
+                None => return None,
+            };
+            Some(Self { seconds })
+        })
+    }
+}
+
+impl Plugin for StressTestPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(StressTest {
+            duration: Timer::from_seconds(self.seconds, false),
+            report: Timer::from_seconds(REPORT_SECONDS, true),
+            ..default()
+        })
+        .add_system_set(SystemSet::on_update(GameState::MainMenu).with_system(start_when_loaded))
+        .add_system_set(
+            SystemSet::on_update(GameState::Game)
+                .with_system(pop_board_when_settled)
+                .with_system(record_frame_times),
+        );
+    }
+}
+
+#[derive(Default)]
+pub struct StressTest {
+    duration: Timer,
+    report: Timer,
+    /// Frame times in seconds since the last report
+    window: Vec<f32>,
+    /// Frame times in seconds since the start
+    total: Vec<f32>,
+    cascades: u32,
+}
+
+struct FrameStats {
+    frames: usize,
+    average: f32,
+    p99: f32,
+    worst: f32,
+}
+
+impl FrameStats {
+    fn of(frame_times: &[f32]) -> Option<Self> {
+        if frame_times.is_empty() {
+            return None;
+        }
+        let mut sorted = frame_times.to_vec();
+        sorted.sort_by(f32::total_cmp);
+        Some(Self {
+            frames: sorted.len(),
+            average: sorted.iter().sum::<f32>() / sorted.len() as f32,
+            p99: sorted[(sorted.len() - 1) * 99 / 100],
+            worst: sorted[sorted.len() - 1],
+        })
+    }
+
+    fn log(&self, label: &str, cascades: u32) {
+        info!(
+            "{label}: {} frames, {cascades} cascades, avg {:.2}ms, p99 {:.2}ms, worst {:.2}ms",
+            self.frames,
+            self.average * 1000.0,
+            self.p99 * 1000.0,
+            self.worst * 1000.0,
+        );
+    }
+}
+
+// Skips the menu, but only once every gem mesh can actually be spawned
+fn start_when_loaded(
+    assets: Option<Res<GemAssets>>,
+    gltf_assets: Res<Assets<Gltf>>,
+    mut mode: ResMut<GameMode>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let loaded = assets.map_or(false, |assets| {
+        assets
+            .meshes
+            .values()
+            .chain(assets.shatter_meshes.values())
+            .all(|mesh| gltf_assets.contains(mesh))
+    });
+    if loaded {
+        // Practice keeps the turn with the player so nothing else waits on a move
+        *mode = GameMode::Practice;
+        state.set(GameState::Game).unwrap();
+    }
+}
+
+fn pop_board_when_settled(
+    mut settled: EventReader<BoardSettled>,
+    mut stress_test: ResMut<StressTest>,
+    mut turn_state: ResMut<State<TurnState>>,
+    board: Res<Board>,
+    mut board_commands: ResMut<BoardCommands>,
+) {
+    if settled.iter().count() == 0 {
+        return;
+    }
+    board_commands
+        .push(BoardCommand::Pop(
+            board.iter().map(|(pos, _)| *pos).collect(),
+        ))
+        .unwrap();
+    turn_state.set(TurnState::Resolving).unwrap();
+    stress_test.cascades += 1;
+}
+
+fn record_frame_times(
+    time: Res<Time>,
+    mut stress_test: ResMut<StressTest>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let delta = time.delta();
+    stress_test.window.push(delta.as_secs_f32());
+    stress_test.total.push(delta.as_secs_f32());
+
+    if stress_test.report.tick(delta).just_finished() {
+        if let Some(stats) = FrameStats::of(&stress_test.window) {
+            stats.log("Stress test", stress_test.cascades);
+        }
+        stress_test.window.clear();
+    }
+
+    if stress_test.duration.tick(delta).just_finished() {
+        if let Some(stats) = FrameStats::of(&stress_test.total) {
+            stats.log("Stress test finished", stress_test.cascades);
+        }
+        exit.send(AppExit);
+    }
+}
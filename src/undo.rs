@@ -0,0 +1,148 @@
+use bevy::prelude::*;
+use bevy_match3::prelude::*;
+
+use crate::{
+    encounter::Lineup,
+    experience::Experience,
+    save::{MatchSnapshot, SavedGame, SideComponents},
+    score::Score,
+    spawn_slots,
+    status::StatusEffects,
+    BoardSettled, BoardTweens, Collected, GemSlot, GemSpawner, GemType, Health, MoveResolved,
+    MovesLeft, Player, Resources, Skill, Turn, TurnState,
+};
+
+// Resource containing what's needed to take back the player's last move
+#[derive(Default)]
+pub struct Undo {
+    /// The match as it was when the board last came to rest
+    settled: Option<SavedGame>,
+    /// The match as it was before the player's last move, for as long as it can be taken back
+    last_move: Option<SavedGame>,
+    requested: bool,
+}
+
+impl Undo {
+    pub fn available(&self) -> bool {
+        self.last_move.is_some()
+    }
+
+    pub fn request(&mut self) {
+        self.requested = true;
+    }
+}
+
+pub fn reset_undo(mut undo: ResMut<Undo>) {
+    *undo = Undo::default();
+}
+
+// Keeps the match as it was before the player's last move until anything else happens, the
+// opponent getting the turn or a skill being cast make it impossible to take back
+pub fn record_undo(
+    mut undo: ResMut<Undo>,
+    mut resolved: EventReader<MoveResolved>,
+    mut skills: EventReader<Skill>,
+    mut settled: EventReader<BoardSettled>,
+    turn: Res<Turn>,
+    player: Query<(), With<Player>>,
+    snapshot: MatchSnapshot,
+) {
+    for MoveResolved(side) in resolved.iter() {
+        undo.last_move = if player.get(*side).is_ok() {
+            undo.settled.take()
+        } else {
+            None
+        };
+    }
+    if skills.iter().count() > 0 || (turn.is_changed() && player.get(**turn).is_err()) {
+        undo.last_move = None;
+    }
+    if settled.iter().count() > 0 {
+        undo.settled = snapshot.capture();
+    }
+}
+
+// Puts the board and every side back the way they were before the player's last move, once per move
+pub fn undo_move(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    mut undo: ResMut<Undo>,
+    turn_state: Res<State<TurnState>>,
+    tweens: Res<BoardTweens>,
+    mut board: ResMut<Board>,
+    mut spawner: GemSpawner,
+    board_entities: Query<Entity, Or<(With<GemSlot>, With<GemType>)>>,
+    mut moves_left: ResMut<MovesLeft>,
+    lineup: Res<Lineup>,
+    mut experience: Query<&mut Experience, With<Player>>,
+    mut player: Query<
+        (
+            &mut Resources,
+            &mut Health,
+            &mut StatusEffects,
+            &mut Score,
+            &mut Collected,
+        ),
+        With<Player>,
+    >,
+    mut opponent: Query<
+        (
+            &mut Resources,
+            &mut Health,
+            &mut StatusEffects,
+            &mut Score,
+            &mut Collected,
+        ),
+        Without<Player>,
+    >,
+) {
+    let ctrl = keys.any_pressed([KeyCode::LControl, KeyCode::RControl]);
+    let requested = std::mem::take(&mut undo.requested) || (ctrl && keys.just_pressed(KeyCode::Z));
+    if !requested || turn_state.current() != &TurnState::AwaitingMove || !tweens.settled() {
+        return;
+    }
+    let before = match undo.last_move.take() {
+        Some(before) => before,
+        None => return,
+    };
+    info!("Taking back the last move");
+
+    for entity in board_entities.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    *board = before.board();
+    spawn_slots(
+        &mut commands,
+        &mut spawner,
+        &board,
+        &before.locked_slots(),
+        Some(&before.locked_gems()),
+    );
+
+    let (player_side, opponent_sides) = before.sides();
+    restore(player.single_mut(), player_side);
+    for (entity, side) in lineup.iter().zip(opponent_sides) {
+        if let Ok(opponent) = opponent.get_mut(*entity) {
+            restore(opponent, side);
+        }
+    }
+    **moves_left = before.moves_left();
+    *experience.single_mut() = before.experience();
+}
+
+fn restore(
+    (mut resources, mut health, mut status, mut score, mut collected): (
+        Mut<Resources>,
+        Mut<Health>,
+        Mut<StatusEffects>,
+        Mut<Score>,
+        Mut<Collected>,
+    ),
+    (saved_resources, saved_health, saved_status, saved_score, saved_collected): SideComponents,
+) {
+    *resources = saved_resources;
+    *health = saved_health;
+    *status = saved_status;
+    *score = saved_score;
+    *collected = saved_collected;
+}